@@ -0,0 +1,133 @@
+//! `--anonymize`: swaps the identifiers in a diff for placeholders, so the
+//! model sees the shape of a change without the real names in it.
+
+use std::collections::HashMap;
+
+/// Keywords and primitive types of the common languages, kept as they are
+/// since they carry the structure rather than anything project-specific.
+const KEYWORDS: &str = "\
+    abstract and as async await bool break byte case catch char class const continue crate def \
+    default defer del do double dyn elif else enum except export extends extern f32 f64 false \
+    final finally float fn for from func function go i128 i16 i32 i64 i8 if impl implements \
+    import in int interface is isize lambda let long loop map match mod move mut new nil None \
+    not null or package pass private protected pub public raise range ref return self Self short \
+    static str string struct super switch this throw throws trait true True False try type \
+    typeof u128 u16 u32 u64 u8 undefined unsafe use usize var void where while with yield";
+
+/// `diff` with every identifier outside `KEYWORDS` replaced by `ident_N`, the
+/// same identifier always getting the same `N`. File headers are kept so the
+/// diff still applies to the listed paths; hunk headers keep their line
+/// ranges but lose the enclosing function name.
+pub fn anonymize_diff(diff: &str) -> String {
+    let mut names = HashMap::new();
+    diff.split_inclusive('\n')
+        .map(|line| {
+            if is_file_header(line) {
+                return line.to_string();
+            }
+            if let Some(rest) = line.strip_prefix("@@") {
+                // `@@ -1,3 +1,4 @@ fn name`: only the part after the ranges is code
+                return match rest.find("@@") {
+                    Some(end) => {
+                        let (ranges, code) = rest.split_at(end + 2);
+                        format!("@@{}{}", ranges, anonymize_code(code, &mut names))
+                    }
+                    None => line.to_string(),
+                };
+            }
+            anonymize_code(line, &mut names)
+        })
+        .collect()
+}
+
+/// Git's per-file lines that name paths and modes rather than code.
+fn is_file_header(line: &str) -> bool {
+    [
+        "diff --git ",
+        "index ",
+        "--- ",
+        "+++ ",
+        "new file mode ",
+        "deleted file mode ",
+        "old mode ",
+        "new mode ",
+        "similarity index ",
+        "rename from ",
+        "rename to ",
+        "Binary files ",
+    ]
+    .iter()
+    .any(|prefix| line.starts_with(prefix))
+}
+
+fn anonymize_code(code: &str, names: &mut HashMap<String, usize>) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut chars = code.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !(c.is_alphanumeric() || c == '_') {
+            out.push(c);
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, next)) = chars.peek() {
+            if !(next.is_alphanumeric() || next == '_') {
+                break;
+            }
+            end = i + next.len_utf8();
+            chars.next();
+        }
+        let word = &code[start..end];
+        // Numbers such as `42` or `0x1f` aren't names
+        if c.is_ascii_digit() || KEYWORDS.split_whitespace().any(|keyword| keyword == word) {
+            out.push_str(word);
+        } else {
+            let next = names.len() + 1;
+            let n = *names.entry(word.to_string()).or_insert(next);
+            out.push_str(&format!("ident_{}", n));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymize_diff_maps_each_identifier_to_one_placeholder() {
+        let diff = "-let total = price * count;\n+let total = price * count + tax;\n";
+        assert_eq!(
+            anonymize_diff(diff),
+            "-let ident_1 = ident_2 * ident_3;\n+let ident_1 = ident_2 * ident_3 + ident_4;\n"
+        );
+    }
+
+    #[test]
+    fn anonymize_diff_keeps_keywords_numbers_and_punctuation() {
+        assert_eq!(
+            anonymize_diff("+    if retries > 0x1f { return None; }\n"),
+            "+    if ident_1 > 0x1f { return None; }\n"
+        );
+    }
+
+    #[test]
+    fn anonymize_diff_keeps_file_headers_and_hunk_ranges() {
+        let diff = "diff --git a/src/billing.rs b/src/billing.rs\n\
+                    index 83db48f..bf269f4 100644\n\
+                    --- a/src/billing.rs\n\
+                    +++ b/src/billing.rs\n\
+                    @@ -10,2 +10,2 @@ fn charge_card(card: Card) {\n\
+                    -    card.charge()\n\
+                    +    card.charge_once()\n";
+        assert_eq!(
+            anonymize_diff(diff),
+            "diff --git a/src/billing.rs b/src/billing.rs\n\
+             index 83db48f..bf269f4 100644\n\
+             --- a/src/billing.rs\n\
+             +++ b/src/billing.rs\n\
+             @@ -10,2 +10,2 @@ fn ident_1(ident_2: ident_3) {\n\
+             -    ident_2.ident_4()\n\
+             +    ident_2.ident_5()\n"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// 64-bit FNV-1a, used instead of `std`'s hasher because its output must stay
+/// stable across runs and Rust versions for cache keys to be reusable.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Key under which a generated message for `context` and `model` is cached.
+pub fn cache_key(context: &str, model: &str) -> String {
+    let mut input = Vec::with_capacity(model.len() + context.len() + 1);
+    input.extend_from_slice(model.as_bytes());
+    input.push(0);
+    input.extend_from_slice(context.as_bytes());
+    format!("{:016x}", fnv1a(&input))
+}
+
+/// `$XDG_CACHE_HOME/auto-commit`, falling back to `~/.cache/auto-commit`.
+pub fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("auto-commit"))
+}
+
+pub fn load(dir: &Path, key: &str) -> Option<String> {
+    fs::read_to_string(dir.join(format!("{}.json", key))).ok()
+}
+
+pub fn store(dir: &Path, key: &str, commit_json: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(format!("{}.json", key)), commit_json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_key_is_stable() {
+        assert_eq!(
+            cache_key("diff", "gpt-4.1-nano"),
+            cache_key("diff", "gpt-4.1-nano")
+        );
+        assert_eq!(cache_key("", ""), "af63bd4c8601b7df");
+    }
+
+    #[test]
+    fn cache_key_depends_on_context_and_model() {
+        let key = cache_key("diff", "gpt-4.1-nano");
+        assert_ne!(key, cache_key("other diff", "gpt-4.1-nano"));
+        assert_ne!(key, cache_key("diff", "gpt-4o"));
+        assert_ne!(cache_key("ab", "c"), cache_key("b", "ca"));
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn store_then_load_round_trips() {
+        let dir = std::env::temp_dir().join(format!("auto-commit-cache-{}", std::process::id()));
+        let key = cache_key("diff", "model");
+
+        assert_eq!(load(&dir, &key), None);
+        store(&dir, &key, r#"{"title":"Fix bug","description":"Details"}"#).unwrap();
+        assert_eq!(
+            load(&dir, &key).as_deref(),
+            Some(r#"{"title":"Fix bug","description":"Details"}"#)
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! `--changelog`: asks the model for a user-facing changelog line in the
+//! Keep a Changelog categories instead of a commit message.
+
+use crate::{error::AppError, message::strip_code_fence};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The section of a Keep a Changelog release an entry goes under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ChangelogCategory {
+    Added,
+    Changed,
+    Deprecated,
+    Removed,
+    Fixed,
+    Security,
+}
+
+impl fmt::Display for ChangelogCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// One changelog line and the section it belongs in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ChangelogEntry {
+    pub category: ChangelogCategory,
+    #[schemars(length(min = 1))]
+    pub entry: String,
+}
+
+/// System prompt for `--changelog`, with the `ChangelogEntry` schema the reply must follow.
+pub fn build_changelog_prompt() -> String {
+    let mut gen =
+        SchemaGenerator::new(SchemaSettings::openapi3().with(|s| s.inline_subschemas = true));
+    let schema = serde_json::to_value(gen.subschema_for::<ChangelogEntry>().into_object())
+        .expect("Failed to serialize the changelog schema");
+    format!(
+        "You are an experienced developer writing the project's changelog. Describe the change \
+         in one sentence for the people who use the software, not the people who work on it: \
+         what they can now do or what stopped going wrong, without file or function names. \
+         Pick the Keep a Changelog category it belongs under. Reply with only a JSON object \
+         matching this schema:\n{}",
+        schema
+    )
+}
+
+/// Parses the model's entry, which may be wrapped in a code fence, rejecting
+/// categories outside Keep a Changelog's and empty entries.
+pub fn parse_changelog(reply: &str) -> Result<ChangelogEntry, AppError> {
+    let mut entry: ChangelogEntry = serde_json::from_str(strip_code_fence(reply))
+        .map_err(|e| AppError::Api(format!("The model's changelog entry isn't valid: {}", e)))?;
+    // Keep it to the one line it's pasted as
+    entry.entry = entry.entry.split_whitespace().collect::<Vec<_>>().join(" ");
+    if entry.entry.is_empty() {
+        return Err(AppError::Api(
+            "The model's changelog entry is empty.".to_string(),
+        ));
+    }
+    Ok(entry)
+}
+
+/// `entry` as it's pasted into `CHANGELOG.md`, under its section heading.
+pub fn format_changelog(entry: &ChangelogEntry) -> String {
+    format!("### {}\n- {}", entry.category, entry.entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_changelog_reads_a_fenced_entry() {
+        let reply =
+            "```json\n{\"category\": \"Fixed\", \"entry\": \"Commits no longer hang.\"}\n```";
+        assert_eq!(
+            parse_changelog(reply).unwrap(),
+            ChangelogEntry {
+                category: ChangelogCategory::Fixed,
+                entry: "Commits no longer hang.".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_changelog_rejects_unknown_categories() {
+        let err = parse_changelog(r#"{"category": "Improved", "entry": "Faster."}"#).unwrap_err();
+        assert!(err.to_string().contains("isn't valid"), "{}", err);
+    }
+
+    #[test]
+    fn parse_changelog_keeps_the_entry_to_one_line() {
+        let entry = parse_changelog(r#"{"category": "Added", "entry": "A --changelog\nflag."}"#);
+        assert_eq!(entry.unwrap().entry, "A --changelog flag.");
+        assert!(parse_changelog(r#"{"category": "Added", "entry": "  "}"#).is_err());
+    }
+
+    #[test]
+    fn format_changelog_puts_the_entry_under_its_heading() {
+        let entry = ChangelogEntry {
+            category: ChangelogCategory::Security,
+            entry: "Secrets are redacted from diffs.".to_string(),
+        };
+        assert_eq!(
+            format_changelog(&entry),
+            "### Security\n- Secrets are redacted from diffs."
+        );
+    }
+
+    #[test]
+    fn build_changelog_prompt_lists_the_categories() {
+        let prompt = build_changelog_prompt();
+        for category in [
+            "Added",
+            "Changed",
+            "Deprecated",
+            "Removed",
+            "Fixed",
+            "Security",
+        ] {
+            assert!(prompt.contains(category), "{}", category);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! `auto-commit check`: asks the model whether a hand-written commit message
+//! describes the diff.
+
+use crate::{error::AppError, message::strip_code_fence};
+use serde::Deserialize;
+
+pub const CHECK_SYSTEM_PROMPT: &str = "You are an experienced developer reviewing commit \
+     messages. Decide whether the commit message accurately describes the diff: it may leave \
+     out minor details, but it must not describe changes the diff doesn't make or miss its main \
+     change. Reply with only a JSON object of the form \
+     {\"matches\": true or false, \"reason\": \"one or two sentences explaining why\"}.";
+
+/// The model's answer to a check.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Verdict {
+    pub matches: bool,
+    pub reason: String,
+}
+
+/// The user message for a check: the message under review, then the diff context.
+pub fn build_check_prompt(message: &str, context: &str) -> String {
+    format!("Commit message:\n{}\n\n{}", message.trim(), context)
+}
+
+/// Parses the model's reply, which may be wrapped in a code fence.
+pub fn parse_verdict(reply: &str) -> Result<Verdict, AppError> {
+    serde_json::from_str(strip_code_fence(reply))
+        .map_err(|e| AppError::Api(format!("The model's verdict isn't valid: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_verdict_reads_a_match() {
+        assert_eq!(
+            parse_verdict(r#"{"matches": true, "reason": "It adds the login page."}"#),
+            Ok(Verdict {
+                matches: true,
+                reason: "It adds the login page.".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_verdict_reads_a_fenced_mismatch() {
+        let reply = "```json\n{\"matches\": false, \"reason\": \"The diff removes logging.\"}\n```";
+        let verdict = parse_verdict(reply).unwrap();
+        assert!(!verdict.matches);
+        assert_eq!(verdict.reason, "The diff removes logging.");
+    }
+
+    #[test]
+    fn parse_verdict_rejects_other_replies() {
+        for reply in [
+            "Yes, it matches.",
+            r#"{"matches": "yes", "reason": "..."}"#,
+            r#"{"reason": "no verdict"}"#,
+        ] {
+            assert!(
+                matches!(parse_verdict(reply), Err(AppError::Api(_))),
+                "{}",
+                reply
+            );
+        }
+    }
+
+    #[test]
+    fn build_check_prompt_puts_the_message_first() {
+        assert_eq!(
+            build_check_prompt("Fix bug\n", "Changed files:\na.rs"),
+            "Commit message:\nFix bug\n\nChanged files:\na.rs"
+        );
+    }
+}
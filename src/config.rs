@@ -0,0 +1,635 @@
+use crate::context::{clamp_response_tokens, default_response_tokens, RESPONSE_TOKENS_RANGE};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings written by `auto-commit init`. Only the values the user gave are
+/// written, so the rest keep following the provider's and model's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub language: Option<String>,
+}
+
+/// Generation settings given on the command line; unset fields fall through to
+/// the environment, git config and the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingOverrides {
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub language: Option<String>,
+}
+
+/// Generation settings after layering every source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub model: String,
+    pub max_tokens: u32,
+    /// Language to write the message in; `None` leaves it to the model.
+    pub language: Option<String>,
+}
+
+/// Merges settings from `cli`, then `env` (`AUTO_COMMIT_MODEL`,
+/// `AUTO_COMMIT_MAX_TOKENS`, `AUTO_COMMIT_LANGUAGE`), then `git_config`
+/// (`autocommit.model`, `autocommit.maxtokens`, `autocommit.language`), then
+/// the defaults, with the response token limit defaulting per model. Values
+/// that aren't valid numbers are skipped with a warning, and token limits
+/// outside `RESPONSE_TOKENS_RANGE` are clamped into it.
+pub fn resolve_settings(
+    cli: &SettingOverrides,
+    default_model: &str,
+    env: impl Fn(&str) -> Option<String>,
+    git_config: impl Fn(&str) -> Option<String>,
+) -> Settings {
+    let lookup = |env_var: &str, git_key: &str| {
+        env(env_var)
+            .filter(|value| !value.is_empty())
+            .map(|value| (env_var.to_string(), value))
+            .into_iter()
+            .chain(git_config(git_key).map(|value| (format!("git config {}", git_key), value)))
+    };
+    let max_tokens = cli.max_tokens.or_else(|| {
+        lookup("AUTO_COMMIT_MAX_TOKENS", "autocommit.maxtokens").find_map(|(source, value)| {
+            let parsed = value.trim().parse().ok();
+            if parsed.is_none() {
+                warn!("Ignoring {}: `{}` isn't a number of tokens.", source, value);
+            }
+            parsed
+        })
+    });
+
+    let max_tokens = max_tokens.map(|tokens| {
+        let clamped = clamp_response_tokens(tokens);
+        if clamped != tokens {
+            warn!(
+                "A response token limit of {} is outside {:?}; using {}.",
+                tokens, RESPONSE_TOKENS_RANGE, clamped
+            );
+        }
+        clamped
+    });
+    let model = cli
+        .model
+        .clone()
+        .or_else(|| {
+            lookup("AUTO_COMMIT_MODEL", "autocommit.model")
+                .next()
+                .map(|(_, model)| model)
+        })
+        .unwrap_or_else(|| default_model.to_string());
+
+    Settings {
+        max_tokens: max_tokens.unwrap_or_else(|| default_response_tokens(&model)),
+        model,
+        language: cli.language.clone().or_else(|| {
+            lookup("AUTO_COMMIT_LANGUAGE", "autocommit.language")
+                .next()
+                .map(|(_, language)| language)
+        }),
+    }
+}
+
+/// The model `--map-reduce` summarizes files with: `AUTO_COMMIT_SUMMARY_MODEL`
+/// from `env`, then `autocommit.summarymodel` from `git_config`, then
+/// `commit_model`, the resolved model the commit itself is written with.
+pub fn resolve_summary_model(
+    commit_model: &str,
+    env: impl Fn(&str) -> Option<String>,
+    git_config: impl Fn(&str) -> Option<String>,
+) -> String {
+    env("AUTO_COMMIT_SUMMARY_MODEL")
+        .filter(|model| !model.is_empty())
+        .or_else(|| git_config("autocommit.summarymodel"))
+        .unwrap_or_else(|| commit_model.to_string())
+}
+
+/// `$XDG_CONFIG_HOME/auto-commit`, falling back to `%APPDATA%\auto-commit` on
+/// Windows and `~/.config/auto-commit` elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                var("APPDATA").map(PathBuf::from)
+            } else {
+                None
+            }
+        })
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("auto-commit"))
+}
+
+/// The project file of `KEY=value` lines read at startup.
+pub const DOTENV_FILE: &str = ".env";
+
+/// The variables in the `.env` file at `path`, or none if it can't be read.
+pub fn load_dotenv(path: &Path) -> HashMap<String, String> {
+    fs::read_to_string(path)
+        .map(|text| parse_dotenv(&text))
+        .unwrap_or_default()
+}
+
+/// Parses `KEY=value` lines, skipping blank lines and `#` comments. Values may
+/// be wrapped in single or double quotes; unquoted values end at a ` #` comment.
+pub fn parse_dotenv(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                warn!("Skipping malformed line in {}: {}", DOTENV_FILE, line);
+                return None;
+            }
+            Some((key.to_string(), dotenv_value(value.trim()).to_string()))
+        })
+        .collect()
+}
+
+fn dotenv_value(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value.split(" #").next().unwrap_or("").trim_end()
+}
+
+/// Sets each of `vars` that isn't already in the environment.
+pub fn apply_dotenv(vars: HashMap<String, String>) {
+    for (key, value) in vars {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+}
+
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Renders `config` as a TOML document.
+pub fn to_toml(config: &Config) -> String {
+    let mut toml = String::new();
+    if let Some(model) = &config.model {
+        toml += &format!("model = {}\n", toml_string(model));
+    }
+    if let Some(api_key) = &config.api_key {
+        toml += &format!("api_key = {}\n", toml_string(api_key));
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        toml += &format!("max_tokens = {}\n", max_tokens);
+    }
+    if let Some(language) = &config.language {
+        toml += &format!("language = {}\n", toml_string(language));
+    }
+    toml
+}
+
+/// Parses the `key = value` lines `to_toml` writes, unescaping quoted strings
+/// and skipping blank lines, `#` comments and lines it can't read.
+pub fn parse_config(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = match value.strip_prefix('"') {
+                Some(quoted) => toml_unescape(quoted)?,
+                None => value
+                    .split(" #")
+                    .next()
+                    .unwrap_or("")
+                    .trim_end()
+                    .to_string(),
+            };
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// The contents of a basic TOML string after its opening quote, up to the
+/// closing one, or `None` if it isn't closed.
+fn toml_unescape(quoted: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    value.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+/// The settings in the config file at `path`, or none if it can't be read.
+pub fn load_config(path: &Path) -> HashMap<String, String> {
+    fs::read_to_string(path)
+        .map(|text| parse_config(&text))
+        .unwrap_or_default()
+}
+
+/// The config file entry standing in for the git config key `git_key`, so the
+/// file is read as the layer below git config.
+pub fn config_file_value(file: &HashMap<String, String>, git_key: &str) -> Option<String> {
+    let key = match git_key {
+        "autocommit.model" => "model",
+        "autocommit.maxtokens" => "max_tokens",
+        "autocommit.language" => "language",
+        _ => return None,
+    };
+    file.get(key).filter(|value| !value.is_empty()).cloned()
+}
+
+/// Writes `config` to `dir/config.toml`, creating `dir` if needed and refusing
+/// to replace an existing file unless `force` is set. The file holds an API key,
+/// so it's only readable by its owner.
+pub fn write_config(dir: &Path, config: &Config, force: bool) -> io::Result<PathBuf> {
+    let path = dir.join(CONFIG_FILE);
+    if path.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            ),
+        ));
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(&path, to_toml(config))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    fn config() -> Config {
+        Config {
+            model: Some("gpt-4.1-nano".to_string()),
+            api_key: Some("sk-test".to_string()),
+            max_tokens: Some(2000),
+            language: Some("English".to_string()),
+        }
+    }
+
+    fn temp_config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("auto-commit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("auto-commit")
+    }
+
+    #[test]
+    fn to_toml_writes_every_field() {
+        assert_eq!(
+            to_toml(&config()),
+            "model = \"gpt-4.1-nano\"\napi_key = \"sk-test\"\nmax_tokens = 2000\nlanguage = \"English\"\n"
+        );
+    }
+
+    #[test]
+    fn to_toml_leaves_out_fields_that_werent_given() {
+        assert_eq!(to_toml(&Config::default()), "");
+        let config = Config {
+            language: Some("English".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(to_toml(&config), "language = \"English\"\n");
+        let file = parse_config(&to_toml(&config));
+        assert_eq!(config_file_value(&file, "autocommit.model"), None);
+        assert_eq!(config_file_value(&file, "autocommit.maxtokens"), None);
+    }
+
+    #[test]
+    fn to_toml_escapes_strings() {
+        let config = Config {
+            language: Some("Portuguese \"BR\"\\pt".to_string()),
+            ..config()
+        };
+        assert!(to_toml(&config).ends_with("language = \"Portuguese \\\"BR\\\"\\\\pt\"\n"));
+    }
+
+    #[test]
+    fn parse_config_reads_what_to_toml_writes() {
+        let config = Config {
+            api_key: Some("sk-\"quoted\"\\path".to_string()),
+            language: Some("Português\n".to_string()),
+            ..config()
+        };
+        let file = parse_config(&format!("# written by init\n\n{}", to_toml(&config)));
+        assert_eq!(Some(&file["model"]), config.model.as_ref());
+        assert_eq!(Some(&file["api_key"]), config.api_key.as_ref());
+        assert_eq!(file["max_tokens"], "2000");
+        assert_eq!(Some(&file["language"]), config.language.as_ref());
+        assert!(parse_config("model = \"unterminated\n").is_empty());
+    }
+
+    #[test]
+    fn config_file_value_maps_git_config_keys() {
+        let file = parse_config(&to_toml(&config()));
+        assert_eq!(
+            config_file_value(&file, "autocommit.model").as_deref(),
+            Some("gpt-4.1-nano")
+        );
+        assert_eq!(
+            config_file_value(&file, "autocommit.maxtokens").as_deref(),
+            Some("2000")
+        );
+        assert_eq!(config_file_value(&file, "autocommit.trailer"), None);
+
+        let settings = resolve_settings(
+            &SettingOverrides::default(),
+            "default-model",
+            |_| None,
+            |key| config_file_value(&file, key),
+        );
+        assert_eq!(settings.model, "gpt-4.1-nano");
+        assert_eq!(settings.max_tokens, 2000);
+        assert_eq!(settings.language.as_deref(), Some("English"));
+    }
+
+    #[test]
+    fn write_config_creates_the_directory() {
+        let dir = temp_config_dir("config-write");
+        let path = write_config(&dir, &config(), false).unwrap();
+
+        assert_eq!(path, dir.join(CONFIG_FILE));
+        assert_eq!(fs::read_to_string(&path).unwrap(), to_toml(&config()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn write_config_refuses_to_overwrite_without_force() {
+        let dir = temp_config_dir("config-overwrite");
+        write_config(&dir, &config(), false).unwrap();
+
+        let other = Config {
+            model: Some("gpt-4o".to_string()),
+            ..config()
+        };
+        let err = write_config(&dir, &other, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        write_config(&dir, &other, true).unwrap();
+        assert!(fs::read_to_string(dir.join(CONFIG_FILE))
+            .unwrap()
+            .starts_with("model = \"gpt-4o\""));
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    fn vars(pairs: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |key| {
+            pairs
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn resolve_settings_falls_back_to_defaults() {
+        let settings = resolve_settings(
+            &SettingOverrides::default(),
+            "gpt-4.1-nano",
+            vars(&[]),
+            vars(&[]),
+        );
+        assert_eq!(
+            settings,
+            Settings {
+                model: "gpt-4.1-nano".to_string(),
+                max_tokens: default_response_tokens("gpt-4.1-nano"),
+                language: None,
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_summary_model_falls_back_to_the_commit_model() {
+        assert_eq!(
+            resolve_summary_model("gpt-4.1", |_| None, |_| None),
+            "gpt-4.1"
+        );
+        let git =
+            |key: &str| (key == "autocommit.summarymodel").then(|| "gpt-4.1-nano".to_string());
+        assert_eq!(
+            resolve_summary_model("gpt-4.1", |_| None, git),
+            "gpt-4.1-nano"
+        );
+        let env = |name: &str| (name == "AUTO_COMMIT_SUMMARY_MODEL").then(|| "cheap".to_string());
+        assert_eq!(resolve_summary_model("gpt-4.1", env, git), "cheap");
+    }
+
+    #[test]
+    fn resolve_settings_reads_git_config() {
+        let git = vars(&[
+            ("autocommit.model", "gpt-4o"),
+            ("autocommit.maxtokens", "500"),
+            ("autocommit.language", "German"),
+        ]);
+        let settings =
+            resolve_settings(&SettingOverrides::default(), "gpt-4.1-nano", vars(&[]), git);
+        assert_eq!(settings.model, "gpt-4o");
+        assert_eq!(settings.max_tokens, 500);
+        assert_eq!(settings.language.as_deref(), Some("German"));
+    }
+
+    #[test]
+    fn resolve_settings_defaults_and_clamps_the_token_limit_per_model() {
+        let settings = resolve_settings(&SettingOverrides::default(), "o3", vars(&[]), vars(&[]));
+        assert_eq!(settings.max_tokens, 8_000);
+
+        let cli = SettingOverrides {
+            max_tokens: Some(1_000_000),
+            ..SettingOverrides::default()
+        };
+        let settings = resolve_settings(&cli, "gpt-4.1-nano", vars(&[]), vars(&[]));
+        assert_eq!(settings.max_tokens, 65_535);
+    }
+
+    #[test]
+    fn resolve_settings_prefers_env_over_git_config() {
+        let env = vars(&[
+            ("AUTO_COMMIT_MODEL", "gpt-4.1"),
+            ("AUTO_COMMIT_MAX_TOKENS", "800"),
+        ]);
+        let git = vars(&[
+            ("autocommit.model", "gpt-4o"),
+            ("autocommit.maxtokens", "500"),
+            ("autocommit.language", "German"),
+        ]);
+        let settings = resolve_settings(&SettingOverrides::default(), "gpt-4.1-nano", env, git);
+        assert_eq!(settings.model, "gpt-4.1");
+        assert_eq!(settings.max_tokens, 800);
+        assert_eq!(settings.language.as_deref(), Some("German"));
+    }
+
+    #[test]
+    fn resolve_settings_prefers_cli_over_everything() {
+        let cli = SettingOverrides {
+            model: Some("claude-sonnet-4-0".to_string()),
+            max_tokens: Some(100),
+            language: Some("French".to_string()),
+        };
+        let env = vars(&[
+            ("AUTO_COMMIT_MODEL", "gpt-4.1"),
+            ("AUTO_COMMIT_LANGUAGE", "Dutch"),
+        ]);
+        let git = vars(&[
+            ("autocommit.model", "gpt-4o"),
+            ("autocommit.maxtokens", "500"),
+        ]);
+        let settings = resolve_settings(&cli, "gpt-4.1-nano", env, git);
+        assert_eq!(settings.model, "claude-sonnet-4-0");
+        assert_eq!(settings.max_tokens, 100);
+        assert_eq!(settings.language.as_deref(), Some("French"));
+    }
+
+    #[test]
+    fn resolve_settings_skips_invalid_token_counts() {
+        let env = vars(&[("AUTO_COMMIT_MAX_TOKENS", "lots")]);
+        let git = vars(&[("autocommit.maxtokens", "300")]);
+        let settings = resolve_settings(&SettingOverrides::default(), "gpt-4.1-nano", env, git);
+        assert_eq!(settings.max_tokens, 300);
+    }
+
+    #[test]
+    fn config_dir_prefers_xdg_config_home() {
+        let _env = env_lock();
+        std::env::set_var("XDG_CONFIG_HOME", "/tmp/xdg");
+        assert_eq!(config_dir(), Some(PathBuf::from("/tmp/xdg/auto-commit")));
+        std::env::remove_var("XDG_CONFIG_HOME");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_dir_falls_back_to_home() {
+        let _env = env_lock();
+        let home = std::env::var_os("HOME");
+        std::env::set_var("XDG_CONFIG_HOME", "");
+        std::env::set_var("HOME", "/home/ada");
+        assert_eq!(
+            config_dir(),
+            Some(PathBuf::from("/home/ada/.config/auto-commit"))
+        );
+        std::env::remove_var("XDG_CONFIG_HOME");
+        match home {
+            Some(home) => std::env::set_var("HOME", home),
+            None => std::env::remove_var("HOME"),
+        }
+    }
+
+    #[test]
+    fn parse_dotenv_reads_assignments() {
+        let vars = parse_dotenv(
+            "# API keys\n\
+             \n\
+             OPENAI_API_KEY=sk-test\n\
+             export AUTO_COMMIT_MODEL = gpt-4.1 # the cheap one\n\
+             \n\
+             AUTO_COMMIT_LANGUAGE=\"Brazilian Portuguese\"\n\
+             AUTO_COMMIT_PROVIDER='anthropic'\n\
+             EMPTY=\n",
+        );
+        assert_eq!(vars.len(), 5);
+        assert_eq!(vars["OPENAI_API_KEY"], "sk-test");
+        assert_eq!(vars["AUTO_COMMIT_MODEL"], "gpt-4.1");
+        assert_eq!(vars["AUTO_COMMIT_LANGUAGE"], "Brazilian Portuguese");
+        assert_eq!(vars["AUTO_COMMIT_PROVIDER"], "anthropic");
+        assert_eq!(vars["EMPTY"], "");
+    }
+
+    #[test]
+    fn parse_dotenv_keeps_hashes_inside_quotes() {
+        let vars = parse_dotenv("TOKEN=\"abc #123\"\nOTHER=abc#123\n");
+        assert_eq!(vars["TOKEN"], "abc #123");
+        assert_eq!(vars["OTHER"], "abc#123");
+    }
+
+    #[test]
+    fn parse_dotenv_skips_malformed_lines() {
+        let vars = parse_dotenv("not an assignment\n=value\nMY KEY=value\nOK=1\n");
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars["OK"], "1");
+    }
+
+    #[test]
+    fn load_dotenv_without_a_file_is_empty() {
+        assert!(load_dotenv(Path::new("/nonexistent/auto-commit/.env")).is_empty());
+    }
+
+    #[test]
+    fn apply_dotenv_keeps_existing_variables() {
+        let _lock = env_lock();
+        std::env::set_var("AUTO_COMMIT_DOTENV_SET", "from the shell");
+        std::env::remove_var("AUTO_COMMIT_DOTENV_UNSET");
+
+        apply_dotenv(parse_dotenv(
+            "AUTO_COMMIT_DOTENV_SET=from .env\nAUTO_COMMIT_DOTENV_UNSET=from .env\n",
+        ));
+        assert_eq!(
+            std::env::var("AUTO_COMMIT_DOTENV_SET").unwrap(),
+            "from the shell"
+        );
+        assert_eq!(
+            std::env::var("AUTO_COMMIT_DOTENV_UNSET").unwrap(),
+            "from .env"
+        );
+
+        std::env::remove_var("AUTO_COMMIT_DOTENV_SET");
+        std::env::remove_var("AUTO_COMMIT_DOTENV_UNSET");
+    }
+}
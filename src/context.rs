@@ -0,0 +1,605 @@
+use crate::{
+    cost::count_tokens,
+    generator::{GenOptions, DEFAULT_MAX_RESPONSE_TOKENS},
+    mapreduce::split_diff_by_file,
+    truncate_to_n_tokens, truncate_with_report, TruncationReport,
+};
+
+/// Context window assumed for models missing from `CONTEXT_WINDOWS`.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+/// Tokens taken by the tool definitions and message framing, on top of the system prompt.
+pub const PROMPT_OVERHEAD_TOKENS: usize = 300;
+
+/// The range of response token limits sent to the API; the old `u16` builder
+/// argument set the upper bound.
+pub const RESPONSE_TOKENS_RANGE: std::ops::RangeInclusive<u32> = 1..=65_535;
+
+/// Response token limits for models that want something other than
+/// `DEFAULT_MAX_RESPONSE_TOKENS`, matched by longest model name prefix.
+/// Reasoning models spend part of the limit thinking, so they get more.
+const RESPONSE_TOKEN_DEFAULTS: &[(&str, u32)] = &[
+    ("gpt-4.1", 1_000),
+    ("gpt-4o", 1_000),
+    ("gpt-5", 8_000),
+    ("o1", 8_000),
+    ("o3", 8_000),
+    ("o4-mini", 8_000),
+    ("claude", 1_000),
+];
+
+/// The response token limit used for `model` when none is configured.
+pub fn default_response_tokens(model: &str) -> u32 {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    RESPONSE_TOKEN_DEFAULTS
+        .iter()
+        .filter(|(name, _)| model.starts_with(name))
+        .max_by_key(|(name, _)| name.len())
+        .map_or(DEFAULT_MAX_RESPONSE_TOKENS, |(_, tokens)| *tokens)
+}
+
+/// `tokens` moved into `RESPONSE_TOKENS_RANGE`.
+pub fn clamp_response_tokens(tokens: u32) -> u32 {
+    tokens.clamp(*RESPONSE_TOKENS_RANGE.start(), *RESPONSE_TOKENS_RANGE.end())
+}
+
+/// Context window sizes in tokens, matched by longest model name prefix.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude", 200_000),
+];
+
+pub fn context_window_for(model: &str) -> usize {
+    // GitHub Models prefixes IDs with the vendor, e.g. `openai/gpt-4.1`
+    let model = model.rsplit('/').next().unwrap_or(model);
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(name, _)| model.starts_with(name))
+        .max_by_key(|(name, _)| name.len())
+        .map_or(DEFAULT_CONTEXT_WINDOW, |(_, window)| *window)
+}
+
+/// Tokens left for the diff once the response and the prompt are accounted for.
+pub fn diff_token_budget(opts: &GenOptions) -> usize {
+    context_window_for(&opts.model).saturating_sub(
+        opts.max_tokens as usize
+            + count_tokens(&opts.model, &opts.system_prompt)
+            + PROMPT_OVERHEAD_TOKENS,
+    )
+}
+
+/// Size of the diff context before and after truncation, counted in the
+/// whitespace-separated tokens `truncate_to_n_tokens` works with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextStats {
+    pub original_tokens: usize,
+    pub sent_tokens: usize,
+    pub truncated: bool,
+}
+
+/// The context sent to the model: the changed file list followed by the diff.
+pub fn combine_context(files: &str, diff: &str) -> String {
+    format!("Changed files:\n{}\n\nDiff:\n{}", files, diff)
+}
+
+/// Puts a `git diff --stat` summary in front of `context`, so it counts toward
+/// the same token budget.
+pub fn prepend_stat(stat: &str, context: &str) -> String {
+    if stat.trim().is_empty() {
+        return context.to_string();
+    }
+    format!("Diffstat:\n{}\n\n{}", stat.trim_end(), context)
+}
+
+/// `(old, new)` paths of the files `diff` renames, from git's `rename from` and
+/// `rename to` headers.
+pub fn extract_renames(diff: &str) -> Vec<(String, String)> {
+    let mut renames = Vec::new();
+    let mut from = None;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            from = None;
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            from = Some(path.to_string());
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            if let Some(from) = from.take() {
+                renames.push((from, path.to_string()));
+            }
+        }
+    }
+    renames
+}
+
+/// Puts a `Renamed: old -> new` line per rename in front of `context`, so moves
+/// aren't mistaken for a deletion and an unrelated new file.
+pub fn prepend_renames(renames: &[(String, String)], context: &str) -> String {
+    if renames.is_empty() {
+        return context.to_string();
+    }
+    let lines: Vec<String> = renames
+        .iter()
+        .map(|(from, to)| format!("Renamed: {} -> {}", from, to))
+        .collect();
+    format!("{}\n\n{}", lines.join("\n"), context)
+}
+
+/// `diff` without the hunks that only change whitespace, and without the files
+/// left with no hunks. Files that never had hunks, like pure renames, are kept.
+pub fn drop_whitespace_only_hunks(diff: &str) -> String {
+    let mut out = String::new();
+    let mut section: Vec<&str> = Vec::new();
+    for line in diff.split_inclusive('\n') {
+        if line.starts_with("diff --git ") && !section.is_empty() {
+            out.push_str(&filter_file_hunks(&section));
+            section.clear();
+        }
+        section.push(line);
+    }
+    out.push_str(&filter_file_hunks(&section));
+    out
+}
+
+/// One file's section of a diff, minus its whitespace-only hunks.
+fn filter_file_hunks(lines: &[&str]) -> String {
+    let Some(first_hunk) = lines.iter().position(|line| line.starts_with("@@")) else {
+        return lines.concat();
+    };
+    let mut hunks: Vec<Vec<&str>> = Vec::new();
+    for line in &lines[first_hunk..] {
+        match hunks.last_mut() {
+            Some(hunk) if !line.starts_with("@@") => hunk.push(line),
+            _ => hunks.push(vec![line]),
+        }
+    }
+    let kept: Vec<String> = hunks
+        .iter()
+        .filter(|hunk| !is_whitespace_only(hunk))
+        .map(|hunk| hunk.concat())
+        .collect();
+    if kept.is_empty() {
+        return String::new();
+    }
+    format!("{}{}", lines[..first_hunk].concat(), kept.concat())
+}
+
+/// Whether the hunk's removed and added lines differ only in whitespace.
+fn is_whitespace_only(hunk: &[&str]) -> bool {
+    let text = |prefix: char| -> String {
+        hunk.iter()
+            .filter_map(|line| line.strip_prefix(prefix))
+            .flat_map(str::chars)
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    };
+    let changed = hunk[1..]
+        .iter()
+        .any(|line| line.starts_with('+') || line.starts_with('-'));
+    changed && text('-') == text('+')
+}
+
+/// Paths of the files a diff touches, for diffs that don't come with a file list.
+pub fn files_from_diff(diff: &str) -> Vec<String> {
+    split_diff_by_file(diff)
+        .into_iter()
+        .map(|file| file.path)
+        .collect()
+}
+
+/// Number of added and removed lines in `diff`, not counting file headers.
+pub fn changed_line_count(diff: &str) -> usize {
+    diff.lines()
+        .filter(|line| {
+            (line.starts_with('+') && !line.starts_with("+++"))
+                || (line.starts_with('-') && !line.starts_with("---"))
+        })
+        .count()
+}
+
+/// Puts the `--context` background in front of `context` and truncates the
+/// result to `limit` tokens. The diff context is trimmed first, so the
+/// background survives whole unless it alone is over the limit.
+pub fn assemble_context(
+    background: &[String],
+    context: &str,
+    limit: usize,
+) -> (String, TruncationReport) {
+    let background: Vec<&str> = background
+        .iter()
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .collect();
+    if background.is_empty() {
+        return truncate_with_report(context, limit);
+    }
+
+    let header = format!("Background:\n{}", background.join("\n\n"));
+    let header_tokens = header.split_whitespace().count();
+    if header_tokens >= limit {
+        let total_tokens = header_tokens + context.split_whitespace().count();
+        let report = TruncationReport {
+            total_tokens,
+            kept_tokens: limit,
+            dropped_tokens: total_tokens - limit,
+        };
+        return (truncate_to_n_tokens(&header, limit), report);
+    }
+    let (context, mut report) = truncate_with_report(context, limit - header_tokens);
+    report.total_tokens += header_tokens;
+    report.kept_tokens += header_tokens;
+    (format!("{}\n\n{}", header, context), report)
+}
+
+/// Like `assemble_context`, but keeps the result within `token_budget` tokens
+/// of `model`'s tokenizer. `assemble_context` cuts by whitespace-separated
+/// words, and a word of code is often several tokens, so the word limit is
+/// scaled down by the measured ratio until the result fits.
+pub fn fit_context(
+    model: &str,
+    background: &[String],
+    context: &str,
+    token_budget: usize,
+) -> (String, TruncationReport) {
+    let (mut fitted, mut report) = assemble_context(background, context, usize::MAX);
+    let mut limit = report.total_tokens;
+    loop {
+        let tokens = count_tokens(model, &fitted);
+        if tokens <= token_budget || limit == 0 {
+            return (fitted, report);
+        }
+        limit = (limit - 1).min(limit * token_budget / tokens);
+        (fitted, report) = assemble_context(background, context, limit);
+    }
+}
+
+pub fn context_stats(original: &str, truncated: &str) -> ContextStats {
+    let original_tokens = original.split_whitespace().count();
+    let sent_tokens = truncated.split_whitespace().count();
+    ContextStats {
+        original_tokens,
+        sent_tokens,
+        truncated: sent_tokens < original_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator::{generate_checked, stub::StubGenerator, GenOptions},
+        message::CommitRules,
+    };
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-pub mod old;
++pub mod new;
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1 +1 @@
+-Old
++New
+";
+
+    fn opts(model: &str, max_tokens: u32) -> GenOptions {
+        GenOptions {
+            model: model.to_string(),
+            system_prompt: "hello world".to_string(),
+            max_tokens,
+            no_tools: false,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn context_window_for_known_models() {
+        assert_eq!(context_window_for("gpt-4.1-nano"), 1_047_576);
+        assert_eq!(context_window_for("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window_for("gpt-4"), 8_192);
+        assert_eq!(context_window_for("gpt-4-turbo-2024-04-09"), 128_000);
+        assert_eq!(context_window_for("claude-3-5-haiku-latest"), 200_000);
+        assert_eq!(context_window_for("openai/gpt-4.1-nano"), 1_047_576);
+    }
+
+    #[test]
+    fn default_response_tokens_by_model() {
+        assert_eq!(default_response_tokens("gpt-4.1-nano"), 1_000);
+        assert_eq!(default_response_tokens("openai/gpt-4o-mini"), 1_000);
+        assert_eq!(default_response_tokens("o3-mini"), 8_000);
+        assert_eq!(default_response_tokens("claude-3-5-haiku-latest"), 1_000);
+        assert_eq!(
+            default_response_tokens("llama3"),
+            DEFAULT_MAX_RESPONSE_TOKENS
+        );
+    }
+
+    #[test]
+    fn clamp_response_tokens_keeps_limits_in_range() {
+        assert_eq!(clamp_response_tokens(0), 1);
+        assert_eq!(clamp_response_tokens(500), 500);
+        assert_eq!(clamp_response_tokens(100_000), 65_535);
+    }
+
+    #[test]
+    fn context_window_for_unknown_models_is_conservative() {
+        assert_eq!(context_window_for("llama3"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(context_window_for(""), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn diff_token_budget_reserves_response_and_prompt() {
+        // "hello world" is two tokens
+        assert_eq!(
+            diff_token_budget(&opts("gpt-3.5-turbo", 2000)),
+            16_385 - 2000 - 2 - PROMPT_OVERHEAD_TOKENS
+        );
+        assert_eq!(diff_token_budget(&opts("llama3", 10_000)), 0);
+    }
+
+    #[test]
+    fn prepend_stat_puts_the_stat_first() {
+        let stat = " src/lib.rs | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n";
+        let context = combine_context("src/lib.rs", DIFF);
+        let with_stat = prepend_stat(stat, &context);
+
+        assert!(with_stat.starts_with("Diffstat:\n src/lib.rs | 2 +-\n 1 file changed"));
+        assert!(with_stat.ends_with(&context));
+        assert_eq!(prepend_stat("", &context), context);
+    }
+
+    #[test]
+    fn prepend_stat_counts_toward_the_budget() {
+        let stat = "one two three four five";
+        let (truncated, report) = truncate_with_report(&prepend_stat(stat, "six seven"), 6);
+        assert_eq!(truncated, "Diffstat: one two three four five");
+        assert!(report.truncated());
+    }
+
+    #[test]
+    fn files_from_diff_lists_each_file() {
+        assert_eq!(files_from_diff(DIFF), ["src/lib.rs", "README.md"]);
+        assert!(files_from_diff("").is_empty());
+    }
+
+    #[test]
+    fn changed_line_count_skips_file_headers() {
+        assert_eq!(changed_line_count(DIFF), 4);
+        assert_eq!(changed_line_count(""), 0);
+    }
+
+    #[tokio::test]
+    async fn a_piped_diff_flows_through_to_the_generator() {
+        let combined = combine_context(&files_from_diff(DIFF).join("\n"), DIFF);
+        let (context, _) = truncate_with_report(&combined, 20_000);
+        let generator = StubGenerator::default();
+        let opts = GenOptions {
+            model: "test-model".to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        };
+        let commit = generate_checked(&generator, &context, &opts, &CommitRules::default())
+            .await
+            .unwrap();
+
+        assert_eq!(commit.title, "Stub commit");
+        let contexts = generator.contexts();
+        assert_eq!(contexts.len(), 1);
+        assert!(contexts[0].starts_with("Changed files: src/lib.rs README.md Diff:"));
+        assert!(contexts[0].ends_with("-Old +New"));
+    }
+
+    #[test]
+    fn context_stats_not_truncated() {
+        let original = "Changed files:\nsrc/main.rs\n\nDiff:\n+fn main() {}";
+        let sent = truncate_to_n_tokens(original, 100);
+        assert_eq!(
+            context_stats(original, &sent),
+            ContextStats {
+                original_tokens: 7,
+                sent_tokens: 7,
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn context_stats_truncated() {
+        let original = "a b c d e f g h";
+        let sent = truncate_to_n_tokens(original, 3);
+        assert_eq!(
+            context_stats(original, &sent),
+            ContextStats {
+                original_tokens: 8,
+                sent_tokens: 3,
+                truncated: true,
+            }
+        );
+    }
+
+    #[test]
+    fn extract_renames_reads_a_rename() {
+        let diff = "diff --git a/src/old.rs b/src/new.rs
+similarity index 100%
+rename from src/old.rs
+rename to src/new.rs
+";
+        assert_eq!(
+            extract_renames(diff),
+            [("src/old.rs".to_string(), "src/new.rs".to_string())]
+        );
+    }
+
+    #[test]
+    fn extract_renames_reads_several_renames_among_edits() {
+        let diff = "diff --git a/a.rs b/b.rs
+similarity index 87%
+rename from a.rs
+rename to b.rs
+index 1111111..2222222 100644
+--- a/a.rs
++++ b/b.rs
+@@ -1 +1 @@
+-old
++new
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1 +1 @@
+-rename from here
++rename to there
+diff --git a/docs/x.md b/guide/x.md
+similarity index 100%
+rename from docs/x.md
+rename to guide/x.md
+";
+        assert_eq!(
+            extract_renames(diff),
+            [
+                ("a.rs".to_string(), "b.rs".to_string()),
+                ("docs/x.md".to_string(), "guide/x.md".to_string()),
+            ]
+        );
+        assert!(extract_renames(DIFF).is_empty());
+    }
+
+    #[test]
+    fn assemble_context_trims_the_diff_before_the_background() {
+        let background = vec!["This implements the caching RFC.".to_string()];
+        let context = "Changed files:\na.rs\n\nDiff:\n+one +two +three +four +five";
+        let (assembled, report) = assemble_context(&background, context, 10);
+
+        assert!(assembled.starts_with("Background:\nThis implements the caching RFC.\n\n"));
+        assert!(assembled.contains("Changed files:"));
+        assert!(!assembled.contains("+five"));
+        assert_eq!(assembled.split_whitespace().count(), 10);
+        assert!(report.truncated());
+    }
+
+    #[test]
+    fn fit_context_stays_within_the_token_budget() {
+        let model = "gpt-4";
+        // Punctuation-heavy code takes several tokens per word
+        let diff = "+let x=self.map.get(&k).unwrap().clone();\n".repeat(2_000);
+        let context = combine_context("src/lib.rs", &diff);
+        let background = vec!["Why: faster lookups.".to_string()];
+        let budget = diff_token_budget(&GenOptions {
+            model: model.to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 1_000,
+            no_tools: false,
+            seed: None,
+        });
+        let (words, _) = assemble_context(&background, &context, budget);
+        assert!(count_tokens(model, &words) > budget);
+
+        let (fitted, report) = fit_context(model, &background, &context, budget);
+        assert!(count_tokens(model, &fitted) <= budget);
+        assert!(fitted.starts_with("Background:\nWhy: faster lookups."));
+        assert!(report.truncated());
+    }
+
+    #[test]
+    fn fit_context_keeps_a_context_that_fits() {
+        let context = "Changed files:\na.rs\n\nDiff:\n+one";
+        assert_eq!(
+            fit_context("gpt-4", &[], context, 100),
+            assemble_context(&[], context, usize::MAX)
+        );
+    }
+
+    #[test]
+    fn assemble_context_without_background_is_plain_truncation() {
+        let context = "Diff:\n+one +two +three";
+        assert_eq!(
+            assemble_context(&["  ".to_string()], context, 3),
+            truncate_with_report(context, 3)
+        );
+        let (assembled, report) = assemble_context(&["Why: speed".to_string()], context, 100);
+        assert_eq!(
+            assembled,
+            "Background:\nWhy: speed\n\nDiff: +one +two +three"
+        );
+        assert!(!report.truncated());
+    }
+
+    #[test]
+    fn prepend_renames_adds_a_line_per_rename() {
+        let renames = [("a.rs".to_string(), "b.rs".to_string())];
+        assert_eq!(
+            prepend_renames(&renames, "Changed files:\nb.rs"),
+            "Renamed: a.rs -> b.rs\n\nChanged files:\nb.rs"
+        );
+        assert_eq!(prepend_renames(&[], "context"), "context");
+    }
+
+    #[test]
+    fn drop_whitespace_only_hunks_removes_reindented_files() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-  run();
++    run();
+ }
+@@ -10,2 +10,2 @@
+-let  x  =  1;
++let x = 1;
+";
+        assert_eq!(drop_whitespace_only_hunks(diff), "");
+    }
+
+    #[test]
+    fn drop_whitespace_only_hunks_keeps_real_changes() {
+        let reformatted = "diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1 +1 @@
+-foo( x )
++foo(x)
+";
+        let mixed = "diff --git a/b.rs b/b.rs
+--- a/b.rs
++++ b/b.rs
+@@ -1,2 +1,2 @@
+-  let a = 1;
++    let a = 1;
+@@ -8 +8 @@
+-let b = 1;
++let b = 2;
+";
+        let renamed = "diff --git a/c.rs b/d.rs
+similarity index 100%
+rename from c.rs
+rename to d.rs
+";
+        let diff = format!("{}{}{}", reformatted, mixed, renamed);
+        assert_eq!(
+            drop_whitespace_only_hunks(&diff),
+            "diff --git a/b.rs b/b.rs
+--- a/b.rs
++++ b/b.rs
+@@ -8 +8 @@
+-let b = 1;
++let b = 2;
+diff --git a/c.rs b/d.rs
+similarity index 100%
+rename from c.rs
+rename to d.rs
+"
+        );
+        assert_eq!(drop_whitespace_only_hunks(DIFF), DIFF);
+    }
+}
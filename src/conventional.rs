@@ -0,0 +1,95 @@
+/// The scope of a conventional commit title such as `feat(parser)!: ...`.
+pub fn title_scope(title: &str) -> Option<&str> {
+    let (prefix, _) = title.trim_start().split_once(':')?;
+    let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+    let (kind, rest) = prefix.split_once('(')?;
+    let scope = rest.strip_suffix(')')?;
+    (!kind.is_empty() && kind.chars().all(char::is_alphanumeric)).then_some(scope)
+}
+
+/// Whether `title` is a conventional commit title scoped to `scope`.
+pub fn has_scope(title: &str, scope: &str) -> bool {
+    title_scope(title).is_some_and(|s| s.trim().eq_ignore_ascii_case(scope.trim()))
+}
+
+/// Directories that hold one package per subdirectory in monorepos.
+const PACKAGE_DIRS: &[&str] = &["crates", "packages", "apps", "libs", "services", "modules"];
+
+/// The top-level directory every file in `files` lives under, or the package
+/// name for monorepo layouts like `crates/<name>/...`.
+pub fn infer_scope(files: &[&str]) -> Option<String> {
+    let dirs: Vec<Vec<&str>> = files
+        .iter()
+        .map(|file| {
+            let mut parts: Vec<&str> = file.split('/').collect();
+            parts.pop(); // the file name
+            parts
+        })
+        .collect();
+    let shared = |depth: usize| {
+        let first = dirs.first()?.get(depth)?;
+        dirs.iter()
+            .all(|parts| parts.get(depth) == Some(first))
+            .then_some(*first)
+    };
+
+    let top = shared(0)?;
+    match shared(1) {
+        Some(package) if PACKAGE_DIRS.contains(&top) => Some(package.to_string()),
+        _ => Some(top.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_scope_reads_the_scope() {
+        assert_eq!(title_scope("feat(parser): Add lists"), Some("parser"));
+        assert_eq!(title_scope("fix(api)!: Drop v1"), Some("api"));
+        assert_eq!(title_scope("feat: Add lists"), None);
+        assert_eq!(title_scope("Add lists (parser)"), None);
+    }
+
+    #[test]
+    fn infer_scope_uses_the_shared_top_level_directory() {
+        assert_eq!(
+            infer_scope(&["docs/intro.md", "docs/guide/setup.md"]),
+            Some("docs".to_string())
+        );
+        assert_eq!(
+            infer_scope(&["crates/parser/src/lib.rs", "crates/parser/Cargo.toml"]),
+            Some("parser".to_string())
+        );
+        assert_eq!(
+            infer_scope(&["crates/parser/src/lib.rs", "crates/lexer/src/lib.rs"]),
+            Some("crates".to_string())
+        );
+    }
+
+    #[test]
+    fn infer_scope_without_a_shared_directory() {
+        assert_eq!(infer_scope(&["src/lib.rs", "docs/intro.md"]), None);
+        assert_eq!(infer_scope(&["src/lib.rs", "README.md"]), None);
+        assert_eq!(infer_scope(&[]), None);
+    }
+
+    #[test]
+    fn infer_scope_for_a_single_file_at_the_root() {
+        assert_eq!(infer_scope(&["README.md"]), None);
+    }
+
+    #[test]
+    fn has_scope_matches_the_given_scope() {
+        assert!(has_scope("feat(parser): Add lists", "parser"));
+        assert!(has_scope("feat(Parser): Add lists", "parser"));
+    }
+
+    #[test]
+    fn has_scope_rejects_other_or_missing_scopes() {
+        assert!(!has_scope("feat(lexer): Add lists", "parser"));
+        assert!(!has_scope("feat: Add lists", "parser"));
+        assert!(!has_scope("Add parser lists", "parser"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    ops::Add,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tiktoken_rs::{
+    cl100k_base_singleton, o200k_base_singleton,
+    tokenizer::{get_tokenizer, Tokenizer},
+    CoreBPE,
+};
+
+/// USD per million input and output tokens. Dated snapshots such as
+/// `gpt-4.1-nano-2025-04-14` match by prefix, longest first.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("o4-mini", 1.10, 4.40),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+];
+
+/// The BPE tokenizer for `model`'s family, loaded on first use and shared by
+/// every later lookup in the process. Models other than OpenAI's get
+/// `o200k_base` as an approximation.
+pub fn tokenizer_for(model: &str) -> &'static CoreBPE {
+    match get_tokenizer(model) {
+        Some(Tokenizer::Cl100kBase) => cl100k_base_singleton(),
+        _ => o200k_base_singleton(),
+    }
+}
+
+/// Number of tokens in `text` for `model`, using `tokenizer_for`.
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    tokenizer_for(model).encode_with_special_tokens(text).len()
+}
+
+/// Estimated USD cost of a request, or `None` if `model` isn't in the price table.
+pub fn estimate_cost(model: &str, input_tokens: usize, output_tokens: usize) -> Option<f64> {
+    let (_, input_price, output_price) = PRICES
+        .iter()
+        .filter(|(name, _, _)| model.starts_with(name))
+        .max_by_key(|(name, _, _)| name.len())?;
+    Some((input_tokens as f64 * input_price + output_tokens as f64 * output_price) / 1_000_000.0)
+}
+
+/// Tokens billed for one or more requests, as reported by the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total_tokens(self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+        }
+    }
+}
+
+/// Running total of the usage reported by a generator's requests.
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+}
+
+impl UsageMeter {
+    pub fn record(&self, usage: Usage) {
+        self.prompt_tokens
+            .fetch_add(usage.prompt_tokens, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(usage.completion_tokens, Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens.load(Ordering::Relaxed),
+            completion_tokens: self.completion_tokens.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The `--stats` summary of a run.
+pub fn format_usage_line(usage: Usage, model: &str, elapsed: Duration) -> String {
+    format!(
+        "model={} prompt={} completion={} total={} elapsed={}ms",
+        model,
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.total_tokens(),
+        elapsed.as_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("expected a price");
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn estimate_cost_for_known_models() {
+        assert_close(estimate_cost("gpt-4.1-nano", 1_000_000, 0), 0.10);
+        assert_close(estimate_cost("gpt-4.1-nano", 10_000, 500), 0.0012);
+        assert_close(estimate_cost("gpt-4o", 0, 1_000_000), 10.00);
+        assert_close(
+            estimate_cost("claude-3-5-haiku-latest", 1_000, 1_000),
+            0.0048,
+        );
+    }
+
+    #[test]
+    fn estimate_cost_matches_the_longest_prefix() {
+        assert_close(estimate_cost("gpt-4.1-nano-2025-04-14", 1_000_000, 0), 0.10);
+        assert_close(estimate_cost("gpt-4.1", 1_000_000, 0), 2.00);
+        assert_close(estimate_cost("gpt-4o-mini", 1_000_000, 0), 0.15);
+    }
+
+    #[test]
+    fn estimate_cost_for_unknown_models() {
+        assert_eq!(estimate_cost("llama3", 1_000, 1_000), None);
+        assert_eq!(estimate_cost("", 1_000, 1_000), None);
+    }
+
+    #[test]
+    fn tokenizer_for_reuses_the_loaded_tokenizer() {
+        // Same family, so the same loaded tokenizer rather than a fresh one
+        let first = tokenizer_for("gpt-4.1-nano");
+        assert!(std::ptr::eq(first, tokenizer_for("gpt-4.1-mini")));
+        assert!(std::ptr::eq(tokenizer_for("claude-sonnet-4"), first));
+        assert!(!std::ptr::eq(tokenizer_for("gpt-4"), first));
+    }
+
+    #[test]
+    fn count_tokens_uses_the_model_tokenizer() {
+        assert_eq!(count_tokens("gpt-4.1-nano", ""), 0);
+        assert_eq!(count_tokens("gpt-4.1-nano", "hello world"), 2);
+        assert_eq!(count_tokens("gpt-4", "hello world"), 2);
+    }
+
+    #[test]
+    fn format_usage_line_lists_every_field() {
+        let usage = Usage {
+            prompt_tokens: 1_200,
+            completion_tokens: 45,
+        };
+        assert_eq!(
+            format_usage_line(usage, "gpt-4.1-nano", Duration::from_millis(1534)),
+            "model=gpt-4.1-nano prompt=1200 completion=45 total=1245 elapsed=1534ms"
+        );
+        assert_eq!(
+            format_usage_line(Usage::default(), "claude", Duration::ZERO),
+            "model=claude prompt=0 completion=0 total=0 elapsed=0ms"
+        );
+    }
+
+    #[test]
+    fn usage_meter_adds_up_requests() {
+        let meter = UsageMeter::default();
+        assert_eq!(meter.total(), Usage::default());
+        meter.record(Usage {
+            prompt_tokens: 100,
+            completion_tokens: 10,
+        });
+        meter.record(Usage {
+            prompt_tokens: 50,
+            completion_tokens: 5,
+        });
+        assert_eq!(
+            meter.total(),
+            Usage {
+                prompt_tokens: 150,
+                completion_tokens: 15,
+            }
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fs, io, process::Command};
+
+/// Used when none of the editor variables is set.
+pub const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// `$GIT_EDITOR`, then `$VISUAL`, then `$EDITOR`, then `DEFAULT_EDITOR`, the
+/// same order `git commit -e` uses.
+pub fn resolve_editor() -> String {
+    ["GIT_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Opens `initial` in the user's editor and returns the edited text.
+pub fn edit_in_editor(initial: &str) -> io::Result<String> {
+    let path = std::env::temp_dir().join(format!("auto-commit-{}.txt", std::process::id()));
+    fs::write(&path, initial)?;
+
+    // The editor may carry its own arguments, e.g. `code --wait`
+    let editor = resolve_editor();
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(DEFAULT_EDITOR);
+    let status = Command::new(program).args(parts).arg(&path).status();
+
+    let edited = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            editor, status
+        ))),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&path);
+
+    Ok(edited?.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    fn clear_editor_vars() {
+        for var in ["GIT_EDITOR", "VISUAL", "EDITOR"] {
+            std::env::remove_var(var);
+        }
+    }
+
+    #[test]
+    fn resolve_editor_prefers_git_editor() {
+        let _lock = env_lock();
+        clear_editor_vars();
+        std::env::set_var("GIT_EDITOR", "nano");
+        std::env::set_var("VISUAL", "code --wait");
+        std::env::set_var("EDITOR", "vim");
+        assert_eq!(resolve_editor(), "nano");
+
+        std::env::set_var("GIT_EDITOR", " ");
+        assert_eq!(resolve_editor(), "code --wait");
+        std::env::remove_var("VISUAL");
+        assert_eq!(resolve_editor(), "vim");
+        clear_editor_vars();
+    }
+
+    #[test]
+    fn resolve_editor_falls_back_to_the_platform_default() {
+        let _lock = env_lock();
+        clear_editor_vars();
+        assert_eq!(resolve_editor(), DEFAULT_EDITOR);
+        assert_eq!(DEFAULT_EDITOR, if cfg!(windows) { "notepad" } else { "vi" });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edit_in_editor_round_trips_through_the_editor() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _lock = env_lock();
+        clear_editor_vars();
+        let script =
+            std::env::temp_dir().join(format!("auto-commit-editor-{}.sh", std::process::id()));
+        // Stands in for an editor: keeps the message and adds a line to it
+        fs::write(&script, "#!/bin/sh\necho 'Edited by hand.' >> \"$1\"\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var("EDITOR", &script);
+
+        let edited = edit_in_editor("Fix bug\n\nDetails\n");
+        std::env::remove_var("EDITOR");
+        fs::remove_file(&script).unwrap();
+
+        assert_eq!(edited.unwrap(), "Fix bug\n\nDetails\nEdited by hand.");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edit_in_editor_reports_a_failing_editor() {
+        let _lock = env_lock();
+        clear_editor_vars();
+        std::env::set_var("EDITOR", "false");
+
+        let edited = edit_in_editor("Fix bug");
+        std::env::remove_var("EDITOR");
+
+        assert!(edited
+            .unwrap_err()
+            .to_string()
+            .contains("false exited with"));
+    }
+}
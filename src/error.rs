@@ -0,0 +1,130 @@
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// The working directory isn't inside a git repository.
+    NotAGitRepo,
+    /// The `git` executable couldn't be run.
+    GitNotFound,
+    /// There is nothing staged to describe.
+    NoStagedChanges,
+    /// Missing or invalid configuration, such as an unset API key.
+    Config(String),
+    /// The model API failed or returned something unusable.
+    Api(String),
+    /// The model answered, but not with a valid commit.
+    InvalidCommit(String),
+    /// The model didn't answer in time.
+    Timeout(Duration),
+    /// The `--post-process` command failed.
+    PostProcess(String),
+    /// `git push` failed or had nowhere to push to.
+    Push(String),
+    /// `--split` couldn't carry out the model's plan.
+    Split(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotAGitRepo => {
+                write!(f, "Not in a git repo; run from the root or `git init`.")
+            }
+            AppError::GitNotFound => write!(f, "git not found on PATH; install git and try again."),
+            AppError::NoStagedChanges => write!(f, "No staged files – try `git add`."),
+            AppError::Config(msg) => write!(f, "{}", msg),
+            AppError::Api(msg) => write!(f, "API request failed: {}", msg),
+            AppError::InvalidCommit(msg) => {
+                write!(f, "The model returned an invalid commit: {}", msg)
+            }
+            AppError::Timeout(timeout) => write!(
+                f,
+                "The model didn't respond within {}s; try again or raise --timeout.",
+                timeout.as_secs()
+            ),
+            AppError::PostProcess(msg) => write!(f, "The post-process command failed: {}", msg),
+            AppError::Push(msg) => write!(f, "Couldn't push: {}", msg),
+            AppError::Split(msg) => write!(f, "Couldn't split the commit: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// Process exit codes, so scripts can tell failures apart:
+///
+/// | code | meaning |
+/// |------|---------|
+/// | 0 | success |
+/// | 1 | any other failure, e.g. an aborted review |
+/// | 2 | not inside a git repository |
+/// | 3 | no staged changes |
+/// | 4 | missing API key or invalid configuration |
+/// | 5 | the model API failed or timed out |
+/// | 6 | `git` isn't installed or isn't on `PATH` |
+/// | 130 | interrupted with Ctrl-C |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Generic = 1,
+    NotAGitRepo = 2,
+    NoStagedChanges = 3,
+    Config = 4,
+    Api = 5,
+    GitNotFound = 6,
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl From<&AppError> for ExitCode {
+    fn from(error: &AppError) -> Self {
+        match error {
+            AppError::NotAGitRepo => ExitCode::NotAGitRepo,
+            AppError::GitNotFound => ExitCode::GitNotFound,
+            AppError::NoStagedChanges => ExitCode::NoStagedChanges,
+            AppError::Config(_) => ExitCode::Config,
+            AppError::Api(_) | AppError::InvalidCommit(_) | AppError::Timeout(_) => ExitCode::Api,
+            AppError::PostProcess(_) | AppError::Push(_) | AppError::Split(_) => ExitCode::Generic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_match_the_documented_values() {
+        assert_eq!(ExitCode::Success.code(), 0);
+        assert_eq!(ExitCode::Generic.code(), 1);
+        assert_eq!(ExitCode::NotAGitRepo.code(), 2);
+        assert_eq!(ExitCode::NoStagedChanges.code(), 3);
+        assert_eq!(ExitCode::Config.code(), 4);
+        assert_eq!(ExitCode::Api.code(), 5);
+        assert_eq!(ExitCode::GitNotFound.code(), 6);
+    }
+
+    #[test]
+    fn every_app_error_maps_to_its_exit_code() {
+        let cases = [
+            (AppError::NotAGitRepo, 2),
+            (AppError::NoStagedChanges, 3),
+            (AppError::Config("no key".to_string()), 4),
+            (AppError::Api("503".to_string()), 5),
+            (AppError::InvalidCommit("missing title".to_string()), 5),
+            (AppError::Timeout(Duration::from_secs(30)), 5),
+            (AppError::GitNotFound, 6),
+            (AppError::PostProcess("exit status 1".to_string()), 1),
+            (AppError::Push("rejected".to_string()), 1),
+            (AppError::Split("`git add` failed".to_string()), 1),
+        ];
+        for (error, code) in cases {
+            assert_eq!(ExitCode::from(&error).code(), code, "{:?}", error);
+        }
+    }
+}
@@ -0,0 +1,912 @@
+use crate::{
+    cost::Usage,
+    error::AppError,
+    message::{Commit, CommitRules},
+    DEFAULT_MODEL,
+};
+use log::warn;
+use std::{future::Future, time::Duration};
+
+mod anthropic;
+pub mod failover;
+mod openai;
+pub mod retry;
+
+pub use anthropic::AnthropicGenerator;
+use failover::{parse_api_keys, Failover};
+pub use openai::{OpenAiEndpoint, OpenAiGenerator};
+use retry::with_deadline;
+
+pub const DEFAULT_MAX_RESPONSE_TOKENS: u32 = 2000;
+
+/// Settings shared by every backend for a single generation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GenOptions {
+    pub model: String,
+    pub system_prompt: String,
+    pub max_tokens: u32,
+    /// Send the diff in a user message and ask for the commit as plain JSON,
+    /// for OpenAI-compatible endpoints without tool calls.
+    pub no_tools: bool,
+    /// Sampling seed, for reproducible output where the backend supports it.
+    pub seed: Option<i64>,
+}
+
+/// A model backend that turns the diff context into a structured `Commit`.
+pub trait CommitGenerator {
+    fn generate(
+        &self,
+        context: &str,
+        opts: &GenOptions,
+    ) -> impl Future<Output = Result<Commit, AppError>> + Send;
+
+    /// Free-form completion of `prompt` under `opts.system_prompt`, for answers
+    /// that aren't a commit (e.g. per-file summaries).
+    fn complete(
+        &self,
+        prompt: &str,
+        opts: &GenOptions,
+    ) -> impl Future<Output = Result<String, AppError>> + Send;
+
+    /// Tokens used by the requests made so far, for backends that report it.
+    fn usage(&self) -> Usage {
+        Usage::default()
+    }
+
+    /// Whether the commit for `opts` comes back as JSON in the reply, rather
+    /// than as a call to the `commit` tool.
+    fn replies_with_json(&self, _opts: &GenOptions) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    OpenAi,
+    Anthropic,
+    /// GitHub Models, through its OpenAI-compatible API.
+    GitHub,
+}
+
+impl Provider {
+    /// Reads `AUTO_COMMIT_PROVIDER`, defaulting to OpenAI.
+    pub fn from_env() -> Result<Self, AppError> {
+        match std::env::var("AUTO_COMMIT_PROVIDER") {
+            Err(_) => Ok(Provider::OpenAi),
+            Ok(name) => Provider::parse(&name),
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, AppError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "openai" => Ok(Provider::OpenAi),
+            "anthropic" | "claude" => Ok(Provider::Anthropic),
+            "github" => Ok(Provider::GitHub),
+            other => Err(AppError::Config(format!(
+                "Unknown AUTO_COMMIT_PROVIDER `{}`, expected `openai`, `anthropic` or `github`.",
+                other
+            ))),
+        }
+    }
+
+    pub fn api_key_var(self) -> &'static str {
+        match self {
+            Provider::OpenAi => "OPENAI_API_KEY",
+            Provider::Anthropic => "ANTHROPIC_API_KEY",
+            Provider::GitHub => "GITHUB_TOKEN",
+        }
+    }
+
+    /// Variable holding several comma-separated keys to fail over between.
+    pub fn api_keys_var(self) -> &'static str {
+        match self {
+            Provider::OpenAi => "OPENAI_API_KEYS",
+            Provider::Anthropic => "ANTHROPIC_API_KEYS",
+            Provider::GitHub => "GITHUB_TOKENS",
+        }
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            Provider::OpenAi => DEFAULT_MODEL,
+            Provider::Anthropic => anthropic::DEFAULT_MODEL,
+            Provider::GitHub => openai::GITHUB_DEFAULT_MODEL,
+        }
+    }
+
+    /// Where an OpenAI-compatible provider sends its requests, or `None` for the
+    /// others.
+    pub fn openai_endpoint(self) -> Result<Option<OpenAiEndpoint>, AppError> {
+        match self {
+            Provider::OpenAi => OpenAiEndpoint::from_env().map(Some),
+            Provider::GitHub => Ok(Some(OpenAiEndpoint::github(
+                std::env::var("AUTO_COMMIT_GITHUB_BASE_URL").ok(),
+            ))),
+            Provider::Anthropic => Ok(None),
+        }
+    }
+
+    /// `AUTO_COMMIT_MODEL`, or this provider's default model.
+    pub fn model_from_env(self) -> String {
+        std::env::var("AUTO_COMMIT_MODEL").unwrap_or_else(|_| self.default_model().to_string())
+    }
+
+    /// The messages this provider sends for `context`, as pretty-printed JSON.
+    pub fn prompt_json(self, context: &str, opts: &GenOptions) -> Result<String, AppError> {
+        let messages = match self {
+            Provider::OpenAi | Provider::GitHub => {
+                let messages = if opts.no_tools {
+                    openai::build_messages_plain(context, &opts.system_prompt)?
+                } else {
+                    openai::build_messages_tools(context, &opts.system_prompt)?
+                };
+                serde_json::to_value(messages).map_err(|e| AppError::Api(e.to_string()))?
+            }
+            Provider::Anthropic => {
+                let request = anthropic::build_request(context, opts);
+                serde_json::json!({
+                    "system": request["system"],
+                    "messages": request["messages"],
+                })
+            }
+        };
+        serde_json::to_string_pretty(&messages).map_err(|e| AppError::Api(e.to_string()))
+    }
+}
+
+/// The backend selected at runtime.
+pub enum Generator {
+    OpenAi(OpenAiGenerator),
+    Anthropic(AnthropicGenerator),
+    /// Several OpenAI keys from `OPENAI_API_KEYS`.
+    OpenAiKeys(Failover<OpenAiGenerator>),
+    /// Several Anthropic keys from `ANTHROPIC_API_KEYS`.
+    AnthropicKeys(Failover<AnthropicGenerator>),
+}
+
+impl Generator {
+    /// Builds the backend for `provider`, reading its API keys from the
+    /// environment: the comma-separated `api_keys_var` if set, else `api_key_var`.
+    pub fn from_env(provider: Provider) -> Result<Self, AppError> {
+        let keys = std::env::var(provider.api_keys_var())
+            .map(|keys| parse_api_keys(&keys))
+            .unwrap_or_default();
+        let endpoint = provider.openai_endpoint()?;
+        if keys.len() > 1 {
+            return Ok(match endpoint {
+                Some(endpoint) => Generator::OpenAiKeys(Failover::new(
+                    keys.into_iter()
+                        .map(|key| {
+                            OpenAiGenerator::with_endpoint(key, endpoint.clone()).without_backoff()
+                        })
+                        .collect(),
+                )),
+                None => Generator::AnthropicKeys(Failover::new(
+                    keys.into_iter().map(AnthropicGenerator::new).collect(),
+                )),
+            });
+        }
+
+        let api_key = keys
+            .into_iter()
+            .next()
+            .or_else(|| std::env::var(provider.api_key_var()).ok())
+            .ok_or_else(|| {
+                AppError::Config(format!(
+                    "Please set the {} environment variable.",
+                    provider.api_key_var()
+                ))
+            })?;
+
+        Ok(match endpoint {
+            Some(endpoint) => Generator::OpenAi(OpenAiGenerator::with_endpoint(api_key, endpoint)),
+            None => Generator::Anthropic(AnthropicGenerator::new(api_key)),
+        })
+    }
+}
+
+impl Generator {
+    /// IDs of the models the backend offers.
+    pub async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        match self {
+            Generator::OpenAi(generator) => generator.list_models().await,
+            Generator::Anthropic(generator) => generator.list_models().await,
+            Generator::OpenAiKeys(keys) => keys.generators()[0].list_models().await,
+            Generator::AnthropicKeys(keys) => keys.generators()[0].list_models().await,
+        }
+    }
+}
+
+/// Substrings of OpenAI model IDs that can't do chat completions.
+const NON_CHAT_MODELS: &[&str] = &[
+    "embedding",
+    "whisper",
+    "tts",
+    "dall-e",
+    "davinci",
+    "babbage",
+    "moderation",
+    "transcribe",
+    "image",
+    "audio",
+    "realtime",
+    "search",
+];
+
+/// The chat-capable model IDs in `ids`, sorted, one per line.
+pub fn format_model_list(mut ids: Vec<String>) -> String {
+    ids.retain(|id| !NON_CHAT_MODELS.iter().any(|name| id.contains(name)));
+    ids.sort();
+    ids.dedup();
+    ids.join("\n")
+}
+
+impl CommitGenerator for Generator {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        match self {
+            Generator::OpenAi(generator) => generator.generate(context, opts).await,
+            Generator::Anthropic(generator) => generator.generate(context, opts).await,
+            Generator::OpenAiKeys(keys) => keys.generate(context, opts).await,
+            Generator::AnthropicKeys(keys) => keys.generate(context, opts).await,
+        }
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        match self {
+            Generator::OpenAi(generator) => generator.complete(prompt, opts).await,
+            Generator::Anthropic(generator) => generator.complete(prompt, opts).await,
+            Generator::OpenAiKeys(keys) => keys.complete(prompt, opts).await,
+            Generator::AnthropicKeys(keys) => keys.complete(prompt, opts).await,
+        }
+    }
+
+    fn usage(&self) -> Usage {
+        match self {
+            Generator::OpenAi(generator) => generator.usage(),
+            Generator::Anthropic(generator) => generator.usage(),
+            Generator::OpenAiKeys(keys) => keys.usage(),
+            Generator::AnthropicKeys(keys) => keys.usage(),
+        }
+    }
+
+    fn replies_with_json(&self, opts: &GenOptions) -> bool {
+        match self {
+            Generator::OpenAi(generator) => generator.replies_with_json(opts),
+            Generator::Anthropic(generator) => generator.replies_with_json(opts),
+            Generator::OpenAiKeys(keys) => keys.replies_with_json(opts),
+            Generator::AnthropicKeys(keys) => keys.replies_with_json(opts),
+        }
+    }
+}
+
+/// Appended to the system prompt when the first answer wasn't a valid commit
+/// and the commit is asked for as a `commit` tool call.
+pub const STRICT_COMMIT_PROMPT: &str =
+    "\n\nYou must call the `commit` tool exactly once, with a JSON \
+     object containing exactly two non-empty string fields: `title` and `description`.";
+
+/// Appended instead of `STRICT_COMMIT_PROMPT` when no tool was offered, i.e.
+/// for structured outputs and `--no-tools`.
+pub const STRICT_JSON_PROMPT: &str =
+    "\n\nReply with only a JSON object matching the schema, containing exactly \
+     two non-empty string fields: `title` and `description`.";
+
+/// Generates a commit, retrying once if the answer didn't parse as one (with
+/// `STRICT_COMMIT_PROMPT` or `STRICT_JSON_PROMPT`) or broke one of `rules` (with the broken rule). A
+/// retry that still breaks a rule is returned anyway, for the caller to fix up.
+pub async fn generate_checked(
+    generator: &impl CommitGenerator,
+    context: &str,
+    opts: &GenOptions,
+    rules: &CommitRules,
+) -> Result<Commit, AppError> {
+    let feedback = match generator.generate(context, opts).await {
+        Ok(commit) => match rules.check(&commit) {
+            Ok(()) => return Ok(commit),
+            Err(problem) => {
+                warn!("The generated commit was rejected ({}), retrying.", problem);
+                format!(
+                    "\n\nYour previous answer was rejected because {}. Fix that in this answer.",
+                    problem
+                )
+            }
+        },
+        Err(AppError::InvalidCommit(msg)) => {
+            warn!("The model returned an invalid commit ({}), retrying.", msg);
+            if generator.replies_with_json(opts) {
+                STRICT_JSON_PROMPT.to_string()
+            } else {
+                STRICT_COMMIT_PROMPT.to_string()
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    let retry = GenOptions {
+        system_prompt: format!("{}{}", opts.system_prompt, feedback),
+        ..opts.clone()
+    };
+    generator.generate(context, &retry).await
+}
+
+/// Wraps a generator so every request fails with `AppError::Timeout` after
+/// `timeout`. Rate limit retries inside it see the deadline and give up rather
+/// than sleep past it.
+pub struct WithTimeout<G> {
+    inner: G,
+    timeout: Duration,
+}
+
+impl<G> WithTimeout<G> {
+    pub fn new(inner: G, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl<G: CommitGenerator + Sync> CommitGenerator for WithTimeout<G> {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        tokio::time::timeout_at(
+            deadline,
+            with_deadline(deadline, self.inner.generate(context, opts)),
+        )
+        .await
+        .map_err(|_| AppError::Timeout(self.timeout))?
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        tokio::time::timeout_at(
+            deadline,
+            with_deadline(deadline, self.inner.complete(prompt, opts)),
+        )
+        .await
+        .map_err(|_| AppError::Timeout(self.timeout))?
+    }
+
+    fn usage(&self) -> Usage {
+        self.inner.usage()
+    }
+
+    fn replies_with_json(&self, opts: &GenOptions) -> bool {
+        self.inner.replies_with_json(opts)
+    }
+}
+
+/// `AUTO_COMMIT_FALLBACK_MODEL`, the model to retry with when the configured
+/// one is unavailable.
+pub fn fallback_model_from_env() -> Option<String> {
+    std::env::var("AUTO_COMMIT_FALLBACK_MODEL")
+        .ok()
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+}
+
+/// Whether `error` says the requested model doesn't exist, isn't available to
+/// the key, or was retired, so another model might still work.
+pub fn is_model_unavailable(error: &AppError) -> bool {
+    let AppError::Api(msg) = error else {
+        return false;
+    };
+    let msg = msg.to_lowercase();
+    msg.contains("model_not_found")
+        || (msg.contains("model")
+            && [
+                "does not exist",
+                "not_found",
+                "not found",
+                "deprecated",
+                "decommissioned",
+            ]
+            .iter()
+            .any(|hint| msg.contains(hint)))
+}
+
+/// Wraps a generator so a request for an unavailable model is retried once
+/// with `fallback`, if there is one.
+pub struct WithFallbackModel<G> {
+    inner: G,
+    fallback: Option<String>,
+}
+
+impl<G> WithFallbackModel<G> {
+    pub fn new(inner: G, fallback: Option<String>) -> Self {
+        Self { inner, fallback }
+    }
+
+    /// `opts` switched to the fallback model if `result` failed because the
+    /// model is unavailable, and the fallback is a different one.
+    fn fallback_opts<T>(
+        &self,
+        result: &Result<T, AppError>,
+        opts: &GenOptions,
+    ) -> Option<GenOptions> {
+        let fallback = self
+            .fallback
+            .as_ref()
+            .filter(|model| **model != opts.model)?;
+        match result {
+            Err(e) if is_model_unavailable(e) => {
+                warn!(
+                    "The model {} is unavailable ({}), using {} instead.",
+                    opts.model, e, fallback
+                );
+                Some(GenOptions {
+                    model: fallback.clone(),
+                    ..opts.clone()
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<G: CommitGenerator + Sync> CommitGenerator for WithFallbackModel<G> {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        let result = self.inner.generate(context, opts).await;
+        match self.fallback_opts(&result, opts) {
+            Some(opts) => self.inner.generate(context, &opts).await,
+            None => result,
+        }
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        let result = self.inner.complete(prompt, opts).await;
+        match self.fallback_opts(&result, opts) {
+            Some(opts) => self.inner.complete(prompt, &opts).await,
+            None => result,
+        }
+    }
+
+    fn usage(&self) -> Usage {
+        self.inner.usage()
+    }
+
+    fn replies_with_json(&self, opts: &GenOptions) -> bool {
+        self.inner.replies_with_json(opts)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod stub {
+    use super::*;
+    use std::{collections::VecDeque, sync::Mutex};
+
+    /// Test double that records every call. `generate` returns the queued commits in
+    /// order, then a commit echoing the context; `complete` summarizes the prompt's
+    /// first line.
+    #[derive(Default)]
+    pub(crate) struct StubGenerator {
+        commits: Mutex<VecDeque<Result<Commit, AppError>>>,
+        generated: Mutex<Vec<(String, GenOptions)>>,
+        completed: Mutex<Vec<(String, GenOptions)>>,
+    }
+
+    impl StubGenerator {
+        /// A stub whose first `generate` calls return `commits`.
+        pub(crate) fn with_commits(commits: Vec<Result<Commit, AppError>>) -> Self {
+            Self {
+                commits: Mutex::new(commits.into()),
+                ..Self::default()
+            }
+        }
+
+        pub(crate) fn contexts(&self) -> Vec<String> {
+            let generated = self.generated.lock().unwrap();
+            generated
+                .iter()
+                .map(|(context, _)| context.clone())
+                .collect()
+        }
+
+        pub(crate) fn generations(&self) -> Vec<(String, GenOptions)> {
+            self.generated.lock().unwrap().clone()
+        }
+
+        pub(crate) fn completions(&self) -> Vec<(String, GenOptions)> {
+            self.completed.lock().unwrap().clone()
+        }
+    }
+
+    impl CommitGenerator for StubGenerator {
+        async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+            let mut generated = self.generated.lock().unwrap();
+            generated.push((context.to_string(), opts.clone()));
+            self.commits.lock().unwrap().pop_front().unwrap_or_else(|| {
+                Ok(Commit {
+                    title: "Stub commit".to_string(),
+                    description: context.to_string(),
+                })
+            })
+        }
+
+        async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+            let mut completed = self.completed.lock().unwrap();
+            completed.push((prompt.to_string(), opts.clone()));
+            Ok(format!(
+                "Summary of {}",
+                prompt.lines().next().unwrap_or("")
+            ))
+        }
+
+        fn replies_with_json(&self, opts: &GenOptions) -> bool {
+            opts.no_tools
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    struct SlowGenerator;
+
+    impl CommitGenerator for SlowGenerator {
+        async fn generate(&self, _: &str, _: &GenOptions) -> Result<Commit, AppError> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            unreachable!()
+        }
+
+        async fn complete(&self, _: &str, _: &GenOptions) -> Result<String, AppError> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            unreachable!()
+        }
+    }
+
+    fn opts() -> GenOptions {
+        GenOptions {
+            model: "test-model".to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn with_timeout_gives_up_on_slow_requests() {
+        let generator = WithTimeout::new(SlowGenerator, Duration::from_millis(10));
+        assert_eq!(
+            generator.generate("diff", &opts()).await,
+            Err(AppError::Timeout(Duration::from_millis(10)))
+        );
+        assert_eq!(
+            generator.complete("prompt", &opts()).await,
+            Err(AppError::Timeout(Duration::from_millis(10)))
+        );
+    }
+
+    /// Reports the time left before the deadline its requests run under.
+    struct DeadlineGenerator;
+
+    impl CommitGenerator for DeadlineGenerator {
+        async fn generate(&self, _: &str, _: &GenOptions) -> Result<Commit, AppError> {
+            unreachable!()
+        }
+
+        async fn complete(&self, _: &str, _: &GenOptions) -> Result<String, AppError> {
+            Ok(format!(
+                "{:?}",
+                retry::time_left().map(|left| left.as_secs())
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn with_timeout_shares_its_deadline_with_retries() {
+        let generator = WithTimeout::new(DeadlineGenerator, Duration::from_secs(30));
+        let left = generator.complete("prompt", &opts()).await.unwrap();
+        assert!(left == "Some(29)" || left == "Some(30)", "{}", left);
+        assert_eq!(
+            DeadlineGenerator.complete("prompt", &opts()).await.unwrap(),
+            "None"
+        );
+    }
+
+    #[test]
+    fn is_model_unavailable_matches_missing_and_retired_models() {
+        for msg in [
+            "The model `gpt-5o` does not exist or you do not have access to it.",
+            "invalid_request_error: model_not_found",
+            r#"{"type":"not_found_error","message":"model: claude-3-opus-x"}"#,
+            "The model `text-davinci-003` has been deprecated.",
+            "The model `mixtral-8x7b-32768` has been decommissioned and is no longer supported.",
+        ] {
+            assert!(
+                is_model_unavailable(&AppError::Api(msg.to_string())),
+                "{}",
+                msg
+            );
+        }
+    }
+
+    #[test]
+    fn is_model_unavailable_ignores_other_errors() {
+        for msg in [
+            "429: You exceeded your current quota",
+            "500 Internal Server Error",
+            "404 page not found",
+            "Incorrect API key provided",
+        ] {
+            assert!(
+                !is_model_unavailable(&AppError::Api(msg.to_string())),
+                "{}",
+                msg
+            );
+        }
+        assert!(!is_model_unavailable(&AppError::Config(
+            "model not found".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn with_fallback_model_retries_with_the_fallback() {
+        let generator = WithFallbackModel::new(
+            stub::StubGenerator::with_commits(vec![Err(AppError::Api(
+                "The model `gpt-5o` does not exist".to_string(),
+            ))]),
+            Some("gpt-4.1-mini".to_string()),
+        );
+        let commit = generator.generate("diff", &opts()).await.unwrap();
+        assert_eq!(commit.title, "Stub commit");
+        let models: Vec<String> = generator
+            .inner
+            .generations()
+            .into_iter()
+            .map(|(_, opts)| opts.model)
+            .collect();
+        assert_eq!(models, ["test-model", "gpt-4.1-mini"]);
+    }
+
+    #[tokio::test]
+    async fn with_fallback_model_keeps_other_errors() {
+        let unavailable = || Err(AppError::Api("model_not_found".to_string()));
+        let generator = WithFallbackModel::new(
+            stub::StubGenerator::with_commits(vec![Err(AppError::Api("500".to_string()))]),
+            Some("gpt-4.1-mini".to_string()),
+        );
+        assert_eq!(
+            generator.generate("diff", &opts()).await,
+            Err(AppError::Api("500".to_string()))
+        );
+
+        // Nothing to fall back to when the fallback is the model that failed
+        let generator = WithFallbackModel::new(
+            stub::StubGenerator::with_commits(vec![unavailable()]),
+            Some("test-model".to_string()),
+        );
+        assert_eq!(generator.generate("diff", &opts()).await, unavailable());
+        assert_eq!(generator.inner.generations().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn with_timeout_passes_through_fast_requests() {
+        let generator = WithTimeout::new(stub::StubGenerator::default(), Duration::from_secs(5));
+        assert_eq!(
+            generator
+                .generate("diff", &opts())
+                .await
+                .unwrap()
+                .description,
+            "diff"
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_checked_retries_invalid_commits_with_a_stricter_prompt() {
+        let generator = stub::StubGenerator::with_commits(vec![Err(AppError::InvalidCommit(
+            "missing field `description`".to_string(),
+        ))]);
+        let commit = generate_checked(&generator, "diff", &opts(), &CommitRules::default())
+            .await
+            .unwrap();
+
+        assert_eq!(commit.title, "Stub commit");
+        let calls = generator.generations();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].1.system_prompt, "Write great commits.");
+        assert!(calls[1].1.system_prompt.ends_with(STRICT_COMMIT_PROMPT));
+    }
+
+    #[tokio::test]
+    async fn generate_checked_asks_for_json_when_no_tool_was_offered() {
+        let generator = stub::StubGenerator::with_commits(vec![Err(AppError::InvalidCommit(
+            "expected value at line 1 column 1".to_string(),
+        ))]);
+        let opts = GenOptions {
+            no_tools: true,
+            ..opts()
+        };
+        generate_checked(&generator, "diff", &opts, &CommitRules::default())
+            .await
+            .unwrap();
+
+        let calls = generator.generations();
+        assert!(calls[1].1.system_prompt.ends_with(STRICT_JSON_PROMPT));
+        assert!(!calls[1].1.system_prompt.contains("`commit` tool"));
+    }
+
+    #[tokio::test]
+    async fn generate_checked_gives_up_after_one_retry() {
+        let invalid = || Err(AppError::InvalidCommit("no tool call".to_string()));
+        let generator = stub::StubGenerator::with_commits(vec![invalid(), invalid()]);
+        assert_eq!(
+            generate_checked(&generator, "diff", &opts(), &CommitRules::default()).await,
+            invalid()
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_checked_retries_commits_that_break_a_rule() {
+        let long = Commit {
+            title: "Rework the session store so that it can be shared between the workers"
+                .to_string(),
+            description: "Details".to_string(),
+        };
+        let short = Commit {
+            title: "Share the session store".to_string(),
+            description: "Details".to_string(),
+        };
+        let generator = stub::StubGenerator::with_commits(vec![Ok(long), Ok(short.clone())]);
+        let rules = CommitRules {
+            max_title_length: Some(50),
+            ..CommitRules::default()
+        };
+        let commit = generate_checked(&generator, "diff", &opts(), &rules)
+            .await
+            .unwrap();
+
+        assert_eq!(commit, short);
+        let calls = generator.generations();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[1]
+            .1
+            .system_prompt
+            .contains("rejected because the title is 69 characters long, over the limit of 50"));
+    }
+
+    #[tokio::test]
+    async fn generate_checked_keeps_a_retry_that_still_breaks_a_rule() {
+        let unscoped = || {
+            Ok(Commit {
+                title: "Fix the login redirect".to_string(),
+                description: "Details".to_string(),
+            })
+        };
+        let generator = stub::StubGenerator::with_commits(vec![unscoped(), unscoped()]);
+        let rules = CommitRules {
+            scope: Some("auth".to_string()),
+            ..CommitRules::default()
+        };
+        assert_eq!(
+            generate_checked(&generator, "diff", &opts(), &rules).await,
+            unscoped()
+        );
+        assert_eq!(generator.generations().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn generate_checked_does_not_retry_other_errors() {
+        let generator =
+            stub::StubGenerator::with_commits(vec![Err(AppError::Api("503".to_string()))]);
+        assert!(
+            generate_checked(&generator, "diff", &opts(), &CommitRules::default())
+                .await
+                .is_err()
+        );
+        assert_eq!(generator.generations().len(), 1);
+    }
+
+    #[test]
+    fn format_model_list_keeps_chat_models_sorted() {
+        let ids = [
+            "gpt-4o",
+            "text-embedding-3-small",
+            "gpt-4.1-nano",
+            "whisper-1",
+            "dall-e-3",
+            "gpt-4o-mini-tts",
+            "claude-3-5-haiku-latest",
+            "gpt-4o",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            format_model_list(ids),
+            "claude-3-5-haiku-latest\ngpt-4.1-nano\ngpt-4o"
+        );
+        assert_eq!(format_model_list(vec![]), "");
+    }
+
+    #[test]
+    fn provider_parse_known_names() {
+        assert_eq!(Provider::parse("openai"), Ok(Provider::OpenAi));
+        assert_eq!(Provider::parse("OpenAI"), Ok(Provider::OpenAi));
+        assert_eq!(Provider::parse(""), Ok(Provider::OpenAi));
+        assert_eq!(Provider::parse("anthropic"), Ok(Provider::Anthropic));
+        assert_eq!(Provider::parse("claude"), Ok(Provider::Anthropic));
+        assert_eq!(Provider::parse("GitHub"), Ok(Provider::GitHub));
+    }
+
+    #[test]
+    fn provider_parse_rejects_unknown_names() {
+        assert!(matches!(Provider::parse("llama"), Err(AppError::Config(_))));
+    }
+
+    #[test]
+    fn provider_from_env_defaults_to_openai() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_PROVIDER");
+        assert_eq!(Provider::from_env(), Ok(Provider::OpenAi));
+
+        std::env::set_var("AUTO_COMMIT_PROVIDER", "anthropic");
+        assert_eq!(Provider::from_env(), Ok(Provider::Anthropic));
+        std::env::remove_var("AUTO_COMMIT_PROVIDER");
+    }
+
+    #[test]
+    fn provider_model_from_env_uses_provider_default() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_MODEL");
+        assert_eq!(Provider::OpenAi.model_from_env(), DEFAULT_MODEL);
+        assert_eq!(
+            Provider::Anthropic.model_from_env(),
+            anthropic::DEFAULT_MODEL
+        );
+
+        std::env::set_var("AUTO_COMMIT_MODEL", "custom-model");
+        assert_eq!(Provider::Anthropic.model_from_env(), "custom-model");
+        std::env::remove_var("AUTO_COMMIT_MODEL");
+    }
+
+    #[test]
+    fn prompt_json_contains_system_prompt_and_context() {
+        for provider in [Provider::OpenAi, Provider::Anthropic] {
+            let json = provider.prompt_json("diff --git a/a b/a", &opts()).unwrap();
+            assert!(json.contains("Write great commits."), "{}", json);
+            assert!(json.contains("diff --git a/a b/a"), "{}", json);
+        }
+    }
+
+    #[test]
+    fn github_provider_uses_github_token_and_models_endpoint() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_GITHUB_BASE_URL");
+        assert_eq!(Provider::GitHub.api_key_var(), "GITHUB_TOKEN");
+        assert_eq!(
+            Provider::GitHub.openai_endpoint(),
+            Ok(Some(OpenAiEndpoint::github(None)))
+        );
+        assert_eq!(Provider::Anthropic.openai_endpoint(), Ok(None));
+
+        std::env::remove_var("GITHUB_TOKENS");
+        std::env::remove_var("GITHUB_TOKEN");
+        match Generator::from_env(Provider::GitHub) {
+            Err(AppError::Config(msg)) => assert!(msg.contains("GITHUB_TOKEN")),
+            _ => panic!("expected a config error"),
+        }
+        std::env::set_var("GITHUB_TOKEN", "ghp_test");
+        assert!(matches!(
+            Generator::from_env(Provider::GitHub),
+            Ok(Generator::OpenAi(_))
+        ));
+        std::env::remove_var("GITHUB_TOKEN");
+    }
+
+    #[test]
+    fn generator_from_env_requires_api_key() {
+        let _env = env_lock();
+        std::env::remove_var("ANTHROPIC_API_KEY");
+        match Generator::from_env(Provider::Anthropic) {
+            Err(AppError::Config(msg)) => assert!(msg.contains("ANTHROPIC_API_KEY")),
+            _ => panic!("expected a config error"),
+        }
+
+        std::env::set_var("ANTHROPIC_API_KEY", "sk-ant-test");
+        assert!(matches!(
+            Generator::from_env(Provider::Anthropic),
+            Ok(Generator::Anthropic(_))
+        ));
+        std::env::remove_var("ANTHROPIC_API_KEY");
+    }
+}
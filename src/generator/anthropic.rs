@@ -0,0 +1,351 @@
+use super::{
+    retry::{parse_retry_after, retry_delay, retry_fits, time_left, MAX_RATE_LIMIT_RETRIES},
+    CommitGenerator, GenOptions,
+};
+use crate::{
+    cost::{Usage, UsageMeter},
+    error::AppError,
+    message::{commit_schema, parse_commit, Commit},
+};
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub const DEFAULT_MODEL: &str = "claude-3-5-haiku-latest";
+
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+const MODELS_URL: &str = "https://api.anthropic.com/v1/models";
+const API_VERSION: &str = "2023-06-01";
+
+pub struct AnthropicGenerator {
+    client: reqwest::Client,
+    api_key: String,
+    usage: UsageMeter,
+}
+
+impl AnthropicGenerator {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key,
+            usage: UsageMeter::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelInfo {
+    id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct UsageResponse {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageBody {
+    #[serde(default)]
+    usage: UsageResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl AnthropicGenerator {
+    /// IDs of the models the API key can use.
+    pub async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        let response = self
+            .client
+            .get(MODELS_URL)
+            .query(&[("limit", "1000")])
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .send()
+            .await
+            .map_err(|e| AppError::Api(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| AppError::Api(e.to_string()))?;
+        if !status.is_success() {
+            return Err(AppError::Api(format!("{}: {}", status, body)));
+        }
+        parse_models_response(&body)
+    }
+
+    /// Posts `request`, waiting and retrying when rate limited for as long as
+    /// the `Retry-After` header asks, unless the wait would outlast the timeout.
+    async fn send(&self, request: &Value) -> Result<String, AppError> {
+        let mut attempt = 0;
+        let response = loop {
+            let response = self
+                .client
+                .post(API_URL)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", API_VERSION)
+                .json(request)
+                .send()
+                .await
+                .map_err(|e| AppError::Api(e.to_string()))?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt == MAX_RATE_LIMIT_RETRIES
+            {
+                break response;
+            }
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            let delay = retry_delay(attempt, retry_after);
+            if !retry_fits(delay, time_left()) {
+                debug!(
+                    "Not waiting {}s for the rate limit; it's past the timeout.",
+                    delay.as_secs_f32()
+                );
+                break response;
+            }
+            warn!("Rate limited, retrying in {}s.", delay.as_secs_f32());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| AppError::Api(e.to_string()))?;
+        if !status.is_success() {
+            return Err(AppError::Api(format!("{}: {}", status, body)));
+        }
+        self.usage.record(parse_usage(&body));
+        Ok(body)
+    }
+}
+
+impl CommitGenerator for AnthropicGenerator {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        parse_response(&self.send(&build_request(context, opts)).await?)
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        let request = json!({
+            "model": opts.model,
+            "max_tokens": opts.max_tokens,
+            "temperature": 0.0,
+            "system": opts.system_prompt,
+            "messages": [{ "role": "user", "content": prompt }],
+        });
+        parse_text_response(&self.send(&request).await?)
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage.total()
+    }
+}
+
+/// Builds a messages API request that forces the `commit` tool.
+pub fn build_request(context: &str, opts: &GenOptions) -> Value {
+    json!({
+        "model": opts.model,
+        "max_tokens": opts.max_tokens,
+        "temperature": 0.0,
+        "system": opts.system_prompt,
+        "messages": [{
+            "role": "user",
+            "content": format!("Here is the output of `git diff HEAD`:\n\n{}", context),
+        }],
+        "tools": [{
+            "name": "commit",
+            "description": "Creates a commit with the given title and a description.",
+            "input_schema": commit_schema(),
+        }],
+        "tool_choice": { "type": "tool", "name": "commit" },
+    })
+}
+
+/// Extracts the `Commit` from the first `commit` tool use in a messages API response.
+pub fn parse_response(body: &str) -> Result<Commit, AppError> {
+    let response: MessagesResponse = serde_json::from_str(body)
+        .map_err(|e| AppError::Api(format!("Unexpected response from Anthropic: {}", e)))?;
+
+    let input = response
+        .content
+        .into_iter()
+        .find_map(|block| match block {
+            ContentBlock::ToolUse { name, input } if name == "commit" => Some(input),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            AppError::InvalidCommit("the model didn't call the commit tool".to_string())
+        })?;
+
+    let arguments = input.to_string();
+    parse_commit(&arguments).map_err(|e| {
+        debug!("Invalid commit arguments: {}", arguments);
+        AppError::InvalidCommit(e.to_string())
+    })
+}
+
+/// The tokens a messages API response says it used, or none if it doesn't say.
+pub fn parse_usage(body: &str) -> Usage {
+    let usage = serde_json::from_str::<UsageBody>(body)
+        .map(|body| body.usage)
+        .unwrap_or_default();
+    Usage {
+        prompt_tokens: usage.input_tokens,
+        completion_tokens: usage.output_tokens,
+    }
+}
+
+/// Model IDs from a models API response.
+pub fn parse_models_response(body: &str) -> Result<Vec<String>, AppError> {
+    let response: ModelsResponse = serde_json::from_str(body)
+        .map_err(|e| AppError::Api(format!("Unexpected response from Anthropic: {}", e)))?;
+    Ok(response.data.into_iter().map(|model| model.id).collect())
+}
+
+/// Concatenates the text blocks of a messages API response.
+pub fn parse_text_response(body: &str) -> Result<String, AppError> {
+    let response: MessagesResponse = serde_json::from_str(body)
+        .map_err(|e| AppError::Api(format!("Unexpected response from Anthropic: {}", e)))?;
+
+    let text: String = response
+        .content
+        .into_iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text),
+            _ => None,
+        })
+        .collect();
+    if text.trim().is_empty() {
+        return Err(AppError::Api(
+            "The model returned an empty response.".to_string(),
+        ));
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_request_forces_the_commit_tool() {
+        let opts = GenOptions {
+            model: DEFAULT_MODEL.to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        };
+        let request = build_request("diff --git a/a b/a", &opts);
+
+        assert_eq!(request["model"], DEFAULT_MODEL);
+        assert_eq!(request["system"], "Write great commits.");
+        assert_eq!(request["tool_choice"]["name"], "commit");
+        assert_eq!(request["tools"][0]["input_schema"]["type"], "object");
+        assert!(request["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .ends_with("diff --git a/a b/a"));
+    }
+
+    #[test]
+    fn parse_response_reads_the_tool_input() {
+        let body = r#"{
+            "content": [
+                { "type": "text", "text": "Sure." },
+                { "type": "tool_use", "id": "toolu_1", "name": "commit",
+                  "input": { "title": "Fix bug", "description": "Details" } }
+            ]
+        }"#;
+        assert_eq!(
+            parse_response(body).unwrap(),
+            Commit {
+                title: "Fix bug".to_string(),
+                description: "Details".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_text_response_joins_text_blocks() {
+        let body = r#"{ "content": [
+            { "type": "text", "text": "Adds a " },
+            { "type": "text", "text": "login page." }
+        ] }"#;
+        assert_eq!(parse_text_response(body).unwrap(), "Adds a login page.");
+        assert!(parse_text_response(r#"{ "content": [] }"#).is_err());
+    }
+
+    #[test]
+    fn parse_usage_reads_the_token_counts() {
+        let body = r#"{ "content": [], "usage": { "input_tokens": 1200, "output_tokens": 45 } }"#;
+        assert_eq!(
+            parse_usage(body),
+            Usage {
+                prompt_tokens: 1200,
+                completion_tokens: 45,
+            }
+        );
+        assert_eq!(parse_usage(r#"{ "content": [] }"#), Usage::default());
+    }
+
+    #[test]
+    fn parse_response_with_missing_fields_is_an_error() {
+        let body = r#"{ "content": [{ "type": "tool_use", "id": "toolu_1", "name": "commit",
+            "input": { "title": "Fix bug" } }] }"#;
+        assert!(matches!(
+            parse_response(body),
+            Err(AppError::InvalidCommit(_))
+        ));
+    }
+
+    #[test]
+    fn parse_models_response_reads_ids() {
+        let body = r#"{ "data": [
+            { "type": "model", "id": "claude-3-5-haiku-latest", "display_name": "Claude Haiku" },
+            { "type": "model", "id": "claude-sonnet-4-0" }
+        ], "has_more": false }"#;
+        assert_eq!(
+            parse_models_response(body).unwrap(),
+            ["claude-3-5-haiku-latest", "claude-sonnet-4-0"]
+        );
+    }
+
+    #[test]
+    fn parse_response_without_tool_use_is_an_error() {
+        let body = r#"{ "content": [{ "type": "text", "text": "No." }] }"#;
+        assert!(matches!(
+            parse_response(body),
+            Err(AppError::InvalidCommit(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use super::{CommitGenerator, GenOptions};
+use crate::{cost::Usage, error::AppError, message::Commit};
+use log::warn;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Hands out `items` round-robin, wrapping around after the last one.
+pub struct KeyRotator<T> {
+    items: Vec<T>,
+    position: AtomicUsize,
+}
+
+impl<T> KeyRotator<T> {
+    /// A rotator over `items`, which must not be empty.
+    pub fn new(items: Vec<T>) -> Self {
+        assert!(!items.is_empty(), "KeyRotator needs at least one item");
+        Self {
+            items,
+            position: AtomicUsize::new(0),
+        }
+    }
+
+    /// The next item in turn.
+    pub fn next(&self) -> &T {
+        let position = self.position.fetch_add(1, Ordering::Relaxed);
+        &self.items[position % self.items.len()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+}
+
+/// Comma-separated keys, e.g. from `OPENAI_API_KEYS`, with blanks dropped.
+pub fn parse_api_keys(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `error` means the key is rate limited or out of quota, so another
+/// key might still work.
+pub fn is_quota_error(error: &AppError) -> bool {
+    let AppError::Api(msg) = error else {
+        return false;
+    };
+    let msg = msg.to_lowercase();
+    ["429", "quota", "rate limit", "rate_limit"]
+        .iter()
+        .any(|hint| msg.contains(hint))
+}
+
+/// One generator per API key, used round-robin. A request that hits a key's
+/// quota or rate limit moves on to the next key until every key was tried.
+pub struct Failover<G> {
+    generators: KeyRotator<G>,
+}
+
+impl<G: CommitGenerator + Sync> Failover<G> {
+    pub fn new(generators: Vec<G>) -> Self {
+        Self {
+            generators: KeyRotator::new(generators),
+        }
+    }
+
+    pub fn generators(&self) -> &[G] {
+        self.generators.items()
+    }
+
+    async fn with_failover<'a, T, F>(&'a self, request: impl Fn(&'a G) -> F) -> Result<T, AppError>
+    where
+        F: Future<Output = Result<T, AppError>>,
+    {
+        let mut last_error = None;
+        for _ in 0..self.generators.len() {
+            match request(self.generators.next()).await {
+                Err(e) if is_quota_error(&e) => {
+                    warn!("An API key hit its quota or rate limit, trying the next one.");
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+        Err(AppError::Api(format!(
+            "All {} API keys are rate limited or out of quota; last error: {}",
+            self.generators.len(),
+            last_error.map_or_else(String::new, |e| e.to_string())
+        )))
+    }
+}
+
+impl<G: CommitGenerator + Sync> CommitGenerator for Failover<G> {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        self.with_failover(|generator| generator.generate(context, opts))
+            .await
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        self.with_failover(|generator| generator.complete(prompt, opts))
+            .await
+    }
+
+    fn usage(&self) -> Usage {
+        self.generators()
+            .iter()
+            .map(CommitGenerator::usage)
+            .fold(Usage::default(), |total, usage| total + usage)
+    }
+
+    fn replies_with_json(&self, opts: &GenOptions) -> bool {
+        self.generators()
+            .first()
+            .is_some_and(|generator| generator.replies_with_json(opts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::stub::StubGenerator;
+
+    fn opts() -> GenOptions {
+        GenOptions {
+            model: "gpt-4.1-nano".to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        }
+    }
+
+    fn quota() -> Result<Commit, AppError> {
+        Err(AppError::Api(
+            "429: You exceeded your current quota".to_string(),
+        ))
+    }
+
+    #[test]
+    fn key_rotator_cycles_through_the_keys() {
+        let rotator = KeyRotator::new(vec!["a", "b", "c"]);
+        let keys: Vec<_> = (0..7).map(|_| *rotator.next()).collect();
+        assert_eq!(keys, ["a", "b", "c", "a", "b", "c", "a"]);
+        assert_eq!(rotator.len(), 3);
+    }
+
+    #[test]
+    fn parse_api_keys_splits_on_commas() {
+        assert_eq!(
+            parse_api_keys("sk-1, sk-2,,sk-3 "),
+            ["sk-1", "sk-2", "sk-3"]
+        );
+        assert!(parse_api_keys(" , ").is_empty());
+    }
+
+    #[test]
+    fn is_quota_error_matches_rate_limits() {
+        assert!(is_quota_error(&AppError::Api(
+            "429 Too Many Requests".to_string()
+        )));
+        assert!(is_quota_error(&AppError::Api(
+            "insufficient_quota: check your plan".to_string()
+        )));
+        assert!(!is_quota_error(&AppError::Api(
+            "500 Internal Server Error".to_string()
+        )));
+        assert!(!is_quota_error(&AppError::Config("quota".to_string())));
+    }
+
+    #[tokio::test]
+    async fn failover_moves_to_the_next_key() {
+        let failover = Failover::new(vec![
+            StubGenerator::with_commits(vec![quota()]),
+            StubGenerator::default(),
+        ]);
+        let commit = failover.generate("diff", &opts()).await.unwrap();
+        assert_eq!(commit.title, "Stub commit");
+        assert_eq!(failover.generators()[0].contexts(), ["diff"]);
+        assert_eq!(failover.generators()[1].contexts(), ["diff"]);
+    }
+
+    #[tokio::test]
+    async fn failover_reports_when_every_key_is_exhausted() {
+        let failover = Failover::new(vec![
+            StubGenerator::with_commits(vec![quota()]),
+            StubGenerator::with_commits(vec![quota()]),
+        ]);
+        let err = failover.generate("diff", &opts()).await.unwrap_err();
+        assert!(
+            matches!(&err, AppError::Api(msg) if msg.starts_with("All 2 API keys are rate limited")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn failover_keeps_other_errors() {
+        let failover = Failover::new(vec![
+            StubGenerator::with_commits(vec![Err(AppError::Api("500".to_string()))]),
+            StubGenerator::default(),
+        ]);
+        assert_eq!(
+            failover.generate("diff", &opts()).await,
+            Err(AppError::Api("500".to_string()))
+        );
+        assert!(failover.generators()[1].contexts().is_empty());
+    }
+}
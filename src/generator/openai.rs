@@ -0,0 +1,862 @@
+use super::{CommitGenerator, GenOptions};
+use crate::{
+    cost::{Usage, UsageMeter},
+    error::AppError,
+    message::{commit_schema, parse_commit, strip_code_fence, Commit},
+};
+use async_openai::{
+    config::{AzureConfig, OpenAIConfig},
+    error::OpenAIError,
+    types::{
+        ChatCompletionMessageToolCall,             // tool-call struct
+        ChatCompletionNamedToolChoice,             // tool-choice struct
+        ChatCompletionRequestAssistantMessageArgs, // assistant message builder
+        ChatCompletionRequestMessage,              // request message
+        ChatCompletionRequestSystemMessageArgs,    // system message builder
+        ChatCompletionRequestToolMessageArgs,      // tool response builder
+        ChatCompletionRequestUserMessageArgs,      // user message builder
+        ChatCompletionTool,                        // tool struct
+        ChatCompletionToolArgs,                    // tool builder
+        ChatCompletionToolChoiceOption,            // tool-choice enum
+        ChatCompletionToolType,                    // tool types
+        CreateChatCompletionRequest,               // request
+        CreateChatCompletionRequestArgs,           // request builder
+        CreateChatCompletionResponse,              // response
+        FinishReason,                              // why the model stopped
+        FunctionCall,                              // function-call struct
+        FunctionName,                              // function name for tool choice
+        FunctionObject,                            // function definition for tool
+        ResponseFormat,                            // structured output format
+        ResponseFormatJsonSchema,                  // structured output schema
+    },
+    Client,
+};
+use backoff::ExponentialBackoff;
+use log::{debug, warn};
+use serde_json::json;
+use std::time::Duration;
+
+/// Azure API version used when `AUTO_COMMIT_AZURE_API_VERSION` isn't set.
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// GitHub Models' OpenAI-compatible inference API, used with a `GITHUB_TOKEN`.
+pub const GITHUB_MODELS_BASE_URL: &str = "https://models.github.ai/inference";
+
+/// Default model for GitHub Models, which prefixes model IDs with their vendor.
+pub const GITHUB_DEFAULT_MODEL: &str = "openai/gpt-4.1-nano";
+
+/// The most `max_tokens` a cut-off answer is retried with.
+pub const MAX_BUMPED_RESPONSE_TOKENS: u32 = 8000;
+
+/// Models that take `response_format: json_schema`, matched by prefix.
+const STRUCTURED_OUTPUT_MODELS: &[&str] = &["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"];
+
+/// Snapshots of those families that predate structured outputs.
+const NO_STRUCTURED_OUTPUT_MODELS: &[&str] = &["gpt-4o-2024-05-13", "o1-mini", "o1-preview"];
+
+/// Whether `model` supports structured outputs; older models get the commit
+/// as a forced tool call instead.
+pub fn supports_structured_output(model: &str) -> bool {
+    STRUCTURED_OUTPUT_MODELS
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+        && !NO_STRUCTURED_OUTPUT_MODELS
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+}
+
+/// Where chat completions are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenAiEndpoint {
+    OpenAi,
+    /// An Azure OpenAI deployment; the deployment decides the model.
+    Azure {
+        endpoint: String,
+        deployment: String,
+        api_version: String,
+    },
+    /// Another OpenAI-compatible API, such as GitHub Models.
+    Custom {
+        base_url: String,
+    },
+}
+
+impl OpenAiEndpoint {
+    /// Azure when any `AUTO_COMMIT_AZURE_*` variable is set, else the API at
+    /// `AUTO_COMMIT_OPENAI_BASE_URL`, else OpenAI.
+    pub fn from_env() -> Result<Self, AppError> {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|v: &String| !v.trim().is_empty())
+        };
+        Ok(Self::from_vars(
+            var("AUTO_COMMIT_AZURE_ENDPOINT"),
+            var("AUTO_COMMIT_AZURE_DEPLOYMENT"),
+            var("AUTO_COMMIT_AZURE_API_VERSION"),
+        )?
+        .with_base_url(var("AUTO_COMMIT_OPENAI_BASE_URL")))
+    }
+
+    /// OpenAI's API moved to `base_url`, e.g. a proxy, a local server or a mock
+    /// in tests; other endpoints keep their own URL.
+    pub fn with_base_url(self, base_url: Option<String>) -> Self {
+        let base_url = base_url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+        match (self, base_url) {
+            (OpenAiEndpoint::OpenAi, Some(base_url)) => OpenAiEndpoint::Custom { base_url },
+            (endpoint, _) => endpoint,
+        }
+    }
+
+    /// GitHub Models at `base_url` (from `AUTO_COMMIT_GITHUB_BASE_URL`), or at
+    /// `GITHUB_MODELS_BASE_URL` when it isn't set.
+    pub fn github(base_url: Option<String>) -> Self {
+        let base_url = base_url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| GITHUB_MODELS_BASE_URL.to_string());
+        OpenAiEndpoint::Custom { base_url }
+    }
+
+    pub fn from_vars(
+        endpoint: Option<String>,
+        deployment: Option<String>,
+        api_version: Option<String>,
+    ) -> Result<Self, AppError> {
+        match (endpoint, deployment, api_version) {
+            (None, None, None) => Ok(OpenAiEndpoint::OpenAi),
+            (Some(endpoint), Some(deployment), api_version) => Ok(OpenAiEndpoint::Azure {
+                endpoint,
+                deployment,
+                api_version: api_version.unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+            }),
+            (None, _, _) => Err(AppError::Config(
+                "Please set AUTO_COMMIT_AZURE_ENDPOINT to use Azure OpenAI.".to_string(),
+            )),
+            (Some(_), None, _) => Err(AppError::Config(
+                "Please set AUTO_COMMIT_AZURE_DEPLOYMENT to use Azure OpenAI.".to_string(),
+            )),
+        }
+    }
+}
+
+/// `async-openai` clients are generic over their config, so keep one of each.
+enum OpenAiClient {
+    OpenAi(Client<OpenAIConfig>),
+    Azure(Client<AzureConfig>),
+}
+
+impl OpenAiClient {
+    fn new(api_key: String, endpoint: OpenAiEndpoint) -> Self {
+        match endpoint {
+            OpenAiEndpoint::OpenAi => OpenAiClient::OpenAi(Client::with_config(
+                OpenAIConfig::new().with_api_key(api_key),
+            )),
+            OpenAiEndpoint::Azure {
+                endpoint,
+                deployment,
+                api_version,
+            } => OpenAiClient::Azure(Client::with_config(
+                AzureConfig::new()
+                    .with_api_base(endpoint)
+                    .with_deployment_id(deployment)
+                    .with_api_version(api_version)
+                    .with_api_key(api_key),
+            )),
+            OpenAiEndpoint::Custom { base_url } => OpenAiClient::OpenAi(Client::with_config(
+                OpenAIConfig::new()
+                    .with_api_base(base_url)
+                    .with_api_key(api_key),
+            )),
+        }
+    }
+
+    /// The client with `async-openai`'s retries turned off, so rate limit
+    /// errors are returned at once instead of backed off for up to 15 minutes.
+    fn without_backoff(self) -> Self {
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(Duration::ZERO),
+            ..ExponentialBackoff::default()
+        };
+        match self {
+            OpenAiClient::OpenAi(client) => OpenAiClient::OpenAi(client.with_backoff(backoff)),
+            OpenAiClient::Azure(client) => OpenAiClient::Azure(client.with_backoff(backoff)),
+        }
+    }
+
+    async fn create(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, OpenAIError> {
+        match self {
+            OpenAiClient::OpenAi(client) => client.chat().create(request).await,
+            OpenAiClient::Azure(client) => client.chat().create(request).await,
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, OpenAIError> {
+        let response = match self {
+            OpenAiClient::OpenAi(client) => client.models().list().await?,
+            OpenAiClient::Azure(client) => client.models().list().await?,
+        };
+        Ok(response.data.into_iter().map(|model| model.id).collect())
+    }
+}
+
+pub struct OpenAiGenerator {
+    client: OpenAiClient,
+    usage: UsageMeter,
+}
+
+impl OpenAiGenerator {
+    pub fn new(api_key: String) -> Self {
+        Self::with_endpoint(api_key, OpenAiEndpoint::OpenAi)
+    }
+
+    pub fn with_endpoint(api_key: String, endpoint: OpenAiEndpoint) -> Self {
+        Self {
+            client: OpenAiClient::new(api_key, endpoint),
+            usage: UsageMeter::default(),
+        }
+    }
+
+    /// Fails rate limited requests straight away rather than retrying them, for
+    /// a `Failover` that moves on to the next key instead.
+    pub fn without_backoff(self) -> Self {
+        Self {
+            client: self.client.without_backoff(),
+            ..self
+        }
+    }
+
+    /// Sends `request`, adding the tokens it used to the running total.
+    async fn create(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<CreateChatCompletionResponse, AppError> {
+        let completion = self
+            .client
+            .create(request)
+            .await
+            .map_err(|e| AppError::Api(e.to_string()))?;
+        if let Some(usage) = &completion.usage {
+            self.usage.record(Usage {
+                prompt_tokens: usage.prompt_tokens.into(),
+                completion_tokens: usage.completion_tokens.into(),
+            });
+        }
+        Ok(completion)
+    }
+
+    /// Sends the request `build` makes for `opts`, and if the answer was cut
+    /// off by `max_tokens`, once more with room for a longer one.
+    async fn create_with_room(
+        &self,
+        build: impl Fn(&GenOptions) -> Result<CreateChatCompletionRequest, AppError>,
+        opts: &GenOptions,
+    ) -> Result<CreateChatCompletionResponse, AppError> {
+        let completion = self.create(build(opts)?).await?;
+        let finish_reason = completion
+            .choices
+            .first()
+            .and_then(|choice| choice.finish_reason);
+        match bumped_max_tokens(finish_reason, opts.max_tokens) {
+            Some(max_tokens) => {
+                warn!(
+                    "The answer was cut off at {} tokens; retrying with {}. Pass --max-tokens to \
+                     set a higher limit.",
+                    opts.max_tokens, max_tokens
+                );
+                let opts = GenOptions {
+                    max_tokens,
+                    ..opts.clone()
+                };
+                self.create(build(&opts)?).await
+            }
+            None => Ok(completion),
+        }
+    }
+
+    /// Sends the request `build` makes and parses the commit from the message
+    /// content, for requests that don't get it back as a tool call.
+    async fn generate_from_content(
+        &self,
+        build: impl Fn(&GenOptions) -> Result<CreateChatCompletionRequest, AppError>,
+        opts: &GenOptions,
+    ) -> Result<Commit, AppError> {
+        let completion = self.create_with_room(build, opts).await?;
+
+        let content = completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AppError::InvalidCommit("the model returned no content".to_string()))?;
+
+        parse_commit(strip_code_fence(&content)).map_err(|e| {
+            debug!("Invalid commit content: {}", content);
+            AppError::InvalidCommit(e.to_string())
+        })
+    }
+
+    /// IDs of the models the API key can use.
+    pub async fn list_models(&self) -> Result<Vec<String>, AppError> {
+        self.client
+            .list_models()
+            .await
+            .map_err(|e| AppError::Api(e.to_string()))
+    }
+}
+
+impl CommitGenerator for OpenAiGenerator {
+    async fn generate(&self, context: &str, opts: &GenOptions) -> Result<Commit, AppError> {
+        if opts.no_tools {
+            return self
+                .generate_from_content(|opts| build_plain_request(context, opts), opts)
+                .await;
+        }
+        if supports_structured_output(&opts.model) {
+            return self
+                .generate_from_content(|opts| build_structured_request(context, opts), opts)
+                .await;
+        }
+        let completion = self
+            .create_with_room(|opts| build_request(context, opts), opts)
+            .await?;
+
+        // Parse commit message from the first tool call in the assistant’s response
+        let tool_call = completion
+            .choices
+            .first()
+            .and_then(|choice| choice.message.tool_calls.as_ref())
+            .and_then(|calls| calls.first())
+            .ok_or_else(|| {
+                AppError::InvalidCommit("the model didn't call the commit tool".to_string())
+            })?;
+
+        parse_commit(&tool_call.function.arguments).map_err(|e| {
+            debug!("Invalid commit arguments: {}", tool_call.function.arguments);
+            AppError::InvalidCommit(e.to_string())
+        })
+    }
+
+    async fn complete(&self, prompt: &str, opts: &GenOptions) -> Result<String, AppError> {
+        let completion = self.create(build_completion_request(prompt, opts)?).await?;
+
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AppError::Api("The model returned an empty response.".to_string()))
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage.total()
+    }
+
+    fn replies_with_json(&self, opts: &GenOptions) -> bool {
+        opts.no_tools || supports_structured_output(&opts.model)
+    }
+}
+
+/// The `max_tokens` to retry with when the model stopped for `finish_reason`:
+/// double the old limit, up to `MAX_BUMPED_RESPONSE_TOKENS`, if the answer was
+/// cut off by it, and `None` when it finished on its own or can't get more room.
+pub fn bumped_max_tokens(finish_reason: Option<FinishReason>, max_tokens: u32) -> Option<u32> {
+    match finish_reason {
+        Some(FinishReason::Length) if max_tokens < MAX_BUMPED_RESPONSE_TOKENS => {
+            Some(max_tokens.saturating_mul(2).min(MAX_BUMPED_RESPONSE_TOKENS))
+        }
+        _ => None,
+    }
+}
+
+/// The system prompt, followed by a `get_diff` tool call answered with the context.
+pub fn build_messages_tools(
+    context: &str,
+    system_prompt: &str,
+) -> Result<Vec<ChatCompletionRequestMessage>, AppError> {
+    Ok(vec![
+        // System prompt
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt.to_string())
+            .build()
+            .map_err(invalid_request)?
+            .into(),
+        // Assistant invokes get_diff tool
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(vec![ChatCompletionMessageToolCall {
+                id: "call_get_diff".to_string(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: "get_diff".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            }])
+            .build()
+            .map_err(invalid_request)?
+            .into(),
+        // Tool returns diff
+        ChatCompletionRequestToolMessageArgs::default()
+            .tool_call_id("call_get_diff".to_string())
+            .content(context.to_string())
+            .build()
+            .map_err(invalid_request)?
+            .into(),
+    ])
+}
+
+/// The system prompt and a user message holding the context, for endpoints
+/// without tool calls: the commit is asked for as a bare JSON object.
+pub fn build_messages_plain(
+    context: &str,
+    system_prompt: &str,
+) -> Result<Vec<ChatCompletionRequestMessage>, AppError> {
+    let prompt = format!(
+        "Reply with only a JSON object matching this schema, with no other text:\n{}\n\n\
+         The output of `git diff HEAD`:\n{}",
+        commit_schema(),
+        context
+    );
+    Ok(vec![
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(system_prompt.to_string())
+            .build()
+            .map_err(invalid_request)?
+            .into(),
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(prompt)
+            .build()
+            .map_err(invalid_request)?
+            .into(),
+    ])
+}
+
+fn get_diff_tool() -> Result<ChatCompletionTool, AppError> {
+    ChatCompletionToolArgs::default()
+        .r#type(ChatCompletionToolType::Function)
+        .function(FunctionObject {
+            name: "get_diff".to_string(),
+            description: Some("Returns the output of `git diff HEAD` as a string.".to_string()),
+            parameters: Some(json!({ "type": "object", "properties": {} })),
+            strict: None,
+        })
+        .build()
+        .map_err(invalid_request)
+}
+
+fn build_tools() -> Result<Vec<ChatCompletionTool>, AppError> {
+    Ok(vec![
+        get_diff_tool()?,
+        ChatCompletionToolArgs::default()
+            .r#type(ChatCompletionToolType::Function)
+            .function(FunctionObject {
+                name: "commit".to_string(),
+                description: Some(
+                    "Creates a commit with the given title and a description.".to_string(),
+                ),
+                parameters: Some(commit_schema()),
+                strict: None,
+            })
+            .build()
+            .map_err(invalid_request)?,
+    ])
+}
+
+/// Builds the chat request, forcing the "commit" tool.
+pub fn build_request(
+    context: &str,
+    opts: &GenOptions,
+) -> Result<CreateChatCompletionRequest, AppError> {
+    request_args(opts)
+        .messages(build_messages_tools(context, &opts.system_prompt)?)
+        .tools(build_tools()?)
+        .tool_choice(ChatCompletionToolChoiceOption::Named(
+            ChatCompletionNamedToolChoice {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionName {
+                    name: "commit".to_string(),
+                },
+            },
+        ))
+        .build()
+        .map_err(invalid_request)
+}
+
+/// The `Commit` schema in the form strict structured outputs accept: no
+/// additional properties and no length constraints.
+pub fn structured_output_format() -> ResponseFormat {
+    let mut schema = commit_schema();
+    schema["additionalProperties"] = json!(false);
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        for property in properties.values_mut() {
+            if let Some(property) = property.as_object_mut() {
+                property.remove("minLength");
+            }
+        }
+    }
+    ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: Some("A commit with a title and a description.".to_string()),
+            name: "commit".to_string(),
+            schema: Some(schema),
+            strict: Some(true),
+        },
+    }
+}
+
+/// Builds the chat request for models with structured outputs: the commit
+/// comes back as the message content instead of a tool call.
+pub fn build_structured_request(
+    context: &str,
+    opts: &GenOptions,
+) -> Result<CreateChatCompletionRequest, AppError> {
+    request_args(opts)
+        .messages(build_messages_tools(context, &opts.system_prompt)?)
+        // The messages replay a get_diff call, so the tool has to be declared
+        .tools(vec![get_diff_tool()?])
+        .tool_choice(ChatCompletionToolChoiceOption::None)
+        .response_format(structured_output_format())
+        .build()
+        .map_err(invalid_request)
+}
+
+/// Builds the chat request for `--no-tools`: no tools are declared and the
+/// commit is parsed from the message content.
+pub fn build_plain_request(
+    context: &str,
+    opts: &GenOptions,
+) -> Result<CreateChatCompletionRequest, AppError> {
+    request_args(opts)
+        .messages(build_messages_plain(context, &opts.system_prompt)?)
+        .build()
+        .map_err(invalid_request)
+}
+
+/// Builds a plain chat request: the system prompt and `prompt` as the user message.
+pub fn build_completion_request(
+    prompt: &str,
+    opts: &GenOptions,
+) -> Result<CreateChatCompletionRequest, AppError> {
+    request_args(opts)
+        .messages(vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(opts.system_prompt.clone())
+                .build()
+                .map_err(invalid_request)?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt.to_string())
+                .build()
+                .map_err(invalid_request)?
+                .into(),
+        ])
+        .build()
+        .map_err(invalid_request)
+}
+
+/// A request builder with the settings every request shares.
+fn request_args(opts: &GenOptions) -> CreateChatCompletionRequestArgs {
+    let mut args = CreateChatCompletionRequestArgs::default();
+    args.model(&opts.model)
+        .temperature(0.0)
+        .max_tokens(opts.max_tokens);
+    if let Some(seed) = opts.seed {
+        args.seed(seed);
+    }
+    args
+}
+
+fn invalid_request(e: impl std::fmt::Display) -> AppError {
+    AppError::Api(format!("Failed to build the request: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> GenOptions {
+        GenOptions {
+            model: "gpt-4.1-nano".to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn build_request_forces_the_commit_tool() {
+        let request = build_request("diff --git a/a b/a", &opts()).unwrap();
+        let value = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(value["model"], "gpt-4.1-nano");
+        assert_eq!(value["tool_choice"]["function"]["name"], "commit");
+        assert_eq!(value["tools"][1]["function"]["name"], "commit");
+        assert_eq!(value["messages"][0]["content"], "Write great commits.");
+        assert_eq!(value["messages"][2]["content"], "diff --git a/a b/a");
+    }
+
+    #[test]
+    fn bumped_max_tokens_doubles_a_cut_off_answer() {
+        assert_eq!(
+            bumped_max_tokens(Some(FinishReason::Length), 2000),
+            Some(4000)
+        );
+        assert_eq!(
+            bumped_max_tokens(Some(FinishReason::Length), 5000),
+            Some(8000)
+        );
+    }
+
+    #[test]
+    fn bumped_max_tokens_stops_at_the_cap() {
+        assert_eq!(bumped_max_tokens(Some(FinishReason::Length), 8000), None);
+        assert_eq!(bumped_max_tokens(Some(FinishReason::Length), 10000), None);
+    }
+
+    #[test]
+    fn bumped_max_tokens_leaves_finished_answers_alone() {
+        for reason in [
+            FinishReason::Stop,
+            FinishReason::ToolCalls,
+            FinishReason::ContentFilter,
+            FinishReason::FunctionCall,
+        ] {
+            assert_eq!(bumped_max_tokens(Some(reason), 2000), None, "{:?}", reason);
+        }
+        assert_eq!(bumped_max_tokens(None, 2000), None);
+    }
+
+    #[test]
+    fn replies_with_json_unless_the_commit_tool_is_used() {
+        let generator = OpenAiGenerator::new("sk-test".to_string());
+        assert!(generator.replies_with_json(&opts()));
+        let older = GenOptions {
+            model: "gpt-4".to_string(),
+            ..opts()
+        };
+        assert!(!generator.replies_with_json(&older));
+        let no_tools = GenOptions {
+            no_tools: true,
+            ..older
+        };
+        assert!(generator.replies_with_json(&no_tools));
+    }
+
+    #[test]
+    fn supports_structured_output_for_recent_models() {
+        for model in [
+            "gpt-4o",
+            "gpt-4o-mini",
+            "gpt-4o-2024-08-06",
+            "gpt-4.1-nano",
+            "o3-mini",
+        ] {
+            assert!(supports_structured_output(model), "{}", model);
+        }
+    }
+
+    #[test]
+    fn supports_structured_output_not_for_older_models() {
+        for model in [
+            "gpt-3.5-turbo",
+            "gpt-4",
+            "gpt-4-turbo",
+            "gpt-4o-2024-05-13",
+            "o1-mini",
+        ] {
+            assert!(!supports_structured_output(model), "{}", model);
+        }
+    }
+
+    #[test]
+    fn structured_output_format_is_a_strict_commit_schema() {
+        let value = serde_json::to_value(structured_output_format()).unwrap();
+        assert_eq!(value["type"], "json_schema");
+        assert_eq!(value["json_schema"]["name"], "commit");
+        assert_eq!(value["json_schema"]["strict"], true);
+
+        let schema = &value["json_schema"]["schema"];
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(schema["required"], json!(["description", "title"]));
+        assert_eq!(schema["properties"]["title"], json!({ "type": "string" }));
+        assert_eq!(
+            schema["properties"]["description"],
+            json!({ "type": "string" })
+        );
+    }
+
+    #[test]
+    fn build_structured_request_uses_the_response_format() {
+        let request = build_structured_request("diff --git a/a b/a", &opts()).unwrap();
+        let value = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(value["response_format"]["type"], "json_schema");
+        assert_eq!(value["tool_choice"], "none");
+        assert_eq!(value["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(value["messages"][2]["content"], "diff --git a/a b/a");
+    }
+
+    #[test]
+    fn endpoint_defaults_to_openai() {
+        assert_eq!(
+            OpenAiEndpoint::from_vars(None, None, None),
+            Ok(OpenAiEndpoint::OpenAi)
+        );
+    }
+
+    #[test]
+    fn endpoint_base_url_overrides_openai_only() {
+        assert_eq!(
+            OpenAiEndpoint::OpenAi.with_base_url(Some("http://127.0.0.1:8080/v1/".to_string())),
+            OpenAiEndpoint::Custom {
+                base_url: "http://127.0.0.1:8080/v1".to_string()
+            }
+        );
+        assert_eq!(
+            OpenAiEndpoint::OpenAi.with_base_url(Some(" ".to_string())),
+            OpenAiEndpoint::OpenAi
+        );
+        let azure = OpenAiEndpoint::Azure {
+            endpoint: "https://example.openai.azure.com".to_string(),
+            deployment: "commits".to_string(),
+            api_version: DEFAULT_AZURE_API_VERSION.to_string(),
+        };
+        assert_eq!(
+            azure
+                .clone()
+                .with_base_url(Some("http://127.0.0.1:8080".to_string())),
+            azure
+        );
+    }
+
+    #[test]
+    fn endpoint_selects_azure_when_configured() {
+        let endpoint = OpenAiEndpoint::from_vars(
+            Some("https://example.openai.azure.com".to_string()),
+            Some("commits".to_string()),
+            None,
+        );
+        assert_eq!(
+            endpoint,
+            Ok(OpenAiEndpoint::Azure {
+                endpoint: "https://example.openai.azure.com".to_string(),
+                deployment: "commits".to_string(),
+                api_version: DEFAULT_AZURE_API_VERSION.to_string(),
+            })
+        );
+
+        let endpoint = OpenAiEndpoint::from_vars(
+            Some("https://example.openai.azure.com".to_string()),
+            Some("commits".to_string()),
+            Some("2024-06-01".to_string()),
+        );
+        assert!(matches!(
+            endpoint,
+            Ok(OpenAiEndpoint::Azure { api_version, .. }) if api_version == "2024-06-01"
+        ));
+    }
+
+    #[test]
+    fn github_endpoint_defaults_to_github_models() {
+        let default = OpenAiEndpoint::Custom {
+            base_url: GITHUB_MODELS_BASE_URL.to_string(),
+        };
+        assert_eq!(OpenAiEndpoint::github(None), default);
+        assert_eq!(OpenAiEndpoint::github(Some(" ".to_string())), default);
+        assert_eq!(
+            OpenAiEndpoint::github(Some("https://models.inference.ai.azure.com/".to_string())),
+            OpenAiEndpoint::Custom {
+                base_url: "https://models.inference.ai.azure.com".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn endpoint_rejects_partial_azure_config() {
+        assert!(matches!(
+            OpenAiEndpoint::from_vars(Some("https://example.openai.azure.com".to_string()), None, None),
+            Err(AppError::Config(msg)) if msg.contains("AUTO_COMMIT_AZURE_DEPLOYMENT")
+        ));
+        assert!(matches!(
+            OpenAiEndpoint::from_vars(None, Some("commits".to_string()), None),
+            Err(AppError::Config(msg)) if msg.contains("AUTO_COMMIT_AZURE_ENDPOINT")
+        ));
+    }
+
+    #[test]
+    fn build_completion_request_has_no_tools() {
+        let request = build_completion_request("Summarize this.", &opts()).unwrap();
+        let value = serde_json::to_value(&request).unwrap();
+
+        assert!(value.get("tools").is_none());
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert_eq!(value["messages"][1]["role"], "user");
+        assert_eq!(value["messages"][1]["content"], "Summarize this.");
+    }
+
+    #[test]
+    fn requests_include_the_seed_only_when_set() {
+        let value = serde_json::to_value(build_request("diff", &opts()).unwrap()).unwrap();
+        assert!(value.get("seed").is_none());
+
+        let seeded = GenOptions {
+            seed: Some(42),
+            ..opts()
+        };
+        for request in [
+            build_request("diff", &seeded).unwrap(),
+            build_structured_request("diff", &seeded).unwrap(),
+            build_plain_request("diff", &seeded).unwrap(),
+            build_completion_request("diff", &seeded).unwrap(),
+        ] {
+            let value = serde_json::to_value(&request).unwrap();
+            assert_eq!(value["seed"], 42);
+            assert_eq!(value["temperature"], 0.0);
+        }
+    }
+
+    #[test]
+    fn build_messages_tools_replays_the_get_diff_call() {
+        let messages = build_messages_tools("diff --git a/a b/a", "Write great commits.").unwrap();
+        let value = serde_json::to_value(&messages).unwrap();
+
+        assert_eq!(value.as_array().unwrap().len(), 3);
+        assert_eq!(value[0]["role"], "system");
+        assert_eq!(value[1]["role"], "assistant");
+        assert_eq!(value[1]["tool_calls"][0]["function"]["name"], "get_diff");
+        assert_eq!(value[2]["role"], "tool");
+        assert_eq!(value[2]["tool_call_id"], value[1]["tool_calls"][0]["id"]);
+        assert_eq!(value[2]["content"], "diff --git a/a b/a");
+    }
+
+    #[test]
+    fn build_messages_plain_embeds_the_diff_and_schema() {
+        let messages = build_messages_plain("diff --git a/a b/a", "Write great commits.").unwrap();
+        let value = serde_json::to_value(&messages).unwrap();
+
+        assert_eq!(value.as_array().unwrap().len(), 2);
+        assert_eq!(value[0]["role"], "system");
+        assert_eq!(value[1]["role"], "user");
+        let content = value[1]["content"].as_str().unwrap();
+        assert!(content.contains("JSON object"));
+        assert!(content.contains("\"title\""));
+        assert!(content.ends_with("diff --git a/a b/a"));
+    }
+
+    #[test]
+    fn build_plain_request_declares_no_tools() {
+        let request = build_plain_request("diff --git a/a b/a", &opts()).unwrap();
+        let value = serde_json::to_value(&request).unwrap();
+
+        assert!(value.get("tools").is_none());
+        assert!(value.get("tool_choice").is_none());
+        assert!(value.get("response_format").is_none());
+        assert_eq!(value["messages"][1]["role"], "user");
+    }
+}
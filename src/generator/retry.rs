@@ -0,0 +1,177 @@
+//! Waiting out rate limits for the Anthropic API, which is sent with our own
+//! HTTP client and so can read `Retry-After`. OpenAI, Azure and GitHub Models
+//! requests go through `async-openai`, which doesn't expose response headers
+//! and backs off on its own.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
+
+tokio::task_local! {
+    /// When the request being made has to be answered by, set by `WithTimeout`.
+    static DEADLINE: Instant;
+}
+
+/// Runs `request` with `deadline` visible to `time_left`, so retries inside it
+/// don't sleep past the point where it times out anyway.
+pub async fn with_deadline<F: Future>(deadline: Instant, request: F) -> F::Output {
+    DEADLINE.scope(deadline, request).await
+}
+
+/// Time left before the current request's deadline, or `None` without one.
+pub fn time_left() -> Option<Duration> {
+    DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Whether waiting `delay` to retry still leaves time before the deadline
+/// (`time_left`). If it doesn't, the rate limit error is better returned at
+/// once, so another key can take over before the timeout fires.
+pub fn retry_fits(delay: Duration, time_left: Option<Duration>) -> bool {
+    time_left.is_none_or(|left| delay < left)
+}
+
+/// How often a rate-limited request is retried before giving up.
+pub const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Longest `Retry-After` we're willing to wait for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a `Retry-After` header asks to wait, given either as seconds or as
+/// an HTTP date.
+pub fn parse_retry_after(header: &str) -> Option<Duration> {
+    parse_retry_after_at(header, SystemTime::now())
+}
+
+/// `parse_retry_after` relative to `now`; dates in the past mean no wait.
+fn parse_retry_after_at(header: &str, now: SystemTime) -> Option<Duration> {
+    let header = header.trim();
+    if let Ok(secs) = header.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(parse_http_date(header)?);
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Seconds since the epoch for an IMF-fixdate such as `Wed, 21 Oct 2015 07:28:00 GMT`.
+fn parse_http_date(date: &str) -> Option<u64> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [_weekday, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .position(|name| name == month)? as u64
+        + 1;
+    let year: u64 = year.parse().ok()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let [hours, minutes, seconds] = time.as_slice() else {
+        return None;
+    };
+    if year < 1970 || !(1..=31).contains(&day) || *hours > 23 || *minutes > 59 || *seconds > 60 {
+        return None;
+    }
+    Some(days_since_epoch(year, month, day) * 86_400 + hours * 3_600 + minutes * 60 + seconds)
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// How long to wait before retry number `attempt` (starting at 0): what the
+/// server asked for, capped at a minute, or an exponential 1s, 2s, 4s, ...
+pub fn retry_delay(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    match retry_after {
+        Some(delay) => delay.min(MAX_RETRY_AFTER),
+        None => Duration::from_secs(1 << attempt.min(6)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_fits_before_the_deadline() {
+        let delay = Duration::from_secs(20);
+        assert!(retry_fits(delay, None));
+        assert!(retry_fits(delay, Some(Duration::from_secs(30))));
+        assert!(!retry_fits(delay, Some(Duration::from_secs(20))));
+        assert!(!retry_fits(delay, Some(Duration::ZERO)));
+    }
+
+    #[tokio::test]
+    async fn with_deadline_sets_the_time_left() {
+        assert_eq!(time_left(), None);
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let left = with_deadline(deadline, async { time_left() })
+            .await
+            .unwrap();
+        assert!(left > Duration::from_secs(29) && left <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn parse_retry_after_reads_seconds() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parse_retry_after_reads_http_dates() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_445_412_470); // 07:27:50 GMT
+        assert_eq!(
+            parse_retry_after_at("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            parse_retry_after_at("Thu, 01 Jan 1970 00:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 12:00:00 GMT"),
+            Some(951_825_600)
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_rejects_other_values() {
+        assert_eq!(parse_retry_after(""), None);
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-5"), None);
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 PST"), None);
+        assert_eq!(parse_retry_after("Wed, 21 Foo 2015 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn retry_delay_prefers_the_server_hint() {
+        assert_eq!(
+            retry_delay(2, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            retry_delay(0, Some(Duration::from_secs(3_600))),
+            MAX_RETRY_AFTER
+        );
+        assert_eq!(retry_delay(0, None), Duration::from_secs(1));
+        assert_eq!(retry_delay(2, None), Duration::from_secs(4));
+    }
+}
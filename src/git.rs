@@ -0,0 +1,639 @@
+use crate::{build_commit_args, error::AppError, DiffSource};
+use log::{debug, warn};
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// How `GitRepo::commit` creates the commit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommitOptions {
+    /// Open the editor on the message before committing.
+    pub review: bool,
+    /// Show the diff in the editor, as `git commit --verbose` does.
+    pub verbose: bool,
+    /// GPG-sign the commit.
+    pub sign: bool,
+    /// Replace the last commit instead of adding one.
+    pub amend: bool,
+}
+
+/// The repository operations the commit flow needs, so it can run against a
+/// fake repository in tests.
+pub trait GitRepo {
+    fn is_inside_work_tree(&self) -> bool;
+    /// Whether there's a commit at HEAD, i.e. one to amend.
+    fn has_head(&self) -> bool;
+    /// Runs `git` with `args` to stage changes, returning whether it succeeded.
+    fn stage(&self, args: &[&str]) -> io::Result<bool>;
+    /// The diff to describe.
+    fn staged_diff(&self) -> io::Result<String>;
+    /// The paths the diff touches, one per entry.
+    fn staged_files(&self) -> io::Result<Vec<String>>;
+    /// Commits with `message`, returning git's output, or an error if git
+    /// didn't make the commit.
+    fn commit(&self, message: &str, opts: &CommitOptions) -> io::Result<String>;
+}
+
+/// `GitRepo` backed by the `git` executable in the current directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemGit {
+    pub source: DiffSource,
+    /// Passed to git as `--diff-algorithm`.
+    pub diff_algorithm: Option<String>,
+    /// Pass `-w`, so lines that only changed in whitespace aren't shown.
+    pub ignore_whitespace: bool,
+    /// Lines of context around each change, passed as `-U<N>`; git's default is 3.
+    pub context_lines: Option<u32>,
+}
+
+impl SystemGit {
+    pub fn new(source: DiffSource, diff_algorithm: Option<&str>, ignore_whitespace: bool) -> Self {
+        Self {
+            source,
+            diff_algorithm: diff_algorithm.map(str::to_string),
+            ignore_whitespace,
+            context_lines: None,
+        }
+    }
+
+    pub fn with_context_lines(self, context_lines: Option<u32>) -> Self {
+        Self {
+            context_lines,
+            ..self
+        }
+    }
+
+    fn diff_args(&self) -> Vec<String> {
+        let mut args = self.source.diff_args(self.diff_algorithm.as_deref());
+        if self.ignore_whitespace {
+            args.insert(1, "-w".to_string());
+        }
+        if let Some(lines) = self.context_lines {
+            args.insert(1, format!("-U{}", lines));
+        }
+        args
+    }
+}
+
+impl GitRepo for SystemGit {
+    fn is_inside_work_tree(&self) -> bool {
+        git_output(&["rev-parse", "--is-inside-work-tree"]).as_deref() == Some("true")
+    }
+
+    fn has_head(&self) -> bool {
+        git_output(&["rev-parse", "--verify", "--quiet", "HEAD"]).is_some()
+    }
+
+    fn stage(&self, args: &[&str]) -> io::Result<bool> {
+        Ok(Command::new("git").args(args).status()?.success())
+    }
+
+    fn staged_diff(&self) -> io::Result<String> {
+        let output = Command::new("git").args(self.diff_args()).output()?;
+        let (diff, lossy) = decode_git_output(&output.stdout);
+        if lossy {
+            warn!("The diff isn't valid UTF-8; invalid bytes were replaced.");
+        }
+        Ok(diff)
+    }
+
+    fn staged_files(&self) -> io::Result<Vec<String>> {
+        let output = Command::new("git")
+            .args(self.source.files_args())
+            .output()?;
+        Ok(decode_git_output(&output.stdout)
+            .0
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn commit(&self, message: &str, opts: &CommitOptions) -> io::Result<String> {
+        let mut command = Command::new("git");
+        command
+            .args(build_commit_args(opts.review, opts.verbose, opts.sign))
+            .args(if opts.amend { vec!["--amend"] } else { vec![] })
+            .stdin(Stdio::piped());
+        if opts.review {
+            // The editor needs the terminal, so git's summary goes to stderr instead
+            command.stdout(Stdio::from(io::stderr()));
+        } else {
+            // Returned to the caller rather than printed, keeping stdout for the message
+            command.stdout(Stdio::piped());
+        }
+        let mut child = command.spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let message = message.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(message.as_bytes()));
+        let output = child.wait_with_output()?;
+        // A hook or signing failure is reported by git on stderr; the status says it happened
+        if !output.status.success() {
+            return Err(io::Error::other(format!("git commit {}", output.status)));
+        }
+        writer.join().expect("stdin writer panicked")?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Checks that `git` can be run at all, so a missing install is reported
+/// clearly instead of as an IO error from the first real git call.
+pub fn ensure_git_available() -> Result<(), AppError> {
+    ensure_git_available_with(|args| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .map(|output| output.status.success())
+    })
+}
+
+/// `ensure_git_available` with the command runner injected; `run` executes git
+/// with the given arguments and reports whether it succeeded.
+pub fn ensure_git_available_with(
+    run: impl FnOnce(&[&str]) -> io::Result<bool>,
+) -> Result<(), AppError> {
+    match run(&["--version"]) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AppError::GitNotFound),
+        Err(e) => {
+            debug!("Couldn't run git: {}", e);
+            Err(AppError::GitNotFound)
+        }
+    }
+}
+
+/// Pushes the current branch to its upstream, returning git's report.
+pub fn push() -> Result<String, AppError> {
+    push_with(run_git)
+}
+
+/// Runs `git` with `args`, returning whether it succeeded and its trimmed
+/// stdout and stderr.
+pub fn run_git(args: &[&str]) -> io::Result<(bool, String)> {
+    let output = Command::new("git").args(args).output()?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok((output.status.success(), text.trim().to_string()))
+}
+
+/// `push` with the command runner injected; `run` executes git with the given
+/// arguments and returns whether it succeeded along with its output.
+pub fn push_with(
+    mut run: impl FnMut(&[&str]) -> io::Result<(bool, String)>,
+) -> Result<String, AppError> {
+    let run_git = |e: io::Error| AppError::Push(format!("couldn't run git: {}", e));
+    let HeadState::Branch(branch) = head_state_with(&mut run) else {
+        debug!("HEAD is detached; not pushing.");
+        return Ok(
+            "HEAD is detached, so there's no branch to push; skipped `git push`.".to_string(),
+        );
+    };
+    let (has_upstream, _) =
+        run(&["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"]).map_err(run_git)?;
+    if !has_upstream {
+        return Err(AppError::Push(format!(
+            "the current branch has no upstream; set one with `git push --set-upstream origin {}`.",
+            branch
+        )));
+    }
+    match run(&["push"]).map_err(run_git)? {
+        (true, output) => Ok(output),
+        (false, output) => Err(AppError::Push(output)),
+    }
+}
+
+/// Checks that `base` names a commit, for `--base`.
+pub fn verify_ref(base: &str) -> Result<(), AppError> {
+    verify_ref_with(base, run_git)
+}
+
+/// `verify_ref` with the command runner injected, as in `push_with`.
+pub fn verify_ref_with(
+    base: &str,
+    mut run: impl FnMut(&[&str]) -> io::Result<(bool, String)>,
+) -> Result<(), AppError> {
+    let commit = format!("{}^{{commit}}", base);
+    match run(&["rev-parse", "--verify", "--quiet", &commit]) {
+        Ok((true, _)) => Ok(()),
+        Ok((false, _)) => Err(AppError::Config(format!(
+            "Unknown base ref `{}`; pass a branch, tag or commit that exists.",
+            base
+        ))),
+        Err(e) => Err(AppError::Config(format!("Couldn't run git: {}", e))),
+    }
+}
+
+/// Runs `git` with `args`, returning its trimmed stdout if it succeeded.
+pub fn git_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Decodes git's output as UTF-8, replacing invalid sequences instead of dropping
+/// the text. The flag is set when anything had to be replaced.
+pub fn decode_git_output(bytes: &[u8]) -> (String, bool) {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) => (text.to_string(), false),
+        Cow::Owned(text) => (text, true),
+    }
+}
+
+/// `git config --get <key>`, or `None` when the key isn't set.
+pub fn git_config_get(key: &str) -> Option<String> {
+    git_output(&["config", "--get", key]).filter(|value| !value.is_empty())
+}
+
+/// `git config --type=bool --get <key>`, accepting anything git reads as a
+/// boolean; `None` if the key isn't set or isn't a boolean.
+pub fn git_config_bool(key: &str) -> Option<bool> {
+    match git_output(&["config", "--type=bool", "--get", key]).as_deref() {
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// `git config --get-all <key>`: every value of a multi-valued key, in order.
+pub fn git_config_get_all(key: &str) -> Vec<String> {
+    git_output(&["config", "--get-all", key])
+        .map(|values| values.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// What HEAD points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadState {
+    /// A branch is checked out, e.g. `main`.
+    Branch(String),
+    /// HEAD is a bare commit, e.g. during a rebase or after checking out a tag.
+    Detached,
+}
+
+pub fn head_state() -> HeadState {
+    head_state_with(run_git)
+}
+
+/// `head_state` with the command runner injected, as in `push_with`. `git
+/// symbolic-ref -q HEAD` fails when HEAD isn't a branch.
+pub fn head_state_with(mut run: impl FnMut(&[&str]) -> io::Result<(bool, String)>) -> HeadState {
+    match run(&["symbolic-ref", "-q", "--short", "HEAD"]) {
+        Ok((true, name)) if !name.trim().is_empty() => HeadState::Branch(name.trim().to_string()),
+        _ => HeadState::Detached,
+    }
+}
+
+/// The checked-out branch, or `None` on a detached HEAD, in which case
+/// branch-derived features such as ticket extraction are skipped.
+pub fn current_branch() -> Option<String> {
+    match head_state() {
+        HeadState::Branch(name) => Some(name),
+        HeadState::Detached => {
+            debug!("HEAD is detached; skipping what's taken from the branch name.");
+            None
+        }
+    }
+}
+
+/// The top-level directory of the current work tree.
+pub fn repo_root() -> Option<PathBuf> {
+    git_output(&["rev-parse", "--show-toplevel"]).map(PathBuf::from)
+}
+
+/// The file `git commit` reads a prepared message from.
+pub const COMMIT_EDITMSG: &str = "COMMIT_EDITMSG";
+
+/// The repository's git directory, usually `.git`.
+pub fn git_dir() -> Option<PathBuf> {
+    git_output(&["rev-parse", "--git-dir"]).map(PathBuf::from)
+}
+
+/// Whether `git_dir` has a `MERGE_HEAD`, i.e. a merge is waiting to be committed.
+pub fn is_merge_in_progress(git_dir: &Path) -> bool {
+    git_dir.join("MERGE_HEAD").is_file()
+}
+
+/// The message git prepared for the merge in `MERGE_MSG`, without its `#` comments.
+pub fn merge_message(git_dir: &Path) -> Option<String> {
+    let text = fs::read_to_string(git_dir.join("MERGE_MSG")).ok()?;
+    let message = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let message = message.trim();
+    (!message.is_empty()).then(|| message.to_string())
+}
+
+/// Where `--write` puts the message: `explicit` if given, else `COMMIT_EDITMSG`
+/// in the directory returned by `git_dir`.
+pub fn message_path(
+    explicit: Option<&Path>,
+    git_dir: impl FnOnce() -> Option<PathBuf>,
+) -> Option<PathBuf> {
+    match explicit {
+        Some(path) => Some(path.to_path_buf()),
+        None => git_dir().map(|dir| dir.join(COMMIT_EDITMSG)),
+    }
+}
+
+/// Writes `message` to `path`, ending it with a newline like git does. The
+/// message goes to a temporary file next to `path` that is then renamed over
+/// it, so a reader never sees it half-written.
+pub fn write_message(path: &Path, message: &str) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    fs::write(&temp, format!("{}\n", message.trim_end()))?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+/// `--prepare`: saves `message` as `COMMIT_EDITMSG` in `git_dir` for the user
+/// to commit themselves, returning the file it went to.
+pub fn prepare_message(git_dir: &Path, message: &str) -> io::Result<PathBuf> {
+    let path = git_dir.join(COMMIT_EDITMSG);
+    write_message(&path, message)?;
+    Ok(path)
+}
+
+/// What to run to commit a message `--prepare` left at `path`. A plain `git
+/// commit` would start over from an empty message.
+pub fn prepare_hint(path: &Path) -> String {
+    format!(
+        "Run `git commit -e -F {}` to review and commit it.",
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_git_available_runs_git_version() {
+        let result = ensure_git_available_with(|args| {
+            assert_eq!(args, ["--version"]);
+            Ok(true)
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn ensure_git_available_reports_a_missing_git() {
+        let missing = ensure_git_available_with(|_| {
+            Err(io::Error::new(io::ErrorKind::NotFound, "No such file"))
+        });
+        assert_eq!(missing, Err(AppError::GitNotFound));
+        assert_eq!(
+            ensure_git_available_with(|_| Ok(false)),
+            Err(AppError::GitNotFound)
+        );
+    }
+
+    #[test]
+    fn system_git_passes_diff_flags() {
+        let git = SystemGit::new(DiffSource::Staged, Some("histogram"), true);
+        assert_eq!(
+            git.diff_args(),
+            ["diff", "-w", "--diff-algorithm=histogram", "--staged"]
+        );
+        let git = SystemGit::new(DiffSource::LastCommit, None, false);
+        assert_eq!(git.diff_args(), ["show", "--format=", "HEAD"]);
+    }
+
+    #[test]
+    fn system_git_passes_the_context_lines() {
+        let git = SystemGit::new(DiffSource::Staged, None, false).with_context_lines(Some(10));
+        assert_eq!(git.diff_args(), ["diff", "-U10", "--staged"]);
+        let git =
+            SystemGit::new(DiffSource::Staged, Some("histogram"), true).with_context_lines(Some(0));
+        assert_eq!(
+            git.diff_args(),
+            [
+                "diff",
+                "-U0",
+                "-w",
+                "--diff-algorithm=histogram",
+                "--staged"
+            ]
+        );
+        let git = SystemGit::new(DiffSource::LastCommit, None, false).with_context_lines(None);
+        assert_eq!(git.diff_args(), ["show", "--format=", "HEAD"]);
+    }
+
+    #[test]
+    fn verify_ref_accepts_a_known_commit() {
+        let mut calls = Vec::new();
+        let result = verify_ref_with("main", |args: &[&str]| {
+            calls.push(args.join(" "));
+            Ok((true, "abc123".to_string()))
+        });
+        assert!(result.is_ok());
+        assert_eq!(calls, ["rev-parse --verify --quiet main^{commit}"]);
+    }
+
+    #[test]
+    fn verify_ref_rejects_an_unknown_ref() {
+        let err = verify_ref_with("nope", |_: &[&str]| Ok((false, String::new()))).unwrap_err();
+        assert!(matches!(err, AppError::Config(_)));
+        assert!(err.to_string().contains("Unknown base ref `nope`"));
+    }
+
+    #[test]
+    fn push_pushes_to_the_upstream() {
+        let mut calls = Vec::new();
+        let result = push_with(|args| {
+            calls.push(args.join(" "));
+            Ok((true, "main -> main".to_string()))
+        });
+        assert_eq!(result, Ok("main -> main".to_string()));
+        assert_eq!(
+            calls,
+            [
+                "symbolic-ref -q --short HEAD",
+                "rev-parse --abbrev-ref --symbolic-full-name @{u}",
+                "push"
+            ]
+        );
+    }
+
+    #[test]
+    fn push_without_an_upstream_suggests_setting_one() {
+        let mut calls = Vec::new();
+        let result = push_with(|args| {
+            calls.push(args.join(" "));
+            match args {
+                ["symbolic-ref", ..] => Ok((true, "feature/login\n".to_string())),
+                _ => Ok((false, "fatal: no upstream configured".to_string())),
+            }
+        });
+        let Err(AppError::Push(msg)) = result else {
+            panic!("expected a push error, got {:?}", result);
+        };
+        assert!(
+            msg.contains("git push --set-upstream origin feature/login"),
+            "{}",
+            msg
+        );
+        assert!(!calls.contains(&"push".to_string()));
+    }
+
+    #[test]
+    fn push_skips_a_detached_head() {
+        let mut calls = Vec::new();
+        let result = push_with(|args| {
+            calls.push(args.join(" "));
+            Ok((false, String::new()))
+        });
+        assert!(result.unwrap().contains("HEAD is detached"));
+        assert_eq!(calls, ["symbolic-ref -q --short HEAD"]);
+    }
+
+    #[test]
+    fn push_reports_a_rejected_push() {
+        let result = push_with(|args| match args {
+            ["push"] => Ok((false, "! [rejected] main -> main (fetch first)".to_string())),
+            _ => Ok((true, "main".to_string())),
+        });
+        assert_eq!(
+            result,
+            Err(AppError::Push(
+                "! [rejected] main -> main (fetch first)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn message_path_prefers_the_explicit_path() {
+        let path = message_path(Some(Path::new("/tmp/msg.txt")), || {
+            panic!("git dir shouldn't be needed")
+        });
+        assert_eq!(path, Some(PathBuf::from("/tmp/msg.txt")));
+    }
+
+    #[test]
+    fn message_path_defaults_to_commit_editmsg() {
+        assert_eq!(
+            message_path(None, || Some(PathBuf::from(".git"))),
+            Some(PathBuf::from(".git/COMMIT_EDITMSG"))
+        );
+        assert_eq!(message_path(None, || None), None);
+    }
+
+    #[test]
+    fn merge_state_comes_from_merge_head() {
+        let dir = std::env::temp_dir().join(format!("auto-commit-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(!is_merge_in_progress(&dir));
+        assert_eq!(merge_message(&dir), None);
+
+        fs::write(dir.join("MERGE_HEAD"), "0123456789abcdef\n").unwrap();
+        fs::write(
+            dir.join("MERGE_MSG"),
+            "Merge branch 'feature'\n\n# Conflicts:\n#\tsrc/lib.rs\n",
+        )
+        .unwrap();
+        let in_progress = is_merge_in_progress(&dir);
+        let message = merge_message(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(in_progress);
+        assert_eq!(message, Some("Merge branch 'feature'".to_string()));
+    }
+
+    #[test]
+    fn write_message_ends_with_a_newline() {
+        let path = std::env::temp_dir().join(format!("auto-commit-msg-{}", std::process::id()));
+        write_message(&path, "Fix bug\n\nDetails\n\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Fix bug\n\nDetails\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_message_replaces_the_file_without_leaving_a_temp_file() {
+        let dir = std::env::temp_dir().join(format!("auto-commit-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(COMMIT_EDITMSG);
+        fs::write(&path, "Old message\n").unwrap();
+
+        write_message(&path, "New message").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let entries = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contents, "New message\n");
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn prepare_message_writes_commit_editmsg() {
+        let dir = std::env::temp_dir().join(format!("auto-commit-prepare-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = prepare_message(&dir, "Add login\n\nAdds a form.").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let missing = prepare_message(&dir.join("missing"), "Add login");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(path, dir.join(COMMIT_EDITMSG));
+        assert_eq!(contents, "Add login\n\nAdds a form.\n");
+        assert!(missing.is_err());
+        assert_eq!(
+            prepare_hint(Path::new(".git/COMMIT_EDITMSG")),
+            "Run `git commit -e -F .git/COMMIT_EDITMSG` to review and commit it."
+        );
+    }
+
+    #[test]
+    fn decode_git_output_keeps_valid_utf8() {
+        assert_eq!(
+            decode_git_output("+café\n".as_bytes()),
+            ("+café\n".to_string(), false)
+        );
+        assert_eq!(decode_git_output(b""), (String::new(), false));
+    }
+
+    #[test]
+    fn decode_git_output_replaces_invalid_bytes() {
+        let (text, lossy) = decode_git_output(b"+caf\xe9 au lait\n");
+        assert!(lossy);
+        assert_eq!(text, "+caf\u{fffd} au lait\n");
+    }
+
+    #[test]
+    fn head_state_reads_the_branch() {
+        let state = head_state_with(|args| {
+            assert_eq!(args, ["symbolic-ref", "-q", "--short", "HEAD"]);
+            Ok((true, "feature/JIRA-123-add-login\n".to_string()))
+        });
+        assert_eq!(
+            state,
+            HeadState::Branch("feature/JIRA-123-add-login".to_string())
+        );
+    }
+
+    #[test]
+    fn head_state_is_detached_when_symbolic_ref_fails() {
+        assert_eq!(
+            head_state_with(|_| Ok((false, String::new()))),
+            HeadState::Detached
+        );
+        assert_eq!(
+            head_state_with(|_| Ok((true, String::new()))),
+            HeadState::Detached
+        );
+        assert_eq!(
+            head_state_with(|_| Err(io::Error::new(io::ErrorKind::NotFound, "no git"))),
+            HeadState::Detached
+        );
+    }
+}
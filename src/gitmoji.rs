@@ -0,0 +1,106 @@
+/// Gitmojis (https://gitmoji.dev) for common change types, plus the verbs
+/// titles usually start with for them.
+const GITMOJIS: &[(&[&str], &str, &str)] = &[
+    (&["feat", "feature", "add"], "✨", ":sparkles:"),
+    (&["fix", "bug", "bugfix"], "🐛", ":bug:"),
+    (&["hotfix"], "🚑️", ":ambulance:"),
+    (&["docs", "doc", "document"], "📝", ":memo:"),
+    (&["style", "format"], "🎨", ":art:"),
+    (&["refactor"], "♻️", ":recycle:"),
+    (&["perf", "optimize"], "⚡️", ":zap:"),
+    (&["test", "tests"], "✅", ":white_check_mark:"),
+    (&["build", "deps"], "📦️", ":package:"),
+    (&["ci"], "👷", ":construction_worker:"),
+    (&["chore", "config"], "🔧", ":wrench:"),
+    (&["remove", "delete"], "🔥", ":fire:"),
+    (&["revert"], "⏪️", ":rewind:"),
+    (&["security"], "🔒️", ":lock:"),
+    (&["release"], "🔖", ":bookmark:"),
+    (&["init"], "🎉", ":tada:"),
+];
+
+fn lookup(t: &str) -> Option<&'static str> {
+    let t = t.trim().to_lowercase();
+    GITMOJIS
+        .iter()
+        .find(|(types, _, _)| types.contains(&t.as_str()))
+        .map(|(_, emoji, _)| *emoji)
+}
+
+/// Gitmoji for a change type such as `feat` or `fix`, or 🔧 for unknown types.
+pub fn gitmoji_for_type(t: &str) -> &'static str {
+    lookup(t).unwrap_or("🔧")
+}
+
+/// Whether `title` starts with a known gitmoji, either as an emoji or a `:shortcode:`.
+pub fn starts_with_gitmoji(title: &str) -> bool {
+    let title = title.trim_start();
+    GITMOJIS.iter().any(|(_, emoji, code)| {
+        title.starts_with(emoji.trim_end_matches('\u{fe0f}')) || title.starts_with(code)
+    })
+}
+
+/// Prefixes `title` with a gitmoji guessed from its first word, unless it already has one.
+pub fn ensure_gitmoji(title: &str) -> String {
+    if starts_with_gitmoji(title) {
+        return title.to_string();
+    }
+    let first_word = title
+        .split(|c: char| !c.is_alphanumeric())
+        .find(|word| !word.is_empty())
+        .unwrap_or("");
+    // "Adds" and "Fixes" should map like "add" and "fix"
+    let emoji = [
+        Some(first_word),
+        first_word.strip_suffix('s'),
+        first_word.strip_suffix("es"),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(lookup)
+    .unwrap_or_else(|| gitmoji_for_type(first_word));
+    format!("{} {}", emoji, title.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitmoji_for_type_maps_known_types() {
+        assert_eq!(gitmoji_for_type("feat"), "✨");
+        assert_eq!(gitmoji_for_type("Feature"), "✨");
+        assert_eq!(gitmoji_for_type("fix"), "🐛");
+        assert_eq!(gitmoji_for_type("docs"), "📝");
+        assert_eq!(gitmoji_for_type("refactor"), "♻️");
+    }
+
+    #[test]
+    fn gitmoji_for_type_falls_back_for_unknown_types() {
+        assert_eq!(gitmoji_for_type("wibble"), "🔧");
+    }
+
+    #[test]
+    fn starts_with_gitmoji_accepts_emoji_and_shortcodes() {
+        assert!(starts_with_gitmoji("✨ Add login page"));
+        assert!(starts_with_gitmoji(":bug: Fix crash on empty diff"));
+        assert!(starts_with_gitmoji("♻ Simplify parser"));
+        assert!(starts_with_gitmoji("⚡️ Speed up startup"));
+    }
+
+    #[test]
+    fn starts_with_gitmoji_rejects_missing_emoji() {
+        assert!(!starts_with_gitmoji("Add login page"));
+        assert!(!starts_with_gitmoji(":notacode: Add login page"));
+        assert!(!starts_with_gitmoji(""));
+    }
+
+    #[test]
+    fn ensure_gitmoji_prefixes_from_first_word() {
+        assert_eq!(ensure_gitmoji("Add login page"), "✨ Add login page");
+        assert_eq!(ensure_gitmoji("Fixes crash"), "🐛 Fixes crash");
+        assert_eq!(ensure_gitmoji("Removes dead code"), "🔥 Removes dead code");
+        assert_eq!(ensure_gitmoji("Tweak settings"), "🔧 Tweak settings");
+        assert_eq!(ensure_gitmoji("🐛 Fix crash"), "🐛 Fix crash");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::message::Commit;
+
+/// How a single file changed, as far as the diff headers tell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Added,
+    Deleted,
+    Modified,
+    Renamed { from: String },
+}
+
+impl Change {
+    fn verb(&self) -> &'static str {
+        match self {
+            Change::Added => "Add",
+            Change::Deleted => "Remove",
+            Change::Modified => "Update",
+            Change::Renamed { .. } => "Rename",
+        }
+    }
+}
+
+/// The change to `path` according to the `diff --git` headers, `Modified` if it isn't there.
+fn change_for(path: &str, diff: &str) -> Change {
+    let mut in_section = false;
+    let mut change = Change::Modified;
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            if in_section {
+                break;
+            }
+            in_section = header.ends_with(&format!(" b/{}", path));
+        } else if in_section {
+            if line.starts_with("new file mode") {
+                change = Change::Added;
+            } else if line.starts_with("deleted file mode") {
+                change = Change::Deleted;
+            } else if let Some(from) = line.strip_prefix("rename from ") {
+                change = Change::Renamed {
+                    from: from.to_string(),
+                };
+            } else if line.starts_with("@@") {
+                break;
+            }
+        }
+    }
+    change
+}
+
+/// The directory all `files` share, if any.
+fn common_dir(files: &[&str]) -> Option<String> {
+    let mut dirs = files.iter().map(|file| match file.rfind('/') {
+        Some(i) => &file[..i],
+        None => "",
+    });
+    let first = dirs.next()?;
+    let mut common: Vec<&str> = first.split('/').collect();
+    for dir in dirs {
+        let parts: Vec<&str> = dir.split('/').collect();
+        let shared = common
+            .iter()
+            .zip(&parts)
+            .take_while(|(a, b)| a == b)
+            .count();
+        common.truncate(shared);
+    }
+    let common = common.join("/");
+    (!common.is_empty()).then_some(common)
+}
+
+/// A commit message built without a model: the title names the dominant kind of
+/// change and the files it touches, the description lists every file.
+pub fn heuristic_commit(files: &[&str], diff: &str) -> Commit {
+    let changes: Vec<(&str, Change)> = files
+        .iter()
+        .map(|file| (*file, change_for(file, diff)))
+        .collect();
+
+    let title = match changes.as_slice() {
+        [] => "Update files".to_string(),
+        [(file, Change::Renamed { from })] => format!("Rename {} to {}", from, file),
+        [(file, change)] => format!("{} {}", change.verb(), file),
+        _ => {
+            let verbs: Vec<&str> = changes.iter().map(|(_, change)| change.verb()).collect();
+            // Ties go to the last, most generic verb
+            let verb = ["Rename", "Remove", "Add", "Update"]
+                .into_iter()
+                .max_by_key(|verb| verbs.iter().filter(|v| *v == verb).count())
+                .unwrap_or("Update");
+            match common_dir(files) {
+                Some(dir) => format!("{} {} files in {}", verb, files.len(), dir),
+                None => format!("{} {} files", verb, files.len()),
+            }
+        }
+    };
+
+    let description = changes
+        .iter()
+        .map(|(file, change)| match change {
+            Change::Renamed { from } => format!("- Rename {} to {}", from, file),
+            _ => format!("- {} {}", change.verb(), file),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Commit { title, description }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_FILE: &str = "diff --git a/src/cache.rs b/src/cache.rs
+new file mode 100644
+index 0000000..1111111
+--- /dev/null
++++ b/src/cache.rs
+@@ -0,0 +1 @@
++pub fn cache() {}
+";
+
+    const MODIFIED: &str = "diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-pub mod old;
++pub mod cache;
+";
+
+    const RENAME: &str = "diff --git a/src/util.rs b/src/helpers.rs
+similarity index 100%
+rename from src/util.rs
+rename to src/helpers.rs
+";
+
+    #[test]
+    fn heuristic_commit_single_file() {
+        let commit = heuristic_commit(&["src/lib.rs"], MODIFIED);
+        assert_eq!(commit.title, "Update src/lib.rs");
+        assert_eq!(commit.description, "- Update src/lib.rs");
+
+        let commit = heuristic_commit(&["src/cache.rs"], NEW_FILE);
+        assert_eq!(commit.title, "Add src/cache.rs");
+    }
+
+    #[test]
+    fn heuristic_commit_multiple_files() {
+        let diff = format!("{}{}", NEW_FILE, MODIFIED);
+        let commit = heuristic_commit(&["src/cache.rs", "src/lib.rs", "src/main.rs"], &diff);
+        assert_eq!(commit.title, "Update 3 files in src");
+        assert_eq!(
+            commit.description,
+            "- Add src/cache.rs\n- Update src/lib.rs\n- Update src/main.rs"
+        );
+
+        let commit = heuristic_commit(&["README.md", "src/lib.rs"], MODIFIED);
+        assert_eq!(commit.title, "Update 2 files");
+    }
+
+    #[test]
+    fn heuristic_commit_rename() {
+        let commit = heuristic_commit(&["src/helpers.rs"], RENAME);
+        assert_eq!(commit.title, "Rename src/util.rs to src/helpers.rs");
+        assert_eq!(commit.description, "- Rename src/util.rs to src/helpers.rs");
+    }
+
+    #[test]
+    fn heuristic_commit_without_files() {
+        assert_eq!(heuristic_commit(&[], "").title, "Update files");
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const HOOK_NAME: &str = "prepare-commit-msg";
+
+/// `prepare-commit-msg` hook that fills in the message from `auto-commit --dry-run`.
+///
+/// Git passes the message file as `$1` and the message source as `$2`; the hook
+/// only runs for plain `git commit`, leaving `-m`, merges, squashes and amends alone.
+pub const HOOK_SCRIPT: &str = r#"#!/bin/sh
+# Installed by auto-commit.
+[ -z "$2" ] || exit 0
+message=$(auto-commit --dry-run) || exit 0
+[ -n "$message" ] || exit 0
+{ printf '%s\n' "$message"; cat "$1"; } > "$1.auto-commit" && mv "$1.auto-commit" "$1"
+"#;
+
+/// Writes the hook into `hooks_dir`, refusing to replace an existing one unless `force` is set.
+pub fn install_hook(hooks_dir: &Path, force: bool) -> io::Result<PathBuf> {
+    let path = hooks_dir.join(HOOK_NAME);
+    if path.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            ),
+        ));
+    }
+
+    fs::create_dir_all(hooks_dir)?;
+    fs::write(&path, HOOK_SCRIPT)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_hooks_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("auto-commit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("hooks")
+    }
+
+    #[test]
+    fn install_hook_writes_executable_script() {
+        let dir = temp_hooks_dir("hook-write");
+        let path = install_hook(&dir, false).unwrap();
+
+        assert_eq!(path, dir.join(HOOK_NAME));
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("#!/bin/sh"));
+        assert!(contents.contains("auto-commit --dry-run"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn install_hook_refuses_to_overwrite_without_force() {
+        let dir = temp_hooks_dir("hook-overwrite");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(HOOK_NAME), "#!/bin/sh\necho custom\n").unwrap();
+
+        let err = install_hook(&dir, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(dir.join(HOOK_NAME)).unwrap(),
+            "#!/bin/sh\necho custom\n"
+        );
+
+        install_hook(&dir, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join(HOOK_NAME)).unwrap(),
+            HOOK_SCRIPT
+        );
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
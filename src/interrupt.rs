@@ -0,0 +1,55 @@
+//! Ctrl-C handling, so an interrupted run doesn't leave a spinner frame behind.
+
+use crate::error::ExitCode;
+use spinners::Spinner;
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
+};
+
+/// The running spinner, if any, shared with the interrupt handler.
+pub type SharedSpinner = Arc<Mutex<Option<Spinner>>>;
+
+/// Stops the spinner in `spinner`, if there is one, leaving `message` in its place.
+pub fn stop_spinner(spinner: &SharedSpinner, message: &str) {
+    let mut guard = spinner.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(mut sp) = guard.take() {
+        sp.stop_with_message(message.to_string());
+    }
+}
+
+/// Registers a Ctrl-C handler that stops `spinner`, restores the cursor and
+/// exits with `ExitCode::Interrupted`. Must be called inside a Tokio runtime.
+pub fn install_interrupt_handler(spinner: SharedSpinner) -> io::Result<()> {
+    #[cfg(unix)]
+    let mut interrupts = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+    #[cfg(windows)]
+    let mut interrupts = tokio::signal::windows::ctrl_c()?;
+
+    tokio::spawn(async move {
+        if interrupts.recv().await.is_some() {
+            stop_spinner(&spinner, "Interrupted.");
+            let mut stdout = io::stdout();
+            if stdout.is_terminal() {
+                let _ = write!(stdout, "\x1b[?25h");
+                let _ = stdout.flush();
+            }
+            std::process::exit(ExitCode::Interrupted.code());
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn install_interrupt_handler_registers() {
+        let spinner: SharedSpinner = Arc::new(Mutex::new(None));
+        assert!(install_interrupt_handler(spinner.clone()).is_ok());
+        // With no spinner running, stopping is a no-op
+        stop_spinner(&spinner, "Done.");
+        assert!(spinner.lock().unwrap().is_none());
+    }
+}
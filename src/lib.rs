@@ -1,12 +1,288 @@
+pub mod anonymize;
+pub mod cache;
+pub mod changelog;
+pub mod check;
+pub mod config;
+pub mod context;
+pub mod conventional;
+pub mod cost;
+pub mod editor;
+pub mod error;
+pub mod generator;
+pub mod git;
+pub mod gitmoji;
+pub mod heuristic;
+pub mod hook;
+pub mod interrupt;
+pub mod logging;
+pub mod mapreduce;
+pub mod message;
+pub mod postprocess;
+pub mod priority;
+pub mod prompt;
+pub mod redact;
+pub mod review;
+pub mod split;
+pub mod template;
+pub mod ticket;
+pub mod trailers;
+pub mod untracked;
+pub mod watch;
+
+use error::AppError;
+use spinners::Spinners;
+use std::{str::FromStr, time::Duration};
+
 pub fn truncate_to_n_tokens(text: &str, limit: usize) -> String {
     text.split_whitespace().take(limit).collect::<Vec<_>>().join(" ")
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncationReport {
+    pub total_tokens: usize,
+    pub kept_tokens: usize,
+    pub dropped_tokens: usize,
+}
+
+impl TruncationReport {
+    pub fn truncated(&self) -> bool {
+        self.dropped_tokens > 0
+    }
+}
+
+/// Like `truncate_to_n_tokens`, but also reports how many tokens were dropped.
+pub fn truncate_with_report(text: &str, limit: usize) -> (String, TruncationReport) {
+    let total_tokens = text.split_whitespace().count();
+    let kept_tokens = total_tokens.min(limit);
+    let report = TruncationReport {
+        total_tokens,
+        kept_tokens,
+        dropped_tokens: total_tokens - kept_tokens,
+    };
+    (truncate_to_n_tokens(text, limit), report)
+}
+
+pub fn ensure_nonempty_diff(diff: &str) -> Result<(), AppError> {
+    if diff.trim().is_empty() {
+        return Err(AppError::NoStagedChanges);
+    }
+    Ok(())
+}
+
 pub const DEFAULT_MODEL: &str = "gpt-4.1-nano";
 
 pub fn get_model_from_env() -> String {
     std::env::var("AUTO_COMMIT_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string())
+}
+
+/// Values `git diff --diff-algorithm` accepts.
+pub const DIFF_ALGORITHMS: &[&str] = &["patience", "minimal", "histogram", "myers"];
 
+/// Most lines of context `--diff-context` may ask git for around each change.
+pub const MAX_DIFF_CONTEXT: u32 = 100;
+
+/// Where the diff describing the change comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Changes staged in the index (`git diff --staged`).
+    Staged,
+    /// Changes introduced by the last commit, used when amending it.
+    LastCommit,
+    /// Changes on `HEAD` since it forked from a base ref (`git diff <base>...HEAD`).
+    Base(String),
+}
+
+impl DiffSource {
+    pub fn from_flags(amend: bool) -> Self {
+        if amend {
+            DiffSource::LastCommit
+        } else {
+            DiffSource::Staged
+        }
+    }
+
+    /// Arguments to `git` that print the full diff, computed with `algorithm`
+    /// (one of `DIFF_ALGORITHMS`) when given.
+    pub fn diff_args(&self, algorithm: Option<&str>) -> Vec<String> {
+        let mut args: Vec<String> = match self {
+            DiffSource::Staged => vec!["diff".into(), "--staged".into()],
+            DiffSource::LastCommit => vec!["show".into(), "--format=".into(), "HEAD".into()],
+            DiffSource::Base(base) => vec!["diff".into(), base_range(base)],
+        };
+        if let Some(algorithm) = algorithm {
+            args.insert(1, format!("--diff-algorithm={}", algorithm));
+        }
+        args
+    }
+
+    /// Arguments to `git` that print the diffstat.
+    pub fn stat_args(&self) -> Vec<String> {
+        let args = match self {
+            DiffSource::Staged => vec!["diff", "--staged", "--stat"],
+            DiffSource::LastCommit => vec!["show", "--format=", "--stat", "HEAD"],
+            DiffSource::Base(base) => {
+                return vec!["diff".into(), "--stat".into(), base_range(base)]
+            }
+        };
+        args.into_iter().map(str::to_string).collect()
+    }
+
+    /// Arguments to `git` that print the names of the changed files.
+    pub fn files_args(&self) -> Vec<String> {
+        let args = match self {
+            DiffSource::Staged => vec!["diff", "--name-only", "--staged"],
+            DiffSource::LastCommit => vec!["show", "--format=", "--name-only", "HEAD"],
+            DiffSource::Base(base) => {
+                return vec!["diff".into(), "--name-only".into(), base_range(base)]
+            }
+        };
+        args.into_iter().map(str::to_string).collect()
+    }
+}
+
+/// The three-dot range of what `HEAD` added since it forked from `base`.
+fn base_range(base: &str) -> String {
+    format!("{}...HEAD", base)
+}
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request timeout from `--timeout`, then `AUTO_COMMIT_TIMEOUT`, then `DEFAULT_TIMEOUT`.
+pub fn resolve_timeout(cli: Option<u64>) -> Duration {
+    if let Some(secs) = cli {
+        return Duration::from_secs(secs);
+    }
+    match std::env::var("AUTO_COMMIT_TIMEOUT") {
+        Ok(value) => value.trim().parse().map(Duration::from_secs).unwrap_or_else(|_| {
+            log::warn!("Ignoring invalid AUTO_COMMIT_TIMEOUT `{}`.", value);
+            DEFAULT_TIMEOUT
+        }),
+        Err(_) => DEFAULT_TIMEOUT,
+    }
+}
+
+pub const DEFAULT_SYSTEM_PROMPT: &str =
+    "You are an experienced developer who writes great commit messages.";
+
+/// Returns the system prompt, overridden by the file at `AUTO_COMMIT_SYSTEM_PROMPT_FILE`
+/// or the inline `AUTO_COMMIT_SYSTEM_PROMPT`, in that order.
+pub fn resolve_system_prompt() -> String {
+    if let Ok(path) = std::env::var("AUTO_COMMIT_SYSTEM_PROMPT_FILE") {
+        match std::fs::read_to_string(&path) {
+            Ok(prompt) => return prompt.trim().to_string(),
+            Err(e) => log::warn!("Couldn't read system prompt from {}: {}", path, e),
+        }
+    }
+
+    std::env::var("AUTO_COMMIT_SYSTEM_PROMPT")
+        .ok()
+        .filter(|prompt| !prompt.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string())
+}
+
+/// Arguments to `git` that stage changes before generating, mirroring `git commit -a`:
+/// `--all` stages tracked files, `--add-untracked` also picks up new ones.
+pub fn stage_args(all: bool, add_untracked: bool) -> Option<Vec<&'static str>> {
+    match (all, add_untracked) {
+        (_, true) => Some(vec!["add", "-A"]),
+        (true, false) => Some(vec!["add", "-u"]),
+        (false, false) => None,
+    }
+}
+
+/// Arguments to `git` that create the commit, reading the message from stdin.
+/// `verbose` shows the diff below the message in the editor, so it only
+/// applies with `review`.
+pub fn build_commit_args(review: bool, verbose: bool, sign: bool) -> Vec<String> {
+    let mut args = vec!["commit".to_string()];
+    if review {
+        args.push("-e".to_string());
+        if verbose {
+            args.push("--verbose".to_string());
+        }
+    }
+    if sign {
+        args.push("-S".to_string());
+    }
+    args.extend(["-F".to_string(), "-".to_string()]);
+    args
+}
+
+pub const DEFAULT_SPINNER: Spinners = Spinners::Dots;
+
+/// Spinner drawn with plain ASCII, for `--ascii`.
+pub const ASCII_SPINNER: Spinners = Spinners::Line;
+
+/// Maps a spinner name (e.g. `earth`, `box-bounce`, `BoxBounce`) to a spinner,
+/// falling back to `DEFAULT_SPINNER` for missing or unknown names.
+pub fn select_spinner(name: Option<&str>) -> Spinners {
+    let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) else {
+        return DEFAULT_SPINNER;
+    };
+    let pascal: String = name
+        .split(['-', '_', ' '])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    Spinners::from_str(name)
+        .or_else(|_| Spinners::from_str(&pascal))
+        .unwrap_or(DEFAULT_SPINNER)
+}
+
+/// What the spinner says while the message is generated, and once it's done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpinnerText {
+    pub working: String,
+    pub done: String,
+}
+
+/// The spinner's text from `env` (`AUTO_COMMIT_SPINNER_TEXT`,
+/// `AUTO_COMMIT_DONE_TEXT`), then `git_config` (`autocommit.spinnertext`,
+/// `autocommit.donetext`), then the defaults. With `ascii`, custom text is
+/// transliterated like the message is.
+pub fn resolve_spinner_text(
+    ascii: bool,
+    env: impl Fn(&str) -> Option<String>,
+    git_config: impl Fn(&str) -> Option<String>,
+) -> SpinnerText {
+    let lookup = |env_var: &str, git_key: &str, default: &str| {
+        let text = env(env_var)
+            .or_else(|| git_config(git_key))
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+            .unwrap_or_else(|| default.to_string());
+        if ascii {
+            message::to_ascii(&text)
+        } else {
+            text
+        }
+    };
+    SpinnerText {
+        working: lookup(
+            "AUTO_COMMIT_SPINNER_TEXT",
+            "autocommit.spinnertext",
+            "Analyzing code…",
+        ),
+        done: lookup(
+            "AUTO_COMMIT_DONE_TEXT",
+            "autocommit.donetext",
+            "Analysis complete.",
+        ),
+    }
+}
+
+/// Serializes tests that read or modify process environment variables.
+#[cfg(test)]
+pub(crate) fn env_lock() -> std::sync::MutexGuard<'static, ()> {
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
 }
 
 #[cfg(test)]
@@ -19,14 +295,60 @@ mod tests {
         assert_eq!(truncate_to_n_tokens(text, 3), "a b c");
     }
 
+    #[test]
+    fn test_truncate_with_report_truncated() {
+        let (text, report) = truncate_with_report("a b c d e", 3);
+        assert_eq!(text, "a b c");
+        assert_eq!(
+            report,
+            TruncationReport {
+                total_tokens: 5,
+                kept_tokens: 3,
+                dropped_tokens: 2,
+            }
+        );
+        assert!(report.truncated());
+    }
+
+    #[test]
+    fn test_truncate_with_report_not_truncated() {
+        let (text, report) = truncate_with_report("hello world", 5);
+        assert_eq!(text, "hello world");
+        assert_eq!(report.total_tokens, 2);
+        assert_eq!(report.kept_tokens, 2);
+        assert_eq!(report.dropped_tokens, 0);
+        assert!(!report.truncated());
+    }
+
+    #[test]
+    fn test_truncate_with_report_empty() {
+        let (text, report) = truncate_with_report("", 5);
+        assert_eq!(text, "");
+        assert_eq!(report.total_tokens, 0);
+        assert!(!report.truncated());
+    }
+
+    #[test]
+    fn test_ensure_nonempty_diff_accepts_diff() {
+        assert_eq!(ensure_nonempty_diff("diff --git a/a b/a\n+hello"), Ok(()));
+    }
+
+    #[test]
+    fn test_ensure_nonempty_diff_rejects_empty_and_blank() {
+        assert_eq!(ensure_nonempty_diff(""), Err(AppError::NoStagedChanges));
+        assert_eq!(ensure_nonempty_diff(" \n\t\n"), Err(AppError::NoStagedChanges));
+    }
+
     #[test]
     fn test_get_model_from_env_default() {
+        let _env = env_lock();
         std::env::remove_var("AUTO_COMMIT_MODEL");
         assert_eq!(get_model_from_env(), DEFAULT_MODEL);
     }
 
     #[test]
     fn test_get_model_from_env_custom() {
+        let _env = env_lock();
         std::env::set_var("AUTO_COMMIT_MODEL", "custom-model");
         assert_eq!(get_model_from_env(), "custom-model");
         std::env::remove_var("AUTO_COMMIT_MODEL");
@@ -64,6 +386,7 @@ mod tests {
 
     #[test]
     fn test_get_model_from_env_empty_string() {
+        let _env = env_lock();
         std::env::set_var("AUTO_COMMIT_MODEL", "");
         assert_eq!(get_model_from_env(), "");
         std::env::remove_var("AUTO_COMMIT_MODEL");
@@ -71,6 +394,7 @@ mod tests {
 
     #[test]
     fn test_get_model_from_env_whitespace_value() {
+        let _env = env_lock();
         let custom = "   ";
         std::env::set_var("AUTO_COMMIT_MODEL", custom);
         assert_eq!(get_model_from_env(), custom);
@@ -79,10 +403,200 @@ mod tests {
 
     #[test]
     fn test_get_model_from_env_unicode_value() {
+        let _env = env_lock();
         let custom = "模型一";
         std::env::set_var("AUTO_COMMIT_MODEL", custom);
         assert_eq!(get_model_from_env(), custom);
         std::env::remove_var("AUTO_COMMIT_MODEL");
     }
-}
 
+    #[test]
+    fn test_resolve_timeout_default() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_TIMEOUT");
+        assert_eq!(resolve_timeout(None), DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn test_resolve_timeout_env() {
+        let _env = env_lock();
+        std::env::set_var("AUTO_COMMIT_TIMEOUT", "5");
+        assert_eq!(resolve_timeout(None), Duration::from_secs(5));
+        std::env::set_var("AUTO_COMMIT_TIMEOUT", "soon");
+        assert_eq!(resolve_timeout(None), DEFAULT_TIMEOUT);
+        std::env::remove_var("AUTO_COMMIT_TIMEOUT");
+    }
+
+    #[test]
+    fn test_resolve_timeout_cli_wins_over_env() {
+        let _env = env_lock();
+        std::env::set_var("AUTO_COMMIT_TIMEOUT", "5");
+        assert_eq!(resolve_timeout(Some(60)), Duration::from_secs(60));
+        std::env::remove_var("AUTO_COMMIT_TIMEOUT");
+    }
+
+    #[test]
+    fn test_resolve_system_prompt_default() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT");
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE");
+        assert_eq!(resolve_system_prompt(), DEFAULT_SYSTEM_PROMPT);
+    }
+
+    #[test]
+    fn test_resolve_system_prompt_inline_override() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE");
+        std::env::set_var("AUTO_COMMIT_SYSTEM_PROMPT", "Write terse commits.");
+        assert_eq!(resolve_system_prompt(), "Write terse commits.");
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT");
+    }
+
+    #[test]
+    fn test_resolve_system_prompt_file_override() {
+        let _env = env_lock();
+        let path = std::env::temp_dir().join(format!("auto-commit-prompt-{}", std::process::id()));
+        std::fs::write(&path, "Write commits like a pirate.\n").unwrap();
+        std::env::set_var("AUTO_COMMIT_SYSTEM_PROMPT", "Write terse commits.");
+        std::env::set_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE", &path);
+
+        assert_eq!(resolve_system_prompt(), "Write commits like a pirate.");
+
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT");
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_system_prompt_missing_file_falls_back() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT");
+        std::env::set_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE", "/nonexistent/auto-commit/prompt");
+        assert_eq!(resolve_system_prompt(), DEFAULT_SYSTEM_PROMPT);
+        std::env::remove_var("AUTO_COMMIT_SYSTEM_PROMPT_FILE");
+    }
+
+    #[test]
+    fn test_diff_source_from_flags() {
+        assert_eq!(DiffSource::from_flags(false), DiffSource::Staged);
+        assert_eq!(DiffSource::from_flags(true), DiffSource::LastCommit);
+    }
+
+    #[test]
+    fn test_diff_args_pass_the_diff_algorithm() {
+        assert_eq!(
+            DiffSource::Staged.diff_args(Some("histogram")),
+            ["diff", "--diff-algorithm=histogram", "--staged"]
+        );
+        assert_eq!(
+            DiffSource::LastCommit.diff_args(Some("patience")),
+            ["show", "--diff-algorithm=patience", "--format=", "HEAD"]
+        );
+    }
+
+    #[test]
+    fn test_diff_source_args() {
+        assert_eq!(DiffSource::Staged.diff_args(None), ["diff", "--staged"]);
+        assert_eq!(DiffSource::Staged.files_args(), ["diff", "--name-only", "--staged"]);
+        assert_eq!(DiffSource::LastCommit.diff_args(None), ["show", "--format=", "HEAD"]);
+        assert_eq!(DiffSource::Staged.stat_args(), ["diff", "--staged", "--stat"]);
+        assert_eq!(
+            DiffSource::LastCommit.stat_args(),
+            ["show", "--format=", "--stat", "HEAD"]
+        );
+        assert_eq!(
+            DiffSource::LastCommit.files_args(),
+            ["show", "--format=", "--name-only", "HEAD"]
+        );
+    }
+
+    #[test]
+    fn test_base_source_diffs_against_the_merge_base() {
+        let source = DiffSource::Base("main".to_string());
+        assert_eq!(source.diff_args(None), ["diff", "main...HEAD"]);
+        assert_eq!(source.stat_args(), ["diff", "--stat", "main...HEAD"]);
+        assert_eq!(source.files_args(), ["diff", "--name-only", "main...HEAD"]);
+    }
+
+    #[test]
+    fn test_stage_args() {
+        assert_eq!(stage_args(false, false), None);
+        assert_eq!(stage_args(true, false), Some(vec!["add", "-u"]));
+        assert_eq!(stage_args(false, true), Some(vec!["add", "-A"]));
+        assert_eq!(stage_args(true, true), Some(vec!["add", "-A"]));
+    }
+
+    #[test]
+    fn test_build_commit_args_plain() {
+        assert_eq!(build_commit_args(false, false, false), ["commit", "-F", "-"]);
+    }
+
+    #[test]
+    fn test_build_commit_args_review() {
+        assert_eq!(build_commit_args(true, false, false), ["commit", "-e", "-F", "-"]);
+    }
+
+    #[test]
+    fn test_build_commit_args_sign() {
+        assert_eq!(build_commit_args(false, false, true), ["commit", "-S", "-F", "-"]);
+    }
+
+    #[test]
+    fn test_build_commit_args_review_and_sign() {
+        assert_eq!(build_commit_args(true, false, true), ["commit", "-e", "-S", "-F", "-"]);
+    }
+
+    #[test]
+    fn test_build_commit_args_verbose_only_with_review() {
+        assert_eq!(
+            build_commit_args(true, true, false),
+            ["commit", "-e", "--verbose", "-F", "-"]
+        );
+        assert_eq!(
+            build_commit_args(true, true, true),
+            ["commit", "-e", "--verbose", "-S", "-F", "-"]
+        );
+        assert_eq!(build_commit_args(false, true, false), ["commit", "-F", "-"]);
+    }
+
+    #[test]
+    fn test_select_spinner_known_names() {
+        assert_eq!(select_spinner(Some("Earth")).to_string(), "Earth");
+        assert_eq!(select_spinner(Some("earth")).to_string(), "Earth");
+        assert_eq!(select_spinner(Some("box-bounce")).to_string(), "BoxBounce");
+        assert_eq!(select_spinner(Some("bouncing_bar")).to_string(), "BouncingBar");
+    }
+
+    #[test]
+    fn test_select_spinner_fallback() {
+        let default = DEFAULT_SPINNER.to_string();
+        assert_eq!(select_spinner(None).to_string(), default);
+        assert_eq!(select_spinner(Some("")).to_string(), default);
+        assert_eq!(select_spinner(Some("not-a-spinner")).to_string(), default);
+    }
+
+    #[test]
+    fn resolve_spinner_text_defaults() {
+        let text = resolve_spinner_text(false, |_| None, |_| None);
+        assert_eq!(text.working, "Analyzing code…");
+        assert_eq!(text.done, "Analysis complete.");
+        let text = resolve_spinner_text(true, |_| Some("  ".to_string()), |_| None);
+        assert_eq!(text.working, "Analyzing code...");
+    }
+
+    #[test]
+    fn resolve_spinner_text_overrides() {
+        let env = |name: &str| {
+            (name == "AUTO_COMMIT_SPINNER_TEXT").then(|| "Analyse du code…".to_string())
+        };
+        let git_config =
+            |key: &str| (key == "autocommit.donetext").then(|| "Analyse terminée.".to_string());
+        let text = resolve_spinner_text(false, env, git_config);
+        assert_eq!(text.working, "Analyse du code…");
+        assert_eq!(text.done, "Analyse terminée.");
+
+        let text = resolve_spinner_text(true, env, git_config);
+        assert_eq!(text.working, "Analyse du code...");
+        assert_eq!(text.done, "Analyse terminee.");
+    }
+}
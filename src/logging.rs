@@ -0,0 +1,48 @@
+use std::io::{self, Write};
+
+use env_logger::fmt::Formatter;
+use log::Record;
+
+/// Renders `record` as one JSON object, e.g.
+/// `{"level":"warn","msg":"Nothing staged","ts":"2024-05-01T12:00:00Z"}`.
+pub fn json_log_line(record: &Record, ts: &str) -> String {
+    serde_json::json!({
+        "level": record.level().as_str().to_lowercase(),
+        "msg": record.args().to_string(),
+        "ts": ts,
+    })
+    .to_string()
+}
+
+/// An `env_logger` format that writes each record as a line of JSON, for `--log-json`.
+pub fn format_json(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let ts = buf.timestamp().to_string();
+    writeln!(buf, "{}", json_log_line(record, &ts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn json_log_line_is_valid_json() {
+        let line = json_log_line(
+            &Record::builder()
+                .level(Level::Warn)
+                .args(format_args!("Couldn't read \"{}\"\n", "a.txt"))
+                .build(),
+            "2024-05-01T12:00:00Z",
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "level": "warn",
+                "msg": "Couldn't read \"a.txt\"\n",
+                "ts": "2024-05-01T12:00:00Z",
+            })
+        );
+        assert!(!line.contains('\n'));
+    }
+}
@@ -1,38 +1,90 @@
-use async_openai::{
-    config::OpenAIConfig,
-    types::{
-        ChatCompletionRequestSystemMessageArgs,      // system message builder
-        ChatCompletionRequestAssistantMessageArgs,   // assistant message builder
-        ChatCompletionRequestToolMessageArgs,        // tool response builder
-        ChatCompletionMessageToolCall,               // tool-call struct
-        FunctionObject,                              // function definition for tool
-        ChatCompletionTool,                          // tool struct
-        ChatCompletionToolArgs,                      // tool builder
-        CreateChatCompletionRequestArgs,             // request builder
-        FunctionCall, FunctionName,                  // function-call types
-        ChatCompletionToolType,                      // tool types
-        ChatCompletionNamedToolChoice,               // tool-choice struct
-        ChatCompletionToolChoiceOption,              // tool-choice enum
-        Role,                                        // message roles
-    },
-};
-use clap::Parser;
+use clap::{builder::PossibleValuesParser, error::ErrorKind, CommandFactory, Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
-use log::{error, info};
+use log::{debug, error, info, warn, LevelFilter};
 use question::{Answer, Question};
-use rand::seq::SliceRandom; // for .choose()
-use schemars::{
-    gen::{SchemaGenerator, SchemaSettings},
-    JsonSchema,
-};
-use serde_json::json;
-use spinners::{Spinner, Spinners};
+use spinners::Spinner;
 use std::{
-    io::Write,
-    process::{Command, Stdio},
-    str,
+    collections::HashMap,
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, LazyLock, Mutex},
+    time::Instant,
+};
+use auto_commit::{
+    anonymize::anonymize_diff,
+    cache::{self, cache_key},
+    changelog::{build_changelog_prompt, format_changelog, parse_changelog},
+    check::{build_check_prompt, parse_verdict, CHECK_SYSTEM_PROMPT},
+    config::{
+        apply_dotenv, config_dir, config_file_value, load_config, load_dotenv, resolve_settings,
+        resolve_summary_model, write_config, Config, SettingOverrides, Settings, CONFIG_FILE,
+        DOTENV_FILE,
+    },
+    context::{
+        changed_line_count, combine_context, context_stats, diff_token_budget,
+        drop_whitespace_only_hunks, extract_renames, files_from_diff, fit_context, prepend_renames,
+        prepend_stat,
+    },
+    conventional::{has_scope, infer_scope},
+    cost::{count_tokens, estimate_cost, format_usage_line},
+    editor::edit_in_editor,
+    ensure_nonempty_diff,
+    error::{AppError, ExitCode},
+    generator::{
+        fallback_model_from_env, format_model_list, generate_checked, CommitGenerator, GenOptions,
+        Generator, Provider, WithFallbackModel, WithTimeout,
+    },
+    git::{
+        current_branch, decode_git_output, ensure_git_available, git_config_bool, git_config_get,
+        git_config_get_all, git_dir, git_output, is_merge_in_progress, merge_message, message_path,
+        prepare_hint, prepare_message, push, repo_root, run_git, verify_ref, write_message,
+        CommitOptions, GitRepo, SystemGit,
+    },
+    gitmoji::{ensure_gitmoji, starts_with_gitmoji},
+    heuristic::heuristic_commit,
+    hook::install_hook,
+    interrupt::{install_interrupt_handler, stop_spinner, SharedSpinner},
+    logging::format_json,
+    mapreduce::{map_reduce_commit, DEFAULT_CONCURRENCY},
+    message::{
+        apply_affixes, cap_description_lines, enforce_ascii_title_length, enforce_title_length,
+        normalize_bullets, to_ascii, wrap_body, Commit, CommitRules, BODY_WRAP_WIDTH,
+        DEFAULT_MAX_TITLE_LENGTH,
+    },
+    postprocess::run_post_process,
+    priority::{prioritize_diff, priority_globs_from_env},
+    prompt::{
+        build_explain_prompt, build_system_prompt, dominant_extension, extension_hint, interpolate,
+        preset_names, resolve_preset, PromptOptions, PromptPreset,
+    },
+    redact::redact_secrets,
+    resolve_spinner_text, resolve_system_prompt, resolve_timeout,
+    review::{
+        broad_staging_warning, confirm_default, parse_review_action, render_proposal,
+        review_prompt, use_color, AutoCommitLimits, ReviewAction, DEFAULT_MAX_AUTO_FILES,
+        DEFAULT_MAX_AUTO_LINES, DEFAULT_MAX_TOP_DIRS,
+    },
+    select_spinner,
+    split::{build_split_prompt, parse_plan, plan_commands, run_plan_with, STAGED_PATHS_ARGS},
+    stage_args,
+    template::{read_commit_template, read_pr_template},
+    ticket::{extract_issue_number, extract_ticket},
+    trailers::{
+        append_trailer_block, append_trailers, format_coauthors, format_issue_trailers,
+        signoff_trailer,
+    },
+    untracked::build_untracked_context,
+    watch::{diff_hash, index_changed, WATCH_INTERVAL},
+    DiffSource, ASCII_SPINNER, DIFF_ALGORITHMS, MAX_DIFF_CONTEXT,
 };
-use auto_commit::{get_model_from_env, truncate_to_n_tokens};
+
+/// The settings `auto-commit init` wrote, read once as the lowest layer.
+static FILE_CONFIG: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    config_dir()
+        .map(|dir| load_config(&dir.join(CONFIG_FILE)))
+        .unwrap_or_default()
+});
 
 // CLI definition
 #[derive(Parser)]
@@ -41,26 +93,416 @@ use auto_commit::{get_model_from_env, truncate_to_n_tokens};
 #[command(author = "Miguel Piedrafita <soy@miguelpiedrafita.com>")]
 #[command(about = "Automagically generate commit messages.")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
     #[arg(long = "dry-run", help = "Output the generated message, but don't create a commit.")]
     dry_run: bool,
     #[arg(short, long, help = "Edit the generated commit message before committing.")]
     review: bool,
+    #[arg(
+        long = "verbose-commit",
+        requires = "review",
+        help = "With --review, show the diff below the message in the editor, like \
+                `git commit --verbose`; on by default with commit.verbose."
+    )]
+    verbose_commit: bool,
     #[arg(short, long, help = "Don't ask for confirmation before committing.")]
     force: bool,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "review",
+        help = "Answer every prompt with its default, for non-interactive runs; implies --force \
+                and --yes-really."
+    )]
+    yes: bool,
+    #[arg(long = "no-spinner", help = "Don't show a spinner while the message is generated.")]
+    no_spinner: bool,
+    #[arg(long, help = "Regenerate the message of the last commit and amend it.")]
+    amend: bool,
+    #[arg(
+        long = "max-title-length",
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_TITLE_LENGTH,
+        help = "Maximum number of characters in the commit title."
+    )]
+    max_title_length: usize,
+    #[arg(
+        long,
+        value_name = "S",
+        default_value = "",
+        hide_default_value = true,
+        help = "Put S before every title, e.g. \"[PROJ-123] \"; it counts toward the length limit."
+    )]
+    prefix: String,
+    #[arg(
+        long,
+        value_name = "S",
+        default_value = "",
+        hide_default_value = true,
+        help = "Put S after every title; it counts toward the length limit."
+    )]
+    suffix: String,
+    #[arg(
+        long = "max-description-lines",
+        value_name = "N",
+        help = "Cut the description to at most N lines, ending it with `...`."
+    )]
+    max_description_lines: Option<usize>,
+    #[arg(
+        long = "title-only",
+        visible_alias = "no-description",
+        help = "Write a one-line commit: just the title, without a description."
+    )]
+    title_only: bool,
+    #[arg(
+        short,
+        long,
+        help = "Stage modified and deleted files before generating, like `git commit -a`."
+    )]
+    all: bool,
+    #[arg(long = "add-untracked", help = "Also stage untracked files before generating.")]
+    add_untracked: bool,
+    #[arg(long = "no-cache", help = "Always ask the model, even if this diff was seen before.")]
+    no_cache: bool,
+    #[arg(long = "show-context", help = "Print the context sent to the model to stderr.")]
+    show_context: bool,
+    #[arg(
+        long = "context",
+        value_name = "TEXT",
+        help = "Background for the model that the diff doesn't show; repeatable."
+    )]
+    context: Vec<String>,
+    #[arg(
+        long = "context-file",
+        value_name = "PATH",
+        help = "Read background for the model from a file; repeatable."
+    )]
+    context_file: Vec<PathBuf>,
+    #[arg(long, help = "Start the commit title with a gitmoji.")]
+    gitmoji: bool,
+    #[arg(
+        long,
+        conflicts_with = "gitmoji",
+        help = "Keep the message and the spinner to ASCII, transliterating or dropping anything else."
+    )]
+    ascii: bool,
+    #[arg(
+        short = 'S',
+        long,
+        help = "GPG-sign the commit (git also signs when `commit.gpgsign` is set)."
+    )]
+    sign: bool,
+    #[arg(
+        short = 's',
+        long,
+        visible_alias = "sign-off",
+        help = "Add a Signed-off-by trailer from `git config user.name` and `user.email`."
+    )]
+    signoff: bool,
+    #[arg(
+        long,
+        value_name = "SECS",
+        help = "Give up on the model after this many seconds [default: 30]."
+    )]
+    timeout: Option<u64>,
+    #[arg(
+        long = "map-reduce",
+        help = "Summarize each file separately when the diff is too large, then combine the summaries."
+    )]
+    map_reduce: bool,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_CONCURRENCY,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "How many files --map-reduce summarizes at once."
+    )]
+    concurrency: u16,
+    #[arg(
+        long,
+        visible_alias = "no-network",
+        help = "Don't call the API; describe the changed files with a heuristic message."
+    )]
+    offline: bool,
+    #[arg(
+        long = "include-untracked",
+        help = "Describe untracked files too, without staging them (the commit won't include them)."
+    )]
+    include_untracked: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Component being changed, used as the conventional commit scope."
+    )]
+    scope: Option<String>,
+    #[arg(
+        long = "show-cost",
+        help = "With --dry-run, print the estimated API cost of the request to stderr."
+    )]
+    show_cost: bool,
+    #[arg(
+        long = "diff-from-stdin",
+        conflicts_with_all = ["amend", "all", "add_untracked", "include_untracked", "include_stat"],
+        help = "Describe a diff read from stdin instead of running git; implies --dry-run."
+    )]
+    diff_from_stdin: bool,
+    #[arg(
+        long,
+        value_name = "REF",
+        conflicts_with_all = [
+            "amend", "diff_from_stdin", "split", "all", "add_untracked", "include_untracked"
+        ],
+        help = "Describe what HEAD adds since it forked from REF (`git diff REF...HEAD`); \
+                implies --dry-run."
+    )]
+    base: Option<String>,
+    #[arg(long, help = "Write the commit description as a list of bullet points.")]
+    bullets: bool,
+    #[arg(
+        long = "print-prompt",
+        help = "Print the messages sent to the model as JSON to stderr."
+    )]
+    print_prompt: bool,
+    #[arg(
+        long = "co-author",
+        value_name = "NAME <EMAIL>",
+        help = "Add a Co-authored-by trailer; repeat for several co-authors."
+    )]
+    co_author: Vec<String>,
+    #[arg(
+        long,
+        value_name = "KEY: VALUE",
+        help = "Add a trailer such as `Reviewed-by: Name <email>`; repeat for several."
+    )]
+    trailer: Vec<String>,
+    #[arg(
+        long = "include-stat",
+        help = "Put the `git diff --stat` summary in front of the diff sent to the model."
+    )]
+    include_stat: bool,
+    #[arg(long, help = "Model to use; overrides AUTO_COMMIT_MODEL and `autocommit.model`.")]
+    model: Option<String>,
+    #[arg(
+        long = "max-tokens",
+        visible_alias = "response-tokens",
+        help = "Maximum number of tokens in the model's answer; defaults per model."
+    )]
+    max_tokens: Option<u32>,
+    #[arg(long, help = "Language to write the commit message in.")]
+    language: Option<String>,
+    #[arg(
+        long = "max-auto-files",
+        default_value_t = DEFAULT_MAX_AUTO_FILES,
+        help = "With --force, still ask for confirmation when more files than this changed."
+    )]
+    max_auto_files: usize,
+    #[arg(
+        long = "max-auto-lines",
+        default_value_t = DEFAULT_MAX_AUTO_LINES,
+        help = "With --force, still ask for confirmation when more lines than this changed."
+    )]
+    max_auto_lines: usize,
+    #[arg(
+        long = "max-top-dirs",
+        default_value_t = DEFAULT_MAX_TOP_DIRS,
+        help = "Warn when the staged files span more top-level directories than this; 0 turns \
+                the warning off."
+    )]
+    max_top_dirs: usize,
+    #[arg(
+        long = "yes-really",
+        requires = "force",
+        help = "With --force, commit without confirmation even past --max-auto-files/lines."
+    )]
+    yes_really: bool,
+    #[arg(
+        long,
+        value_name = "ISSUE",
+        help = "Add a `Closes #ISSUE` trailer; repeat for several issues."
+    )]
+    closes: Vec<u64>,
+    #[arg(
+        long = "closes-branch-issue",
+        help = "Also close the issue the branch is named after, e.g. `fix/123-crash`."
+    )]
+    closes_branch_issue: bool,
+    #[arg(
+        long = "include-context-from-pr",
+        value_name = "PATH",
+        num_args = 0..=1,
+        help = "Structure the description after the repo's pull request template, \
+                found in the usual places unless PATH is given."
+    )]
+    include_context_from_pr: Option<Option<PathBuf>>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        help = "Write the message to PATH (default .git/COMMIT_EDITMSG) instead of committing."
+    )]
+    write: Option<Option<PathBuf>>,
+    #[arg(
+        long,
+        conflicts_with_all = ["write", "dry_run", "amend"],
+        help = "Save the message to .git/COMMIT_EDITMSG for a manual `git commit`, \
+                never committing."
+    )]
+    prepare: bool,
+    #[arg(
+        long = "diff-algorithm",
+        value_parser = PossibleValuesParser::new(DIFF_ALGORITHMS),
+        help = "Diff algorithm git uses for the diff sent to the model."
+    )]
+    diff_algorithm: Option<String>,
+    #[arg(
+        long = "diff-context",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(0..=MAX_DIFF_CONTEXT as i64),
+        help = "Lines of context around each change in the diff (git's default is 3)."
+    )]
+    diff_context: Option<u32>,
+    #[arg(
+        long = "ignore-whitespace",
+        help = "Leave out changes that only touch whitespace, e.g. reformatting."
+    )]
+    ignore_whitespace: bool,
+    #[arg(
+        long,
+        help = "Replace identifiers in the diff with placeholders like ident_1 before sending it."
+    )]
+    anonymize: bool,
+    #[arg(
+        long,
+        value_parser = parse_preset,
+        help = "Extra instructions for the model: detailed, terse or changelog."
+    )]
+    preset: Option<PromptPreset>,
+    #[arg(
+        long = "no-tools",
+        help = "Send the diff as a plain message, for OpenAI-compatible endpoints without tool calls."
+    )]
+    no_tools: bool,
+    #[arg(long, help = "Log the model, token usage and time taken once the message is generated.")]
+    stats: bool,
+    #[arg(
+        long = "post-process",
+        value_name = "CMD",
+        help = "Pipe the message through CMD and use its output instead, e.g. a spell checker."
+    )]
+    post_process: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "dry_run", "base", "changelog", "diff_from_stdin", "write", "prepare"
+        ],
+        help = "Push the branch to its upstream after committing."
+    )]
+    push: bool,
+    #[arg(
+        long,
+        value_name = "yes|no",
+        value_parser = ["yes", "no"],
+        help = "What an empty answer to the review prompt does; overrides AUTO_COMMIT_CONFIRM_DEFAULT."
+    )]
+    confirm_default: Option<String>,
+    #[arg(long, help = "Log to stderr as JSON lines, for log aggregation.")]
+    log_json: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["amend", "diff_from_stdin", "write", "prepare"],
+        help = "Experimental: let the model split the staged files into several commits."
+    )]
+    split: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["split", "write", "prepare"],
+        help = "Print a Keep a Changelog entry for the changes instead of committing them."
+    )]
+    changelog: bool,
+    #[arg(
+        long,
+        value_parser = parse_seed,
+        help = "Sampling seed, for reproducible messages with OpenAI models."
+    )]
+    seed: Option<i64>,
+}
+
+impl Cli {
+    /// `-q` given: only the commit message goes to stdout, with the spinner
+    /// and the proposal kept out of the way of pipes.
+    fn quiet(&self) -> bool {
+        self.verbose.log_level_filter() < LevelFilter::Info
+    }
 }
 
-// Commit schema
-#[derive(Debug, serde::Deserialize, JsonSchema)]
-struct Commit {
-    title: String,
-    description: String,
+/// Whether a spinner is drawn while the message is generated: only when
+/// logging is silenced, so it never interleaves with log lines.
+fn shows_spinner(cli: &Cli) -> bool {
+    let hide_spinner = cli.dry_run || cli.no_spinner || cli.offline;
+    !hide_spinner && cli.verbose.is_silent()
 }
 
-impl ToString for Commit {
-    fn to_string(&self) -> String {
-        format!("{}\n\n{}", self.title, self.description)
+fn parse_preset(name: &str) -> Result<PromptPreset, String> {
+    resolve_preset(name).ok_or_else(|| format!("expected one of {}", preset_names().join(", ")))
+}
+
+fn parse_seed(value: &str) -> Result<i64, String> {
+    value
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|seed| *seed >= 0)
+        .ok_or_else(|| "expected a non-negative integer".to_string())
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Install a prepare-commit-msg hook that fills in the message with auto-commit.
+    InstallHook {
+        #[arg(short, long, help = "Overwrite an existing prepare-commit-msg hook.")]
+        force: bool,
+    },
+    /// List the chat models available to the configured provider and key.
+    Models,
+    /// Print a prose summary of the staged changes, e.g. for a PR description.
+    Explain,
+    /// Print a fresh commit message proposal whenever the staged changes change,
+    /// until interrupted.
+    Watch,
+    /// Ask the model whether a commit message describes the staged changes; exits
+    /// non-zero if it doesn't.
+    Check {
+        #[arg(short, long, help = "The message to check; read from stdin if not given.")]
+        message: Option<String>,
+    },
+    /// Write ~/.config/auto-commit/config.toml, asking for any value not given as a flag.
+    Init {
+        #[arg(long, help = "Model to generate messages with.")]
+        model: Option<String>,
+        #[arg(long = "api-key", help = "API key for the model provider.")]
+        api_key: Option<String>,
+        #[arg(long = "max-tokens", help = "Maximum number of tokens in the model's answer.")]
+        max_tokens: Option<u32>,
+        #[arg(long, help = "Language to write commit messages in.")]
+        language: Option<String>,
+        #[arg(short, long, help = "Overwrite an existing config file.")]
+        force: bool,
+    },
+}
+
+impl Commands {
+    /// The subcommand as it's typed.
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::InstallHook { .. } => "install-hook",
+            Commands::Models => "models",
+            Commands::Explain => "explain",
+            Commands::Watch => "watch",
+            Commands::Check { .. } => "check",
+            Commands::Init { .. } => "init",
+        }
     }
 }
 
@@ -69,221 +511,1229 @@ const MAX_DIFF_TOKENS: usize = 20_000;
 #[tokio::main]
 async fn main() -> Result<(), ()> {
     // Parse CLI and init logging
-    let cli = Cli::parse();
-    env_logger::Builder::new()
+    let mut cli = Cli::parse();
+    // None of the subcommands commit, so there'd be nothing to push
+    if let Some(command) = cli.command.as_ref().filter(|_| cli.push) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!("--push can't be used with {}, which never commits", command.name()),
+            )
+            .exit();
+    }
+    // There's nothing staged to commit a piped-in or branch diff with, and explaining never commits
+    if cli.diff_from_stdin
+        || cli.base.is_some()
+        || cli.changelog
+        || matches!(cli.command, Some(Commands::Explain))
+    {
+        cli.dry_run = true;
+    }
+    apply_yes(&mut cli);
+    // Diagnostics go to stderr, leaving stdout to the message
+    let mut logger = env_logger::Builder::new();
+    logger
         .filter_level(cli.verbose.log_level_filter())
-        .init();
+        .target(env_logger::Target::Stderr);
+    if cli.log_json {
+        logger.format(format_json);
+    }
+    logger.init();
+    if cli.command.is_none() && may_prompt(&cli) && !std::io::stdin().is_terminal() {
+        warn!("stdin isn't a terminal, so prompts may not be answerable; pass --yes to skip them.");
+    }
 
-    // Ensure API key
-    let api_token = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| {
-        error!("Please set the OPENAI_API_KEY environment variable.");
-        std::process::exit(1);
-    });
+    // A project `.env` fills in variables that aren't exported
+    apply_dotenv(load_dotenv(&repo_root().unwrap_or_default().join(DOTENV_FILE)));
+    // The config file's key is used only when the environment has none
+    if let (Some(key), Ok(provider)) = (FILE_CONFIG.get("api_key"), Provider::from_env()) {
+        if !key.is_empty() && std::env::var_os(provider.api_keys_var()).is_none() {
+            apply_dotenv(HashMap::from([(provider.api_key_var().to_string(), key.clone())]));
+        }
+    }
 
-    // Gather staged diff
-    let git_staged_cmd = Command::new("git")
-        .args(["diff", "--staged"])
-        .output()
-        .map_err(|e| {
-            error!("Failed to get staged diff: {}", e);
-            ()
-        })?
-        .stdout;
-    let git_staged = std::str::from_utf8(&git_staged_cmd).unwrap_or("");
-    if git_staged.is_empty() {
-        error!("No staged files – try `git add`.");
+    let needs_git = match cli.command {
+        Some(Commands::InstallHook { .. } | Commands::Watch) => true,
+        Some(Commands::Explain | Commands::Check { .. }) => !cli.diff_from_stdin,
+        Some(_) => false,
+        None => !cli.diff_from_stdin,
+    };
+    if needs_git {
+        ensure_git_available().unwrap_or_else(|e| exit_with(e));
     }
 
-    // Verify Git repo
-    let is_repo = Command::new("git")
-        .args(["rev-parse", "--is-inside-work-tree"])
-        .output()
-        .map_err(|e| {
-            error!("Failed repo check: {}", e);
-            ()
-        })?
-        .stdout;
-    if std::str::from_utf8(&is_repo).unwrap_or("") != "true\n" {
-        error!("Not in a git repo; run from the root or `git init`.");
-        std::process::exit(1);
+    match &cli.command {
+        Some(Commands::InstallHook { force }) => return install_hook_command(*force),
+        Some(Commands::Init {
+            model,
+            api_key,
+            max_tokens,
+            language,
+            force,
+        }) => {
+            let ask = |label: &str, hint: &str| {
+                if cli.yes {
+                    None
+                } else {
+                    ask(label, hint)
+                }
+            };
+            // The key is never echoed back, only noted as already set
+            let key_var = Provider::from_env().map_or("OPENAI_API_KEY", Provider::api_key_var);
+            let key_hint = match std::env::var(key_var) {
+                Ok(key) if !key.is_empty() => "keep existing",
+                _ => "",
+            };
+            let config = Config {
+                model: model.clone().or_else(|| ask("Model", "provider default")),
+                api_key: api_key.clone().or_else(|| ask("API key", key_hint)),
+                max_tokens: *max_tokens,
+                language: language.clone().or_else(|| ask("Language", "model's choice")),
+            };
+            return init_command(&config, *force);
+        }
+        Some(Commands::Models) => return models_command().await,
+        Some(Commands::Explain) => return explain_command(&cli).await,
+        Some(Commands::Watch) => return watch_command(&cli).await,
+        Some(Commands::Check { message }) => return check_command(&cli, message.as_deref()).await,
+        None => {}
     }
 
-    // Build OpenAI client
-    let client = async_openai::Client::with_config(OpenAIConfig::new().with_api_key(api_token));
+    if let Err(e) = format_coauthors(&cli.co_author) {
+        exit_with(AppError::Config(e));
+    }
+    if let Err(e) = append_trailers("", &custom_trailers(&cli)) {
+        exit_with(AppError::Config(e));
+    }
+    if cli.split {
+        return split_command(&cli).await;
+    }
+    if cli.changelog {
+        return changelog_command(&cli).await;
+    }
+    if !cli.diff_from_stdin && !cli.amend && cli.base.is_none() {
+        if let Some(dir) = git_dir().filter(|dir| is_merge_in_progress(dir)) {
+            return merge_commit(&cli, &dir).await;
+        }
+    }
 
-    // Prepare diff context
-    let files = Command::new("git")
-        .args(["diff", "--name-only", "--staged"])
-        .output()
-        .map_err(|e| {
-            error!("Couldn't get file list: {}", e);
-            ()
-        })?
-        .stdout;
-    let files = std::str::from_utf8(&files).unwrap_or("");
-    let diff = git_staged; // already UTF-8
-    let combined = format!("Changed files:\n{}\n\nDiff:\n{}", files, diff);
-    let context = truncate_to_n_tokens(&combined, MAX_DIFF_TOKENS);
-
-    // Optional spinner when silent
-    let spinner = if !cli.dry_run && cli.verbose.is_silent() {
-        let choices = [
-            Spinners::Earth,
-            Spinners::Aesthetic,
-            Spinners::Hearts,
-            Spinners::BoxBounce,
-            Spinners::BouncingBar,
-            // … add others …
-        ];
-        Some(Spinner::new(
-            *choices[..].choose(&mut rand::rng()).unwrap(),
-            "Analyzing code…".into(),
-        ))
-    } else {
+    // Pick the model backend and ensure its API key
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    if cli.seed.is_some() && provider == Provider::Anthropic {
+        warn!("Anthropic models don't take a seed; --seed is ignored.");
+    }
+    let generator = if cli.offline {
         None
+    } else {
+        Some(build_generator(&cli, provider))
     };
 
-    // Generate JSON schema for Commit
-    let mut gen = SchemaGenerator::new(SchemaSettings::openapi3().with(|s| s.inline_subschemas = true));
-    let commit_schema = gen.subschema_for::<Commit>().into_object();
-
-    // Construct messages
-    let messages = vec![
-        // System prompt
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content("You are an experienced developer who writes great commit messages.".to_string())
-            .build()
-            .unwrap()
-            .into(),
-        // Assistant invokes get_diff tool
-        ChatCompletionRequestAssistantMessageArgs::default()
-            .tool_calls(vec![ChatCompletionMessageToolCall {
-                id: "call_get_diff".to_string(),
-                r#type: ChatCompletionToolType::Function,
-                function: FunctionCall {
-                    name: "get_diff".to_string(),
-                    arguments: "{}".to_string(),
-                },
-            }])
-            .build()
-            .unwrap()
-            .into(),
-        // Tool returns diff
-        ChatCompletionRequestToolMessageArgs::default()
-            .tool_call_id("call_get_diff".to_string())
-            .content(context.clone())
-            .build()
-            .unwrap()
-            .into(),
-    ];
-
-    // Declare tools
-    let tools = vec![
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(FunctionObject {
-                name: "get_diff".to_string(),
-                description: Some("Returns the output of `git diff HEAD` as a string.".to_string()),
-                parameters: Some(json!({ "type": "object", "properties": {} })),
-                strict: None,
-            })
-            .build()
-            .expect("Failed to build 'get_diff' tool"),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(FunctionObject {
-                name: "commit".to_string(),
-                description: Some("Creates a commit with the given title and a description.".to_string()),
-                parameters: Some(
-                    serde_json::to_value(commit_schema)
-                        .expect("Failed to serialize commit_schema for 'commit' tool"),
-                ),
-                strict: None,
-            })
-            .build()
-            .unwrap(),
-    ];
-
-    // Send request, forcing the "commit" tool
-    let completion = client
-        .chat()
-        .create(
-            CreateChatCompletionRequestArgs::default()
-                .model(&get_model_from_env())
-                .messages(messages)
-                .tools(tools)
-                .tool_choice(ChatCompletionToolChoiceOption::Named(
-                    ChatCompletionNamedToolChoice {
-                        r#type: ChatCompletionToolType::Function,
-                        function: FunctionName {
-                            name: "commit".to_string(),
-                        },
-                    },
-                ))
-                .temperature(0.0)
-                .max_tokens(2000u16)
-                .build()
-                .unwrap(),
-        )
-        .await
-        .expect("Completion failed");
+    let source = diff_source(&cli);
+    let repo = system_git(&cli, source.clone());
+    let (files, diff) = if cli.diff_from_stdin {
+        read_stdin_diff()
+    } else {
+        git_diff(&cli, &repo, &source)?
+    };
+    let diff = clean_diff(&cli, &diff);
 
-    // Stop spinner
-    if let Some(sp) = spinner {
-        sp.stop_with_message("Analysis complete.".into());
+    if source == DiffSource::Staged {
+        let files: Vec<&str> = files.lines().collect();
+        if let Some(warning) = broad_staging_warning(&files, cli.max_top_dirs) {
+            warn!("{}", warning);
+        }
     }
 
-    // Parse commit message from the first tool call in the assistant’s response
-    let tool_call = &completion.choices[0].message.tool_calls.as_ref().unwrap()[0];
-    let commit_args_json = &tool_call.function.arguments;
-    let commit_msg = serde_json::from_str::<Commit>(commit_args_json)
-        .expect("Failed to parse commit JSON")
-        .to_string();
+    // Settings for the request, and how much of the diff fits next to them
+    let opts = commit_options(&cli, provider, &files);
+    let limit = diff_token_budget(&opts).min(MAX_DIFF_TOKENS);
+
+    let stat = if cli.include_stat {
+        git_output(&source.stat_args().iter().map(String::as_str).collect::<Vec<_>>())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let renames = extract_renames(&diff);
+    let frame = |diff: &str| {
+        prepend_renames(&renames, &prepend_stat(&stat, &combine_context(&files, diff)))
+    };
+    let priority_globs = priority_globs_from_env();
+    let combined = if priority_globs.is_empty() {
+        frame(&diff)
+    } else {
+        let budget = limit.saturating_sub(frame("").split_whitespace().count());
+        frame(&prioritize_diff(&diff, &priority_globs, budget))
+    };
+    let (context, report) = fit_context(&opts.model, &read_background(&cli), &combined, limit);
+    if report.truncated() {
+        warn!(
+            "diff truncated: {} of {} tokens dropped",
+            report.dropped_tokens, report.total_tokens
+        );
+    }
+    if cli.show_context {
+        let stats = context_stats(&combined, &context);
+        eprintln!("{}", context);
+        eprintln!(
+            "\n--- {} of {} tokens sent{} ---",
+            stats.sent_tokens,
+            stats.original_tokens,
+            if stats.truncated { " (truncated)" } else { "" }
+        );
+    }
+
+    let spinner: SharedSpinner = Arc::new(Mutex::new(None));
+    let spinner_text =
+        resolve_spinner_text(cli.ascii, |name| std::env::var(name).ok(), git_config_get);
+    if let Err(e) = install_interrupt_handler(spinner.clone()) {
+        debug!("Couldn't install the Ctrl-C handler: {}", e);
+    }
+    if shows_spinner(&cli) {
+        let name = std::env::var("AUTO_COMMIT_SPINNER").ok();
+        let sp = if cli.ascii {
+            Spinner::new(ASCII_SPINNER, spinner_text.working.clone())
+        } else {
+            Spinner::new(select_spinner(name.as_deref()), spinner_text.working.clone())
+        };
+        *spinner.lock().unwrap_or_else(|e| e.into_inner()) = Some(sp);
+    }
+
+    if cli.print_prompt && !cli.offline {
+        match provider.prompt_json(&context, &opts) {
+            Ok(json) => eprintln!("{}", json),
+            Err(e) => warn!("Couldn't print the prompt: {}", e),
+        }
+    }
+    // Look for a message generated earlier for the same diff and settings
+    let map_reduce = cli.map_reduce && report.truncated();
+    let summary_model =
+        resolve_summary_model(&opts.model, |name| std::env::var(name).ok(), git_config_get);
+    let cache_dir = cache::cache_dir();
+    let key = commit_cache_key(&opts, &context, map_reduce.then_some((&summary_model, &diff)));
+    let cached = if cli.no_cache || cli.offline {
+        None
+    } else {
+        cache_dir
+            .as_deref()
+            .and_then(|dir| cache::load(dir, &key))
+            .and_then(|json| serde_json::from_str::<Commit>(&json).ok())
+    };
+
+    let commit = if let Some(commit) = cached {
+        debug!("Using cached commit message {}", key);
+        commit
+    } else if let Some(generator) = &generator {
+        let started = Instant::now();
+        let commit = if map_reduce {
+            info!("The diff is too large, summarizing it file by file.");
+            let concurrency = cli.concurrency.into();
+            let rules = commit_rules(&cli);
+            map_reduce_commit(generator, &diff, &opts, &summary_model, limit, concurrency, &rules)
+                .await
+                .unwrap_or_else(|e| exit_with(e))
+        } else {
+            request_commit(generator, &context, &opts, &commit_rules(&cli)).await
+        };
+        if cli.stats {
+            info!("{}", format_usage_line(generator.usage(), &opts.model, started.elapsed()));
+        }
+        if let Some(dir) = &cache_dir {
+            let json = serde_json::to_string(&commit).expect("Failed to serialize commit");
+            if let Err(e) = cache::store(dir, &key, &json) {
+                debug!("Couldn't cache commit message: {}", e);
+            }
+        }
+        commit
+    } else {
+        heuristic_commit(&files.lines().collect::<Vec<_>>(), &diff)
+    };
+
+    stop_spinner(&spinner, &spinner_text.done);
+
+    let mut commit_msg = post_process(&cli, finalize_commit(&cli, commit));
+
+    // Leave the message for a later `git commit` or an editor
+    if let Some(path) = &cli.write {
+        let Some(path) = message_path(path.as_deref(), git_dir) else {
+            exit_with(AppError::NotAGitRepo);
+        };
+        if let Err(e) = write_message(&path, &commit_msg) {
+            error!("Couldn't write the message to {}: {}", path.display(), e);
+            std::process::exit(ExitCode::Generic.code());
+        }
+        info!("Wrote the commit message to {}.", path.display());
+        return Ok(());
+    }
+    if cli.prepare {
+        let Some(git_dir) = git_dir() else {
+            exit_with(AppError::NotAGitRepo);
+        };
+        prepare_commit(&git_dir, &commit_msg);
+        return Ok(());
+    }
 
     // Dry-run or actual commit
     if cli.dry_run {
-        info!("{}", commit_msg);
+        // Preview the edited message without committing it
+        if cli.review {
+            match edit_in_editor(&commit_msg) {
+                Ok(edited) => commit_msg = edited,
+                Err(e) => warn!("Couldn't open the editor, printing the generated message: {}", e),
+            }
+        }
+        println!("{}", commit_msg);
+        if cli.show_cost && !cli.offline {
+            print_cost(&opts, &context, &commit_msg);
+        }
+        return Ok(());
+    }
+
+    // Let the user commit, edit, regenerate or abort; large changes need it even with --force
+    let limits = AutoCommitLimits {
+        max_files: cli.max_auto_files,
+        max_lines: cli.max_auto_lines,
+    };
+    let (file_count, line_count) = (files.lines().count(), changed_line_count(&diff));
+    if limits.needs_confirmation(file_count, line_count, cli.force, cli.yes_really) {
+        if cli.force {
+            warn!(
+                "This change touches {} files and {} lines; confirm it, or add --yes-really.",
+                file_count, line_count
+            );
+        }
+        commit_msg = review(&cli, generator.as_ref(), &context, &opts, commit_msg).await;
+    }
+
+    // Perform the git commit
+    commit_changes(&repo, &cli, &commit_msg);
+    if cli.quiet() && !cli.force {
+        println!("{}", commit_msg);
+    }
+    if cli.push {
+        let out = push().unwrap_or_else(|e| exit_with(e));
+        info!("{}", out);
+    }
+    Ok(())
+}
+
+/// Concludes an in-progress merge with the message git prepared for it: a
+/// generated message would describe the merged changes rather than the merge.
+async fn merge_commit(cli: &Cli, git_dir: &Path) -> Result<(), ()> {
+    warn!("A merge is in progress; using git's merge message instead of generating one.");
+    let Some(message) = merge_message(git_dir) else {
+        error!("There's no prepared merge message; conclude the merge with `git commit`.");
+        std::process::exit(ExitCode::Generic.code());
+    };
+    let mut message = append_trailers(&message, &custom_trailers(cli))
+        .unwrap_or_else(|e| exit_with(AppError::Config(e)));
+
+    if cli.dry_run {
+        println!("{}", message);
+        return Ok(());
+    }
+    if cli.prepare {
+        prepare_commit(git_dir, &message);
         return Ok(());
     }
-    info!("Proposed Commit:\n{}\n", commit_msg);
     if !cli.force {
-        if Question::new("Commit? (Y/n)")
-            .yes_no()
-            .until_acceptable()
-            .default(Answer::YES)
-            .ask()
-            .unwrap()
-            == Answer::NO
-        {
-            error!("Aborted.");
-            std::process::exit(1);
+        let opts = GenOptions::default();
+        message = review(cli, None::<&Generator>, "", &opts, message).await;
+    }
+    let repo = SystemGit::new(DiffSource::Staged, None, false);
+    commit_changes(&repo, cli, &message);
+    if cli.push {
+        let out = push().unwrap_or_else(|e| exit_with(e));
+        info!("{}", out);
+    }
+    Ok(())
+}
+
+/// `--prepare`: leaves `message` in `COMMIT_EDITMSG` and says how to commit it.
+fn prepare_commit(git_dir: &Path, message: &str) {
+    match prepare_message(git_dir, message) {
+        Ok(path) => {
+            info!("Wrote the commit message to {}.", path.display());
+            info!("{}", prepare_hint(&path));
+        }
+        Err(e) => {
+            error!("Couldn't write the message to {}: {}", git_dir.display(), e);
+            std::process::exit(ExitCode::Generic.code());
         }
     }
+}
 
-    // Perform the git commit
-    let mut proc_commit = Command::new("git")
-        .arg("commit")
-        .args(if cli.review { vec!["-e"] } else { vec![] })
-        .arg("-F")
-        .arg("-")
-        .stdin(Stdio::piped())
-        .spawn()
-        .unwrap();
-    let mut stdin = proc_commit.stdin.take().unwrap();
-    std::thread::spawn(move || {
-        stdin.write_all(commit_msg.as_bytes()).unwrap();
+/// The request settings and commit-writing system prompt for changes to `files`.
+fn commit_options(cli: &Cli, provider: Provider, files: &str) -> GenOptions {
+    let settings = cli_settings(cli, provider);
+    let branch = current_branch();
+    let files: Vec<&str> = files.lines().collect();
+    // Values a custom system prompt can refer to as `{branch}`, `{files}` and `{model}`
+    let pr_template = cli.include_context_from_pr.as_ref().and_then(|path| {
+        let template = read_pr_template(path.as_deref());
+        if template.is_none() {
+            warn!("No pull request template found; the description keeps its usual structure.");
+        }
+        template
     });
-    let out = proc_commit.wait_with_output().unwrap();
-    info!("{}", str::from_utf8(&out.stdout).unwrap());
+    let vars = HashMap::from([
+        ("branch", branch.clone().unwrap_or_default()),
+        ("files", files.join(", ")),
+        ("model", settings.model.clone()),
+    ]);
+    GenOptions {
+        model: settings.model,
+        system_prompt: build_system_prompt(
+            &interpolate(&resolve_system_prompt(), &vars),
+            &PromptOptions {
+                max_title_length: title_budget(cli),
+                gitmoji: cli.gitmoji,
+                template: read_commit_template(),
+                pr_template,
+                ticket: branch.as_deref().and_then(extract_ticket),
+                scope: cli.scope.clone(),
+                suggested_scope: infer_scope(&files),
+                bullets: cli.bullets,
+                language: settings.language,
+                preset: cli.preset,
+                file_hint: dominant_extension(&files)
+                    .and_then(|extension| extension_hint(&extension)),
+                max_description_lines: cli.max_description_lines,
+                title_only: cli.title_only,
+            },
+        ),
+        max_tokens: settings.max_tokens,
+        no_tools: cli.no_tools,
+        seed: cli.seed,
+    }
+}
 
+/// The `git` executable reading `source` with the diff flags from the command line.
+fn system_git(cli: &Cli, source: DiffSource) -> SystemGit {
+    SystemGit::new(source, cli.diff_algorithm.as_deref(), cli.ignore_whitespace)
+        .with_context_lines(cli.diff_context)
+}
+
+/// Prints a fresh proposal each time the staged changes change, until interrupted.
+async fn watch_command(cli: &Cli) -> Result<(), ()> {
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = build_generator(cli, provider);
+    let repo = system_git(cli, DiffSource::Staged);
+    if !repo.is_inside_work_tree() {
+        exit_with(AppError::NotAGitRepo);
+    }
+    info!("Watching the staged changes; press Ctrl-C to stop.");
+
+    let mut prev_hash = None;
+    loop {
+        let diff = repo.staged_diff().map_err(|e| {
+            error!("Failed to get staged diff: {}", e);
+        })?;
+        let hash = diff_hash(&diff);
+        if index_changed(prev_hash, hash) {
+            prev_hash = Some(hash);
+            if diff.trim().is_empty() {
+                info!("Nothing is staged; waiting for changes.");
+            } else {
+                let files = repo.staged_files().map_err(|e| {
+                    error!("Couldn't get file list: {}", e);
+                })?;
+                let files = files.join("\n");
+                let diff = clean_diff(cli, &diff);
+                let opts = commit_options(cli, provider, &files);
+                let context = fitted_context(cli, &files, &diff, &opts);
+                match generate_checked(&generator, &context, &opts, &commit_rules(cli)).await {
+                    Ok(commit) => {
+                        println!("{}\n", post_process(cli, finalize_commit(cli, commit)))
+                    }
+                    // Keep watching; the next change gets another try
+                    Err(e) => error!("{}", e),
+                }
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+/// The backend for `provider`, falling back to `AUTO_COMMIT_FALLBACK_MODEL` when
+/// the model is unavailable and giving up after the timeout; exits if it can't
+/// be set up.
+fn build_generator(cli: &Cli, provider: Provider) -> WithTimeout<WithFallbackModel<Generator>> {
+    let generator = Generator::from_env(provider).unwrap_or_else(|e| exit_with(e));
+    WithTimeout::new(
+        WithFallbackModel::new(generator, fallback_model_from_env()),
+        resolve_timeout(cli.timeout),
+    )
+}
+
+/// The model settings from the flags, environment and git config.
+fn cli_settings(cli: &Cli, provider: Provider) -> Settings {
+    resolve_settings(
+        &SettingOverrides {
+            model: cli.model.clone(),
+            max_tokens: cli.max_tokens,
+            language: cli.language.clone(),
+        },
+        provider.default_model(),
+        |var| std::env::var(var).ok(),
+        |key| git_config_get(key).or_else(|| config_file_value(&FILE_CONFIG, key)),
+    )
+}
+
+/// Prints a prose explanation of the changes instead of committing them.
+async fn explain_command(cli: &Cli) -> Result<(), ()> {
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = build_generator(cli, provider);
+
+    let (files, diff) = read_diff(cli)?;
+    let settings = cli_settings(cli, provider);
+    let opts = GenOptions {
+        model: settings.model,
+        system_prompt: build_explain_prompt(),
+        max_tokens: settings.max_tokens,
+        no_tools: cli.no_tools,
+        seed: cli.seed,
+    };
+    let context = fitted_context(cli, &files, &diff, &opts);
+
+    match generator.complete(&context, &opts).await {
+        Ok(explanation) => {
+            println!("{}", explanation.trim());
+            Ok(())
+        }
+        Err(e) => exit_with(e),
+    }
+}
+
+/// Prints a changelog entry for the changes instead of committing them.
+async fn changelog_command(cli: &Cli) -> Result<(), ()> {
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = build_generator(cli, provider);
+
+    let (files, diff) = read_diff(cli)?;
+    let settings = cli_settings(cli, provider);
+    let opts = GenOptions {
+        model: settings.model,
+        system_prompt: build_changelog_prompt(),
+        max_tokens: settings.max_tokens,
+        no_tools: cli.no_tools,
+        seed: cli.seed,
+    };
+    let context = fitted_context(cli, &files, &diff, &opts);
+
+    let reply = generator.complete(&context, &opts).await.unwrap_or_else(|e| exit_with(e));
+    let entry = parse_changelog(&reply).unwrap_or_else(|e| exit_with(e));
+    println!("{}", format_changelog(&entry));
+    Ok(())
+}
+
+async fn check_command(cli: &Cli, message: Option<&str>) -> Result<(), ()> {
+    let message = match message {
+        Some(message) => message.to_string(),
+        None if cli.diff_from_stdin => exit_with(AppError::Config(
+            "The diff is read from stdin, so pass the message with --message.".to_string(),
+        )),
+        None => {
+            let mut message = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut message) {
+                error!("Failed to read the message from stdin: {}", e);
+                std::process::exit(ExitCode::Generic.code());
+            }
+            message
+        }
+    };
+    if message.trim().is_empty() {
+        exit_with(AppError::Config("There's no commit message to check.".to_string()));
+    }
+
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = build_generator(cli, provider);
+
+    let (files, diff) = read_diff(cli)?;
+    let settings = cli_settings(cli, provider);
+    let opts = GenOptions {
+        model: settings.model,
+        system_prompt: CHECK_SYSTEM_PROMPT.to_string(),
+        max_tokens: settings.max_tokens,
+        no_tools: cli.no_tools,
+        seed: cli.seed,
+    };
+    let context = fitted_context(cli, &files, &diff, &opts);
+
+    let reply = generator
+        .complete(&build_check_prompt(&message, &context), &opts)
+        .await
+        .unwrap_or_else(|e| exit_with(e));
+    let verdict = parse_verdict(&reply).unwrap_or_else(|e| exit_with(e));
+    if verdict.matches {
+        eprintln!("The message matches the diff: {}", verdict.reason);
+        Ok(())
+    } else {
+        eprintln!("The message doesn't match the diff: {}", verdict.reason);
+        std::process::exit(ExitCode::Generic.code());
+    }
+}
+
+/// `--split`: asks the model for a commit plan, then stages and commits each group.
+async fn split_command(cli: &Cli) -> Result<(), ()> {
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = build_generator(cli, provider);
+
+    let (files, diff) = read_diff(cli)?;
+    // Both sides of a rename, so the plan can't drop the deletion
+    let files = git_output(STAGED_PATHS_ARGS).unwrap_or(files);
+    let settings = cli_settings(cli, provider);
+    let opts = GenOptions {
+        model: settings.model,
+        system_prompt: build_split_prompt(),
+        max_tokens: settings.max_tokens,
+        no_tools: cli.no_tools,
+        seed: cli.seed,
+    };
+    let context = fitted_context(cli, &files, &diff, &opts);
+
+    let reply = generator.complete(&context, &opts).await.unwrap_or_else(|e| exit_with(e));
+    let staged: Vec<&str> = files.lines().collect();
+    let plan = parse_plan(&reply, &staged).unwrap_or_else(|e| exit_with(e));
+    let messages: Vec<String> = plan
+        .commits
+        .iter()
+        .map(|planned| {
+            let commit = Commit {
+                title: planned.title.clone(),
+                description: planned.description.clone(),
+            };
+            post_process(cli, finalize_commit(cli, commit))
+        })
+        .collect();
+
+    for (planned, message) in plan.commits.iter().zip(&messages) {
+        eprintln!("{}\n\n{}\n", planned.files.join("\n"), message);
+    }
+    if cli.dry_run {
+        return Ok(());
+    }
+    if !cli.force {
+        let answer = prompt_line(&format!("Make these {} commits? [y/n]", messages.len()));
+        if parse_review_action(&answer, review_default(cli)) != Some(ReviewAction::Commit) {
+            error!("Aborted.");
+            std::process::exit(ExitCode::Generic.code());
+        }
+    }
+
+    // Each group's commit is built from the staged versions saved here
+    let Some(tree) = git_output(&["write-tree"]) else {
+        exit_with(AppError::Split("couldn't save the staged changes".to_string()));
+    };
+    let commands = plan_commands(&plan, &messages, cli.sign, &tree);
+    run_plan_with(&commands, &tree, run_git).unwrap_or_else(|e| exit_with(e));
+    info!("Made {} commits.", messages.len());
+    if cli.push {
+        let out = push().unwrap_or_else(|e| exit_with(e));
+        info!("{}", out);
+    }
     Ok(())
 }
 
+/// `diff` with whitespace-only changes and secrets taken out, and identifiers
+/// replaced with `--anonymize`, as the flags ask.
+fn clean_diff(cli: &Cli, diff: &str) -> String {
+    let diff = if cli.ignore_whitespace {
+        drop_whitespace_only_hunks(diff)
+    } else {
+        diff.to_string()
+    };
+    let (diff, redacted) = redact_secrets(&diff);
+    if redacted > 0 {
+        warn!("Redacted {} likely secret(s) from the diff before sending it.", redacted);
+    }
+    if cli.anonymize {
+        anonymize_diff(&diff)
+    } else {
+        diff
+    }
+}
+
+/// Cache key for the commit generated under `opts` from `context`. Map-reduce
+/// summarizes the whole diff rather than the truncated context, so with
+/// `map_reduce` set to its summary model and diff, both are part of the key.
+fn commit_cache_key(opts: &GenOptions, context: &str, map_reduce: Option<(&str, &str)>) -> String {
+    let input = match map_reduce {
+        Some((summary_model, diff)) => format!(
+            "map-reduce {}\n{}\n\n{}\n\n{}",
+            summary_model, opts.system_prompt, context, diff
+        ),
+        None => format!("{}\n\n{}", opts.system_prompt, context),
+    };
+    cache_key(&input, &opts.model)
+}
+
+/// The changed files and diff the subcommands describe, from stdin or git,
+/// cleaned up by `clean_diff`.
+fn read_diff(cli: &Cli) -> Result<(String, String), ()> {
+    let source = diff_source(cli);
+    let repo = system_git(cli, source.clone());
+    let (files, diff) = if cli.diff_from_stdin {
+        read_stdin_diff()
+    } else {
+        git_diff(cli, &repo, &source)?
+    };
+    let diff = clean_diff(cli, &diff);
+    Ok((files, diff))
+}
+
+/// The context for `files` and `diff`, after any `--context` background and
+/// truncated to what fits next to `opts`.
+fn fitted_context(cli: &Cli, files: &str, diff: &str, opts: &GenOptions) -> String {
+    let limit = diff_token_budget(opts).min(MAX_DIFF_TOKENS);
+    let context = prepend_renames(&extract_renames(diff), &combine_context(files, diff));
+    let (context, report) = fit_context(&opts.model, &read_background(cli), &context, limit);
+    if report.truncated() {
+        warn!("The diff was truncated to fit the model's context window.");
+    }
+    context
+}
+
+/// The `--context-file` contents, then the `--context` texts, exiting if a file
+/// can't be read.
+fn read_background(cli: &Cli) -> Vec<String> {
+    let mut background: Vec<String> = cli
+        .context_file
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path).unwrap_or_else(|e| {
+                exit_with(AppError::Config(format!(
+                    "Couldn't read the context file {}: {}",
+                    path.display(),
+                    e
+                )))
+            })
+        })
+        .collect();
+    background.extend(cli.context.iter().cloned());
+    background
+}
+
+/// Commits `message` the way the flags ask for.
+fn commit_changes(repo: &impl GitRepo, cli: &Cli, message: &str) {
+    let opts = CommitOptions {
+        review: cli.review,
+        // Only asked for when the editor opens; it's what git would do on its own
+        verbose: cli.review
+            && (cli.verbose_commit || git_config_bool("commit.verbose").unwrap_or(false)),
+        sign: cli.sign,
+        amend: cli.amend,
+    };
+    // Exits on failure, so nothing is pushed after a commit that wasn't made
+    let out = repo.commit(message, &opts).unwrap_or_else(|e| {
+        error!("The commit wasn't made: {}", e);
+        std::process::exit(ExitCode::Generic.code());
+    });
+    if !out.trim().is_empty() {
+        info!("{}", out.trim_end());
+    }
+}
+
+/// Where the diff comes from: `--base`, else the last commit with `--amend`,
+/// else the index.
+fn diff_source(cli: &Cli) -> DiffSource {
+    match &cli.base {
+        Some(base) => DiffSource::Base(base.clone()),
+        None => DiffSource::from_flags(cli.amend),
+    }
+}
+
+/// Checks the repository, stages if asked to, and returns the changed files and diff.
+fn git_diff(cli: &Cli, repo: &impl GitRepo, source: &DiffSource) -> Result<(String, String), ()> {
+    // Verify Git repo
+    if !repo.is_inside_work_tree() {
+        exit_with(AppError::NotAGitRepo);
+    }
+
+    // A branch diff needs a base that exists
+    if let DiffSource::Base(base) = source {
+        verify_ref(base).unwrap_or_else(|e| exit_with(e));
+    }
+
+    // Amending needs a commit to amend
+    if *source == DiffSource::LastCommit && !repo.has_head() {
+        error!("There is no previous commit to amend.");
+        std::process::exit(ExitCode::Generic.code());
+    }
+
+    // Stage changes first when asked to
+    if let Some(args) = stage_args(cli.all, cli.add_untracked).filter(|_| !cli.dry_run) {
+        let staged = repo.stage(&args).map_err(|e| {
+            error!("Failed to stage changes: {}", e);
+        })?;
+        if !staged {
+            error!("`git {}` failed.", args.join(" "));
+            std::process::exit(ExitCode::Generic.code());
+        }
+    }
+
+    // Gather diff
+    let git_staged = repo.staged_diff().map_err(|e| {
+        error!("Failed to get staged diff: {}", e);
+    })?;
+
+    // Describe untracked files as if they were added
+    let untracked = if cli.include_untracked && *source == DiffSource::Staged {
+        git_output(&["ls-files", "--others", "--exclude-standard"]).unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let untracked_files: Vec<&str> = untracked.lines().collect();
+    let diff = format!(
+        "{}{}",
+        git_staged,
+        build_untracked_context(&untracked_files, |path| std::fs::read(path))
+    );
+    if let Err(e) = ensure_nonempty_diff(&diff) {
+        let has_unstaged = Command::new("git")
+            .args(["status", "--porcelain"])
+            .output()
+            .map(|o| !o.stdout.is_empty())
+            .unwrap_or(false);
+        if has_unstaged && *source == DiffSource::Staged {
+            error!("No staged files, but there are unstaged changes – try `git add -A`.");
+        } else {
+            error!("{}", e);
+        }
+        std::process::exit(ExitCode::from(&e).code());
+    }
+
+    // Prepare diff context
+    let files = repo.staged_files().map_err(|e| {
+        error!("Couldn't get file list: {}", e);
+    })?;
+    let files = files
+        .iter()
+        .map(String::as_str)
+        .chain(untracked_files.iter().copied())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((files, diff))
+}
+
+/// Reads the diff to describe from stdin, taking the file list from its headers.
+fn read_stdin_diff() -> (String, String) {
+    let mut bytes = Vec::new();
+    if let Err(e) = std::io::stdin().read_to_end(&mut bytes) {
+        error!("Failed to read the diff from stdin: {}", e);
+        std::process::exit(ExitCode::Generic.code());
+    }
+    let (diff, lossy) = decode_git_output(&bytes);
+    if lossy {
+        warn!("The diff isn't valid UTF-8; invalid bytes were replaced.");
+    }
+    if let Err(e) = ensure_nonempty_diff(&diff) {
+        exit_with(e);
+    }
+    (files_from_diff(&diff).join("\n"), diff)
+}
+
+/// Shows the proposal until the user commits it, returning the final message.
+async fn review(
+    cli: &Cli,
+    generator: Option<&impl CommitGenerator>,
+    context: &str,
+    opts: &GenOptions,
+    mut commit_msg: String,
+) -> String {
+    let default = review_default(cli);
+    let no_color = std::env::var("NO_COLOR").ok();
+    let color = use_color(no_color.as_deref(), std::io::stderr().is_terminal());
+    loop {
+        eprintln!("Proposed Commit:\n{}\n", render_proposal(&commit_msg, color));
+        let answer = prompt_line(review_prompt(default));
+        match parse_review_action(&answer, default) {
+            Some(ReviewAction::Commit) => return commit_msg,
+            Some(ReviewAction::Edit) => match edit_in_editor(&commit_msg) {
+                Ok(edited) if !edited.trim().is_empty() => commit_msg = edited,
+                Ok(_) => warn!("The edited message is empty, keeping the previous one."),
+                Err(e) => error!("Couldn't open the editor: {}", e),
+            },
+            Some(ReviewAction::Regenerate) => {
+                let Some(generator) = generator else {
+                    warn!("Can't regenerate the message in offline mode.");
+                    continue;
+                };
+                let feedback = prompt_line("Feedback for the model (optional):");
+                let mut retry_opts = opts.clone();
+                if !feedback.trim().is_empty() {
+                    retry_opts.system_prompt.push_str(&format!(
+                        "\n\nFeedback on a previous attempt: {}",
+                        feedback.trim()
+                    ));
+                }
+                let commit = request_commit(generator, context, &retry_opts, &commit_rules(cli)).await;
+                commit_msg = post_process(cli, finalize_commit(cli, commit));
+            }
+            Some(ReviewAction::Abort) => {
+                error!("Aborted.");
+                std::process::exit(ExitCode::Generic.code());
+            }
+            None => error!("Please answer c, e, r or a."),
+        }
+    }
+}
+
+/// `--yes` stands for `--force --yes-really`, so no confirmation is asked for.
+fn apply_yes(cli: &mut Cli) {
+    if cli.yes {
+        cli.force = true;
+        cli.yes_really = true;
+    }
+}
+
+/// Whether the run may stop to ask something: the editor with `--review`, or,
+/// when committing, the confirmation unless `--force` (and `--yes-really` for
+/// large changes) skips it.
+fn may_prompt(cli: &Cli) -> bool {
+    let commits = !cli.dry_run && cli.write.is_none() && !cli.prepare;
+    cli.review || (commits && !(cli.force && cli.yes_really))
+}
+
+/// What an empty answer to a confirmation does, from the flag or the environment.
+fn review_default(cli: &Cli) -> ReviewAction {
+    let env_default = std::env::var("AUTO_COMMIT_CONFIRM_DEFAULT").ok();
+    confirm_default(cli.confirm_default.as_deref().or(env_default.as_deref()))
+}
+
+/// Asks `question` on stderr and reads the answer from stdin, so prompts never
+/// end up in piped output.
+fn prompt_line(question: &str) -> String {
+    eprint!("{} ", question);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut answer) {
+        debug!("Couldn't read the answer: {}", e);
+    }
+    answer.trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// Logs `e` and exits with its `ExitCode`.
+fn exit_with(e: AppError) -> ! {
+    error!("{}", e);
+    std::process::exit(ExitCode::from(&e).code())
+}
+
+/// Asks the model for a commit message, exiting on failure.
+async fn request_commit(
+    generator: &impl CommitGenerator,
+    context: &str,
+    opts: &GenOptions,
+    rules: &CommitRules,
+) -> Commit {
+    generate_checked(generator, context, opts, rules)
+        .await
+        .unwrap_or_else(|e| exit_with(e))
+}
+
+/// How long the generated title may be once `--prefix` and `--suffix` are added.
+fn title_budget(cli: &Cli) -> usize {
+    let affixes = cli.prefix.chars().count() + cli.suffix.chars().count();
+    cli.max_title_length.saturating_sub(affixes)
+}
+
+/// The rules a generated commit is checked against before it's used.
+fn commit_rules(cli: &Cli) -> CommitRules {
+    CommitRules {
+        max_title_length: Some(title_budget(cli)),
+        scope: cli.scope.clone(),
+        gitmoji: cli.gitmoji,
+    }
+}
+
+/// Prints the estimated cost of generating `commit_msg` from `context` to stderr.
+fn print_cost(opts: &GenOptions, context: &str, commit_msg: &str) {
+    let input_tokens =
+        count_tokens(&opts.model, &opts.system_prompt) + count_tokens(&opts.model, context);
+    let output_tokens = count_tokens(&opts.model, commit_msg);
+    match estimate_cost(&opts.model, input_tokens, output_tokens) {
+        Some(cost) => eprintln!(
+            "Estimated cost: ${:.6} ({} input + {} output tokens with {})",
+            cost, input_tokens, output_tokens, opts.model
+        ),
+        None => eprintln!(
+            "No known price for {} ({} input + {} output tokens)",
+            opts.model, input_tokens, output_tokens
+        ),
+    }
+}
+
+/// Pipes the message through `--post-process`, if given, exiting if the command fails.
+fn post_process(cli: &Cli, message: String) -> String {
+    match &cli.post_process {
+        Some(cmd) => run_post_process(cmd, &message).unwrap_or_else(|e| exit_with(e)),
+        None => message,
+    }
+}
+
+/// Applies the CLI's formatting rules to a generated commit and renders the message.
+fn finalize_commit(cli: &Cli, mut commit: Commit) -> String {
+    if let Some(scope) = cli.scope.as_deref().filter(|s| !has_scope(&commit.title, s)) {
+        warn!("The generated title isn't scoped to `{}`.", scope);
+    }
+    if cli.gitmoji && !starts_with_gitmoji(&commit.title) {
+        warn!("The generated title doesn't start with a gitmoji, adding one.");
+        commit.title = ensure_gitmoji(&commit.title);
+    }
+    if cli.ascii {
+        commit.title = to_ascii(&commit.title);
+        commit.description = to_ascii(&commit.description);
+    }
+    commit.title = if cli.ascii {
+        enforce_ascii_title_length(&commit.title, title_budget(cli))
+    } else {
+        enforce_title_length(&commit.title, title_budget(cli))
+    };
+    commit.title = apply_affixes(&commit.title, &cli.prefix, &cli.suffix);
+    if cli.bullets {
+        commit.description = normalize_bullets(&commit.description);
+    }
+    if cli.title_only || cli.preset.is_some_and(|preset| preset.title_only) {
+        commit.description.clear();
+    }
+    commit.description = wrap_body(&commit.description, BODY_WRAP_WIDTH);
+    if let Some(max) = cli.max_description_lines {
+        commit.description = cap_description_lines(&commit.description, max);
+    }
+    let mut issues = cli.closes.clone();
+    if cli.closes_branch_issue {
+        issues.extend(current_branch().as_deref().and_then(extract_issue_number));
+    }
+    let trailers = [
+        format_issue_trailers(&issues),
+        format_coauthors(&cli.co_author).unwrap_or_default(),
+    ]
+    .into_iter()
+    .filter(|block| !block.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n");
+    let message = append_trailer_block(&commit.to_string(), &trailers);
+    append_trailers(&message, &custom_trailers(cli))
+        .unwrap_or_else(|e| exit_with(AppError::Config(e)))
+}
+
+/// The `--trailer` flags, after any `autocommit.trailer` entries from git config,
+/// then the `--signoff` trailer.
+fn custom_trailers(cli: &Cli) -> Vec<String> {
+    let mut trailers = git_config_get_all("autocommit.trailer");
+    trailers.extend(cli.trailer.iter().cloned());
+    if cli.signoff {
+        trailers.push(signoff_from_git_config().unwrap_or_else(|e| exit_with(e)));
+    }
+    trailers
+}
+
+/// The `Signed-off-by:` trailer for the identity in git config.
+fn signoff_from_git_config() -> Result<String, AppError> {
+    match (git_config_get("user.name"), git_config_get("user.email")) {
+        (Some(name), Some(email)) => Ok(signoff_trailer(&name, &email)),
+        _ => Err(AppError::Config(
+            "--signoff needs your identity; set `git config user.name` and `git config user.email`."
+                .to_string(),
+        )),
+    }
+}
+
+async fn models_command() -> Result<(), ()> {
+    let provider = Provider::from_env().unwrap_or_else(|e| exit_with(e));
+    let generator = Generator::from_env(provider).unwrap_or_else(|e| exit_with(e));
+    match generator.list_models().await {
+        Ok(ids) => {
+            println!("{}", format_model_list(ids));
+            Ok(())
+        }
+        Err(e) => {
+            error!("Couldn't list models; check your API key and endpoint.");
+            exit_with(e)
+        }
+    }
+}
+
+/// Asks for a value on the terminal, showing `hint` for what an empty answer
+/// means, and returns `None` for one.
+fn ask(label: &str, hint: &str) -> Option<String> {
+    let question = if hint.is_empty() {
+        format!("{}:", label)
+    } else {
+        format!("{} [{}]:", label, hint)
+    };
+    match Question::new(&question).ask() {
+        Some(Answer::RESPONSE(answer)) if !answer.trim().is_empty() => {
+            Some(answer.trim().to_string())
+        }
+        _ => None,
+    }
+}
+
+fn init_command(config: &Config, force: bool) -> Result<(), ()> {
+    let Some(dir) = config_dir() else {
+        exit_with(AppError::Config(
+            "Couldn't find a config directory; set HOME or XDG_CONFIG_HOME.".to_string(),
+        ));
+    };
+    match write_config(&dir, config, force) {
+        Ok(path) => {
+            info!("Wrote {}", path.display());
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to write config: {}", e);
+            std::process::exit(ExitCode::Generic.code());
+        }
+    }
+}
+
+fn install_hook_command(force: bool) -> Result<(), ()> {
+    let hooks_dir = Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .output()
+        .map_err(|e| {
+            error!("Failed to locate the hooks directory: {}", e);
+        })?;
+    if !hooks_dir.status.success() {
+        exit_with(AppError::NotAGitRepo);
+    }
+    let hooks_dir = String::from_utf8_lossy(&hooks_dir.stdout).trim().to_string();
+
+    match install_hook(std::path::Path::new(&hooks_dir), force) {
+        Ok(path) => {
+            info!("Installed hook at {}", path.display());
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to install hook: {}", e);
+            std::process::exit(ExitCode::Generic.code());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use clap_verbosity_flag::{InfoLevel, Verbosity};
-    use log::LevelFilter;
+    use auto_commit::{
+        generator::DEFAULT_MAX_RESPONSE_TOKENS, get_model_from_env, truncate_to_n_tokens,
+        DEFAULT_MODEL,
+    };
+    use std::sync::{Mutex, MutexGuard};
+
+    fn env_lock() -> MutexGuard<'static, ()> {
+        static ENV_LOCK: Mutex<()> = Mutex::new(());
+        ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A repository with one staged file that records what gets committed.
+    #[derive(Default)]
+    struct MockRepo {
+        head: bool,
+        staged: std::sync::Mutex<Vec<String>>,
+        commits: std::sync::Mutex<Vec<(String, CommitOptions)>>,
+    }
+
+    impl GitRepo for MockRepo {
+        fn is_inside_work_tree(&self) -> bool {
+            true
+        }
+
+        fn has_head(&self) -> bool {
+            self.head
+        }
+
+        fn stage(&self, args: &[&str]) -> std::io::Result<bool> {
+            self.staged.lock().unwrap().push(args.join(" "));
+            Ok(true)
+        }
+
+        fn staged_diff(&self) -> std::io::Result<String> {
+            Ok("diff --git a/src/lib.rs b/src/lib.rs\n+pub fn login() {}\n".to_string())
+        }
+
+        fn staged_files(&self) -> std::io::Result<Vec<String>> {
+            Ok(vec!["src/lib.rs".to_string()])
+        }
+
+        fn commit(&self, message: &str, opts: &CommitOptions) -> std::io::Result<String> {
+            self.commits
+                .lock()
+                .unwrap()
+                .push((message.to_string(), *opts));
+            Ok(String::new())
+        }
+    }
+
+    /// Describes every context as the same login change.
+    struct FixedGenerator;
+
+    impl CommitGenerator for FixedGenerator {
+        async fn generate(&self, context: &str, _opts: &GenOptions) -> Result<Commit, AppError> {
+            assert!(context.contains("src/lib.rs"), "{}", context);
+            Ok(Commit {
+                title: "Add login".to_string(),
+                description: "Adds a login function.".to_string(),
+            })
+        }
+
+        async fn complete(&self, prompt: &str, _opts: &GenOptions) -> Result<String, AppError> {
+            Ok(prompt.to_string())
+        }
+    }
+
+    #[test]
+    fn git_diff_stages_through_the_repo() {
+        let repo = MockRepo::default();
+        git_diff(&Cli::parse_from(["auto-commit", "-a"]), &repo, &DiffSource::Staged).unwrap();
+        let cli = Cli::parse_from(["auto-commit", "--add-untracked", "--dry-run"]);
+        git_diff(&cli, &repo, &DiffSource::Staged).unwrap();
+        assert_eq!(*repo.staged.lock().unwrap(), ["add -u"]);
+    }
+
+    #[test]
+    fn git_diff_amends_when_there_is_a_head() {
+        let repo = MockRepo {
+            head: true,
+            ..MockRepo::default()
+        };
+        let cli = Cli::parse_from(["auto-commit", "--amend"]);
+        let (files, _) = git_diff(&cli, &repo, &DiffSource::LastCommit).unwrap();
+        assert_eq!(files, "src/lib.rs");
+    }
+
+    #[tokio::test]
+    async fn commits_the_generated_message_through_the_repo() {
+        let cli = Cli::parse_from(["auto-commit", "--force", "--sign"]);
+        let repo = MockRepo::default();
+
+        let (files, diff) = git_diff(&cli, &repo, &DiffSource::Staged).unwrap();
+        assert_eq!(files, "src/lib.rs");
+        let context = combine_context(&files, &diff);
+        let opts = GenOptions {
+            model: DEFAULT_MODEL.to_string(),
+            system_prompt: String::new(),
+            max_tokens: DEFAULT_MAX_RESPONSE_TOKENS,
+            no_tools: false,
+            seed: None,
+        };
+        let commit = request_commit(&FixedGenerator, &context, &opts, &CommitRules::default()).await;
+        commit_changes(&repo, &cli, &finalize_commit(&cli, commit));
+
+        let commits = repo.commits.lock().unwrap();
+        assert_eq!(
+            *commits,
+            [(
+                "Add login\n\nAdds a login function.".to_string(),
+                CommitOptions {
+                    review: false,
+                    verbose: false,
+                    sign: true,
+                    amend: false,
+                }
+            )]
+        );
+    }
 
     #[test]
     fn commit_to_string_formats_title_and_description() {
@@ -296,25 +1746,471 @@ mod tests {
 
     #[test]
     fn cli_default_parsing_sets_flags_and_info_level() {
-        let cli = Cli::parse_from(&["auto-commit"]);
+        let cli = Cli::parse_from(["auto-commit"]);
         assert!(!cli.dry_run);
         assert!(!cli.review);
         assert!(!cli.force);
+        assert!(!cli.no_spinner);
+        assert!(!cli.amend);
+        assert_eq!(cli.max_title_length, DEFAULT_MAX_TITLE_LENGTH);
+        assert!(!cli.no_cache);
+        assert!(!cli.all);
+        assert!(!cli.add_untracked);
+        assert!(!cli.show_context);
+        assert!(!cli.gitmoji);
+        assert!(!cli.sign);
+        assert_eq!(cli.timeout, None);
+        assert!(!cli.map_reduce);
+        assert!(!cli.offline);
+        assert!(!cli.include_untracked);
+        assert_eq!(cli.scope, None);
+        assert!(!cli.show_cost);
+        assert!(!cli.diff_from_stdin);
+        assert!(!cli.bullets);
+        assert!(!cli.print_prompt);
+        assert!(cli.co_author.is_empty());
+        assert!(!cli.include_stat);
         assert_eq!(cli.verbose.log_level_filter(), LevelFilter::Info);
     }
 
     #[test]
     fn cli_parsing_all_flags_and_verbose_levels() {
-        let args = &["auto-commit", "--dry-run", "--review", "--force", "-vv"];
+        let args = ["auto-commit", "--dry-run", "--review", "--force", "-vv"];
         let cli = Cli::parse_from(args);
         assert!(cli.dry_run);
         assert!(cli.review);
         assert!(cli.force);
-        assert_eq!(cli.verbose.log_level_filter(), LevelFilter::Debug);
+        assert_eq!(cli.verbose.log_level_filter(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn base_flag_picks_the_branch_diff() {
+        let cli = Cli::parse_from(["auto-commit", "--base", "main"]);
+        assert_eq!(diff_source(&cli), DiffSource::Base("main".to_string()));
+        assert_eq!(diff_source(&Cli::parse_from(["auto-commit"])), DiffSource::Staged);
+        assert!(Cli::try_parse_from(["auto-commit", "--base", "main", "--amend"]).is_err());
+    }
+
+    #[test]
+    fn yes_skips_every_prompt() {
+        let parse = |args: &[&str]| {
+            let mut cli = Cli::parse_from([&["auto-commit"], args].concat());
+            apply_yes(&mut cli);
+            cli
+        };
+        assert!(may_prompt(&parse(&[])));
+        assert!(may_prompt(&parse(&["--force"])));
+        assert!(!may_prompt(&parse(&["--force", "--yes-really"])));
+        assert!(!may_prompt(&parse(&["-y"])));
+        assert!(!may_prompt(&parse(&["--yes", "--dry-run"])));
+        assert!(!may_prompt(&parse(&["--dry-run"])));
+        assert!(may_prompt(&parse(&["--dry-run", "--review"])));
+        assert!(Cli::try_parse_from(["auto-commit", "--yes", "--review"]).is_err());
+
+        let cli = parse(&["--yes"]);
+        assert!(cli.force && cli.yes_really && !cli.dry_run);
+    }
+
+    #[test]
+    fn cli_parsing_no_spinner_flag() {
+        let cli = Cli::parse_from(["auto-commit", "--no-spinner"]);
+        assert!(cli.no_spinner);
+    }
+
+    #[test]
+    fn cli_parsing_amend_flag() {
+        let cli = Cli::parse_from(["auto-commit", "--amend"]);
+        assert!(cli.amend);
+    }
+
+    #[test]
+    fn cli_parsing_include_context_from_pr() {
+        let cli = Cli::parse_from(["auto-commit", "--include-context-from-pr"]);
+        assert_eq!(cli.include_context_from_pr, Some(None));
+        let cli = Cli::parse_from(["auto-commit", "--include-context-from-pr", "docs/pr.md"]);
+        assert_eq!(cli.include_context_from_pr, Some(Some(PathBuf::from("docs/pr.md"))));
+        assert_eq!(Cli::parse_from(["auto-commit"]).include_context_from_pr, None);
+    }
+
+    #[test]
+    fn cli_parsing_max_description_lines() {
+        let cli = Cli::parse_from(["auto-commit", "--max-description-lines", "5"]);
+        assert_eq!(cli.max_description_lines, Some(5));
+        assert_eq!(Cli::parse_from(["auto-commit"]).max_description_lines, None);
+    }
+
+    #[test]
+    fn affixes_count_toward_the_title_length() {
+        let cli = Cli::parse_from(["auto-commit", "--prefix", "[PROJ-123] ", "--suffix", "!"]);
+        assert_eq!(title_budget(&cli), DEFAULT_MAX_TITLE_LENGTH - 12);
+        let cli =
+            Cli::parse_from(["auto-commit", "--max-title-length", "5", "--prefix", "[PROJ-1] "]);
+        assert_eq!(title_budget(&cli), 0);
+        assert_eq!(title_budget(&Cli::parse_from(["auto-commit"])), DEFAULT_MAX_TITLE_LENGTH);
+    }
+
+    #[test]
+    fn cli_parsing_max_title_length() {
+        let cli = Cli::parse_from(["auto-commit", "--max-title-length", "50"]);
+        assert_eq!(cli.max_title_length, 50);
+    }
+
+    #[test]
+    fn cli_parsing_staging_flags() {
+        let cli = Cli::parse_from(["auto-commit", "-a", "--add-untracked"]);
+        assert!(cli.all);
+        assert!(cli.add_untracked);
+    }
+
+    #[test]
+    fn cli_parsing_sign_flag() {
+        assert!(Cli::parse_from(["auto-commit", "-S"]).sign);
+        assert!(Cli::parse_from(["auto-commit", "--sign"]).sign);
+    }
+
+    #[test]
+    fn cli_parsing_map_reduce_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--map-reduce"]).map_reduce);
+    }
+
+    #[test]
+    fn cli_parsing_concurrency() {
+        assert_eq!(Cli::parse_from(["auto-commit"]).concurrency, DEFAULT_CONCURRENCY);
+        assert_eq!(Cli::parse_from(["auto-commit", "--concurrency", "8"]).concurrency, 8);
+        assert!(Cli::try_parse_from(["auto-commit", "--concurrency", "0"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_offline_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--offline"]).offline);
+        assert!(Cli::parse_from(["auto-commit", "--no-network"]).offline);
+    }
+
+    #[test]
+    fn cli_parsing_scope() {
+        let cli = Cli::parse_from(["auto-commit", "--scope", "parser"]);
+        assert_eq!(cli.scope.as_deref(), Some("parser"));
+    }
+
+    #[test]
+    fn cli_parsing_diff_from_stdin() {
+        assert!(Cli::parse_from(["auto-commit", "--diff-from-stdin"]).diff_from_stdin);
+        assert!(Cli::try_parse_from(["auto-commit", "--diff-from-stdin", "--amend"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_auto_commit_limits() {
+        let cli = Cli::parse_from(["auto-commit"]);
+        assert_eq!(cli.max_auto_files, DEFAULT_MAX_AUTO_FILES);
+        assert_eq!(cli.max_auto_lines, DEFAULT_MAX_AUTO_LINES);
+        assert!(!cli.yes_really);
+
+        let cli = Cli::parse_from(["auto-commit", "-f", "--yes-really", "--max-auto-files", "5"]);
+        assert_eq!(cli.max_auto_files, 5);
+        assert!(cli.yes_really);
+        assert!(Cli::try_parse_from(["auto-commit", "--yes-really"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_repeated_closes() {
+        let cli = Cli::parse_from(["auto-commit", "--closes", "12", "--closes", "7"]);
+        assert_eq!(cli.closes, [12, 7]);
+        assert!(!cli.closes_branch_issue);
+        assert!(Cli::try_parse_from(["auto-commit", "--closes", "#12"]).is_err());
+    }
+
+    #[test]
+    fn prepare_never_commits() {
+        let cli = Cli::parse_from(["auto-commit", "--prepare"]);
+        assert!(cli.prepare);
+        assert!(!may_prompt(&cli));
+        assert!(Cli::try_parse_from(["auto-commit", "--prepare", "--dry-run"]).is_err());
+        assert!(Cli::try_parse_from(["auto-commit", "--prepare", "--write"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_write() {
+        assert_eq!(Cli::parse_from(["auto-commit"]).write, None);
+        assert_eq!(Cli::parse_from(["auto-commit", "--write"]).write, Some(None));
+        assert_eq!(
+            Cli::parse_from(["auto-commit", "--write", "msg.txt"]).write,
+            Some(Some(PathBuf::from("msg.txt")))
+        );
+    }
+
+    #[test]
+    fn spinner_is_shown_at_the_silent_level() {
+        assert!(shows_spinner(&Cli::parse_from(["auto-commit", "-qqq"])));
+        assert!(!shows_spinner(&Cli::parse_from(["auto-commit", "-qqq", "--no-spinner"])));
+        assert!(!shows_spinner(&Cli::parse_from(["auto-commit", "-qqq", "--dry-run"])));
+        assert!(!shows_spinner(&Cli::parse_from(["auto-commit"])));
+    }
+
+    #[test]
+    fn cli_parsing_diff_context() {
+        let cli = Cli::parse_from(["auto-commit", "--diff-context", "10"]);
+        assert_eq!(system_git(&cli, DiffSource::Staged).context_lines, Some(10));
+        assert_eq!(Cli::parse_from(["auto-commit", "--diff-context", "0"]).diff_context, Some(0));
+        assert_eq!(Cli::parse_from(["auto-commit"]).diff_context, None);
+        assert!(Cli::try_parse_from(["auto-commit", "--diff-context", "-1"]).is_err());
+        assert!(Cli::try_parse_from(["auto-commit", "--diff-context", "101"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_diff_algorithm() {
+        let cli = Cli::parse_from(["auto-commit", "--diff-algorithm", "histogram"]);
+        assert_eq!(cli.diff_algorithm.as_deref(), Some("histogram"));
+        assert!(Cli::try_parse_from(["auto-commit", "--diff-algorithm", "fancy"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_preset() {
+        let cli = Cli::parse_from(["auto-commit", "--preset", "terse"]);
+        assert_eq!(cli.preset.map(|preset| preset.name), Some("terse"));
+        assert!(Cli::try_parse_from(["auto-commit", "--preset", "verbose"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_seed() {
+        assert_eq!(Cli::parse_from(["auto-commit"]).seed, None);
+        assert_eq!(Cli::parse_from(["auto-commit", "--seed", "42"]).seed, Some(42));
+        assert!(Cli::try_parse_from(["auto-commit", "--seed", "-1"]).is_err());
+        assert!(Cli::try_parse_from(["auto-commit", "--seed", "abc"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_ignore_whitespace_flag() {
+        assert!(!Cli::parse_from(["auto-commit"]).ignore_whitespace);
+        assert!(Cli::parse_from(["auto-commit", "--ignore-whitespace"]).ignore_whitespace);
+    }
+
+    #[test]
+    fn cli_parsing_stats_flag() {
+        assert!(!Cli::parse_from(["auto-commit"]).stats);
+        assert!(Cli::parse_from(["auto-commit", "--stats"]).stats);
+    }
+
+    #[test]
+    fn cli_parsing_post_process() {
+        let cli = Cli::parse_from(["auto-commit", "--post-process", "aspell list"]);
+        assert_eq!(cli.post_process.as_deref(), Some("aspell list"));
+        assert_eq!(Cli::parse_from(["auto-commit"]).post_process, None);
+    }
+
+    #[test]
+    fn cli_parsing_ascii_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--ascii"]).ascii);
+        assert!(Cli::try_parse_from(["auto-commit", "--ascii", "--gitmoji"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_confirm_default() {
+        let cli = Cli::parse_from(["auto-commit", "--confirm-default", "no"]);
+        assert_eq!(cli.confirm_default.as_deref(), Some("no"));
+        assert!(Cli::try_parse_from(["auto-commit", "--confirm-default", "maybe"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_log_json_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--log-json"]).log_json);
+        assert!(!Cli::parse_from(["auto-commit"]).log_json);
+    }
+
+    #[test]
+    fn cli_parsing_split_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--split", "--dry-run"]).split);
+        assert!(Cli::try_parse_from(["auto-commit", "--split", "--amend"]).is_err());
+    }
+
+    #[test]
+    fn cli_parsing_trailer_flags() {
+        let cli = Cli::parse_from([
+            "auto-commit",
+            "--trailer",
+            "Reviewed-by: Ada <ada@example.com>",
+            "--trailer",
+            "Tested-by: CI",
+        ]);
+        assert_eq!(cli.trailer, ["Reviewed-by: Ada <ada@example.com>", "Tested-by: CI"]);
+    }
+
+    #[test]
+    fn cli_parsing_signoff_flag() {
+        assert!(Cli::parse_from(["auto-commit", "-s"]).signoff);
+        assert!(Cli::parse_from(["auto-commit", "--sign-off"]).signoff);
+        assert!(!Cli::parse_from(["auto-commit", "-S"]).signoff);
+    }
+
+    #[test]
+    fn cli_parsing_context_flags() {
+        let cli = Cli::parse_from([
+            "auto-commit",
+            "--context",
+            "This implements the caching RFC.",
+            "--context-file",
+            "notes.md",
+            "--context-file",
+            "rfc.md",
+        ]);
+        assert_eq!(cli.context, ["This implements the caching RFC."]);
+        assert_eq!(cli.context_file, [PathBuf::from("notes.md"), PathBuf::from("rfc.md")]);
+    }
+
+    #[test]
+    fn title_only_drops_the_description() {
+        let cli = Cli::parse_from(["auto-commit", "--no-description"]);
+        assert!(cli.title_only);
+        let commit = Commit {
+            title: "Bump the serde version".to_string(),
+            description: "Keeps up with upstream.".to_string(),
+        };
+        assert_eq!(finalize_commit(&cli, commit), "Bump the serde version");
+    }
+
+    #[test]
+    fn commands_are_named_as_typed() {
+        for name in ["install-hook", "models", "explain", "watch", "check", "init"] {
+            let cli = Cli::parse_from(["auto-commit", name]);
+            assert_eq!(cli.command.unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn terse_preset_drops_the_description() {
+        let cli = Cli::parse_from(["auto-commit", "--preset", "terse"]);
+        let commit = Commit {
+            title: "Bump the serde version".to_string(),
+            description: "Keeps up with upstream.".to_string(),
+        };
+        assert_eq!(finalize_commit(&cli, commit), "Bump the serde version");
+    }
+
+    #[test]
+    fn ascii_keeps_shortened_titles_ascii() {
+        let cli = Cli::parse_from(["auto-commit", "--ascii", "--max-title-length", "30"]);
+        let commit = Commit {
+            title: "Add support for amending the previous commit".to_string(),
+            description: String::new(),
+        };
+        let message = finalize_commit(&cli, commit);
+        assert_eq!(message, "Add support for amending...");
+        assert!(message.is_ascii());
+    }
+
+    #[test]
+    fn commit_cache_key_covers_the_whole_diff_when_map_reducing() {
+        let opts = GenOptions {
+            model: DEFAULT_MODEL.to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: DEFAULT_MAX_RESPONSE_TOKENS,
+            no_tools: false,
+            seed: None,
+        };
+        // Both diffs truncate to the same context
+        let context = "Diff:\n+one";
+        let key = |diff| commit_cache_key(&opts, context, Some(("gpt-4.1-nano", diff)));
+        assert_ne!(key("+one\n+two"), key("+one\n+three"));
+        assert_eq!(key("+one\n+two"), key("+one\n+two"));
+        assert_ne!(commit_cache_key(&opts, context, None), key("+one\n+two"));
+    }
+
+    #[test]
+    fn cli_parsing_push_flag() {
+        assert!(Cli::parse_from(["auto-commit", "--push"]).push);
+        assert!(Cli::try_parse_from(["auto-commit", "--push", "--dry-run"]).is_err());
+        // These never commit either, so there'd be nothing to push
+        for flag in [
+            &["--base", "main"][..],
+            &["--changelog"],
+            &["--diff-from-stdin"],
+            &["--write"],
+            &["--prepare"],
+        ] {
+            let args = ["auto-commit", "--push"].iter().chain(flag);
+            assert!(Cli::try_parse_from(args).is_err(), "{:?}", flag);
+        }
+    }
+
+    #[test]
+    fn cli_parsing_no_tools_flag() {
+        assert!(!Cli::parse_from(["auto-commit"]).no_tools);
+        assert!(Cli::parse_from(["auto-commit", "--no-tools"]).no_tools);
+    }
+
+    #[test]
+    fn cli_parsing_repeated_co_authors() {
+        let cli = Cli::parse_from([
+            "auto-commit",
+            "--co-author",
+            "Ada Lovelace <ada@example.com>",
+            "--co-author",
+            "Alan Turing <alan@example.org>",
+        ]);
+        assert_eq!(
+            cli.co_author,
+            ["Ada Lovelace <ada@example.com>", "Alan Turing <alan@example.org>"]
+        );
+    }
+
+    #[test]
+    fn cli_parsing_install_hook_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "install-hook", "--force"]);
+        assert!(matches!(cli.command, Some(Commands::InstallHook { force: true })));
+
+        let cli = Cli::parse_from(["auto-commit"]);
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn cli_parsing_explain_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "explain"]);
+        assert!(matches!(cli.command, Some(Commands::Explain)));
+    }
+
+    #[test]
+    fn cli_parsing_watch_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "watch"]);
+        assert!(matches!(cli.command, Some(Commands::Watch)));
+    }
+
+    #[test]
+    fn cli_parsing_check_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "check", "--message", "Fix bug"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Check { message: Some(ref message) }) if message == "Fix bug"
+        ));
+        let cli = Cli::parse_from(["auto-commit", "check"]);
+        assert!(matches!(cli.command, Some(Commands::Check { message: None })));
+    }
+
+    #[test]
+    fn cli_parsing_models_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "models"]);
+        assert!(matches!(cli.command, Some(Commands::Models)));
+    }
+
+    #[test]
+    fn cli_parsing_init_subcommand() {
+        let cli = Cli::parse_from(["auto-commit", "init", "--model", "gpt-4o", "--force"]);
+        match cli.command {
+            Some(Commands::Init {
+                model,
+                api_key,
+                force,
+                ..
+            }) => {
+                assert_eq!(model.as_deref(), Some("gpt-4o"));
+                assert_eq!(api_key, None);
+                assert!(force);
+            }
+            _ => panic!("expected the init subcommand"),
+        }
     }
 
     #[test]
     fn get_model_from_env_returns_env_value_when_set() {
+        let _env = env_lock();
         std::env::set_var("AUTO_COMMIT_MODEL", "test-model");
         let model = get_model_from_env();
         assert_eq!(model, "test-model".to_string());
@@ -323,6 +2219,7 @@ mod tests {
 
     #[test]
     fn get_model_from_env_returns_non_empty_default_when_unset() {
+        let _env = env_lock();
         std::env::remove_var("AUTO_COMMIT_MODEL");
         let model = get_model_from_env();
         assert!(!model.is_empty());
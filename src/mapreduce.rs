@@ -0,0 +1,305 @@
+//! Map-reduce generation for diffs too large to send whole: each file's diff is
+//! summarized on its own, then the commit is written from those summaries.
+
+use crate::{
+    error::AppError,
+    generator::{generate_checked, CommitGenerator, GenOptions},
+    message::{Commit, CommitRules},
+    truncate_to_n_tokens,
+};
+use futures::stream::{self, StreamExt};
+
+pub const SUMMARY_SYSTEM_PROMPT: &str = "You are an experienced developer reviewing a change. \
+     Reply with a single line describing what changed in the given file and why, if apparent.";
+
+pub const SUMMARY_MAX_TOKENS: u32 = 100;
+
+/// Per-file summaries requested at once when `--concurrency` isn't given.
+pub const DEFAULT_CONCURRENCY: u16 = 4;
+
+/// One file's section of a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: String,
+    pub diff: String,
+}
+
+/// Splits the output of `git diff` into per-file sections.
+pub fn split_diff_by_file(diff: &str) -> Vec<FileDiff> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            let path = header
+                .rsplit_once(" b/")
+                .map_or(header, |(_, path)| path)
+                .to_string();
+            files.push(FileDiff {
+                path,
+                diff: String::new(),
+            });
+        }
+        if let Some(file) = files.last_mut() {
+            file.diff.push_str(line);
+            file.diff.push('\n');
+        }
+    }
+    files
+}
+
+/// Asks for a one-line summary of a single file's diff, truncated to `limit` tokens.
+pub async fn summarize_file(
+    generator: &impl CommitGenerator,
+    path: &str,
+    diff: &str,
+    opts: &GenOptions,
+    limit: usize,
+) -> Result<String, AppError> {
+    let opts = GenOptions {
+        system_prompt: SUMMARY_SYSTEM_PROMPT.to_string(),
+        max_tokens: SUMMARY_MAX_TOKENS,
+        ..opts.clone()
+    };
+    let prompt = format!("File: {}\n\n{}", path, truncate_to_n_tokens(diff, limit));
+    let summary = generator.complete(&prompt, &opts).await?;
+    Ok(summary.lines().next().unwrap_or("").trim().to_string())
+}
+
+/// Context handed to the final call in place of the full diff.
+pub fn summaries_context(summaries: &[(String, String)]) -> String {
+    let lines: Vec<String> = summaries
+        .iter()
+        .map(|(path, summary)| format!("- {}: {}", path, summary))
+        .collect();
+    format!(
+        "The diff was too large to show. Summaries of each changed file:\n{}",
+        lines.join("\n")
+    )
+}
+
+/// Writes the commit for a change from its per-file `(path, summary)` pairs.
+pub async fn synthesize_commit(
+    generator: &impl CommitGenerator,
+    summaries: &[(String, String)],
+    opts: &GenOptions,
+    rules: &CommitRules,
+) -> Result<Commit, AppError> {
+    generate_checked(generator, &summaries_context(summaries), opts, rules).await
+}
+
+/// Summarizes every file in `diff` with `summary_model`, up to `concurrency` at a
+/// time, then synthesizes the commit from the summaries with `opts.model`. The
+/// summaries are put back in file order, whichever finishes first.
+pub async fn map_reduce_commit(
+    generator: &impl CommitGenerator,
+    diff: &str,
+    opts: &GenOptions,
+    summary_model: &str,
+    limit: usize,
+    concurrency: usize,
+    rules: &CommitRules,
+) -> Result<Commit, AppError> {
+    let summary_opts = &GenOptions {
+        model: summary_model.to_string(),
+        ..opts.clone()
+    };
+    let mut results: Vec<_> = stream::iter(split_diff_by_file(diff).into_iter().enumerate())
+        .map(|(index, file)| async move {
+            let summary =
+                summarize_file(generator, &file.path, &file.diff, summary_opts, limit).await;
+            (index, file.path, summary)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _, _)| *index);
+
+    let summaries = results
+        .into_iter()
+        .map(|(_, path, summary)| summary.map(|summary| (path, summary)))
+        .collect::<Result<Vec<_>, _>>()?;
+    synthesize_commit(generator, &summaries, opts, rules).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::stub::StubGenerator;
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-old
++new
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1 +1,2 @@
+ # Title
++More docs
+";
+
+    fn opts() -> GenOptions {
+        GenOptions {
+            model: "test-model".to_string(),
+            system_prompt: "Write great commits.".to_string(),
+            max_tokens: 2000,
+            no_tools: false,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn split_diff_by_file_separates_sections() {
+        let files = split_diff_by_file(DIFF);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "src/lib.rs");
+        assert!(files[0].diff.starts_with("diff --git a/src/lib.rs"));
+        assert!(files[0].diff.ends_with("+new\n"));
+        assert_eq!(files[1].path, "README.md");
+        assert!(files[1].diff.contains("+More docs"));
+    }
+
+    #[test]
+    fn split_diff_by_file_empty_diff() {
+        assert!(split_diff_by_file("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn summarize_file_uses_summary_prompt() {
+        let generator = StubGenerator::default();
+        let summary = summarize_file(&generator, "src/lib.rs", "+new", &opts(), 100)
+            .await
+            .unwrap();
+
+        assert_eq!(summary, "Summary of File: src/lib.rs");
+        let calls = generator.completions();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "File: src/lib.rs\n\n+new");
+        assert_eq!(calls[0].1.system_prompt, SUMMARY_SYSTEM_PROMPT);
+        assert_eq!(calls[0].1.max_tokens, SUMMARY_MAX_TOKENS);
+    }
+
+    #[tokio::test]
+    async fn synthesize_commit_sends_the_summaries() {
+        let generator = StubGenerator::default();
+        let summaries = vec![
+            ("src/lib.rs".to_string(), "Renames old to new".to_string()),
+            ("README.md".to_string(), "Documents more".to_string()),
+        ];
+        synthesize_commit(&generator, &summaries, &opts(), &CommitRules::default())
+            .await
+            .unwrap();
+
+        let contexts = generator.contexts();
+        assert_eq!(contexts.len(), 1);
+        assert!(
+            contexts[0].ends_with("- src/lib.rs: Renames old to new\n- README.md: Documents more")
+        );
+    }
+
+    #[tokio::test]
+    async fn map_reduce_commit_summarizes_each_file_in_order() {
+        let generator = StubGenerator::default();
+        map_reduce_commit(
+            &generator,
+            DIFF,
+            &opts(),
+            "test-model",
+            100,
+            1,
+            &CommitRules::default(),
+        )
+        .await
+        .unwrap();
+
+        let prompts: Vec<String> = generator.completions().into_iter().map(|c| c.0).collect();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[0].starts_with("File: src/lib.rs"));
+        assert!(prompts[1].starts_with("File: README.md"));
+        assert!(generator.contexts()[0].contains(
+            "- src/lib.rs: Summary of File: src/lib.rs\n- README.md: Summary of File: README.md"
+        ));
+    }
+
+    #[tokio::test]
+    async fn map_reduce_commit_uses_the_summary_model_for_summaries() {
+        let generator = StubGenerator::default();
+        map_reduce_commit(
+            &generator,
+            DIFF,
+            &opts(),
+            "cheap-model",
+            100,
+            1,
+            &CommitRules::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(generator
+            .completions()
+            .iter()
+            .all(|(_, opts)| opts.model == "cheap-model"));
+        assert_eq!(generator.generations()[0].1.model, "test-model");
+    }
+
+    /// Summarizer whose answers for earlier files take longer, so concurrent
+    /// summaries finish in reverse order.
+    struct SlowFirstGenerator {
+        finished: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl CommitGenerator for SlowFirstGenerator {
+        async fn generate(&self, context: &str, _opts: &GenOptions) -> Result<Commit, AppError> {
+            Ok(Commit {
+                title: "Update files".to_string(),
+                description: context.to_string(),
+            })
+        }
+
+        async fn complete(&self, prompt: &str, _opts: &GenOptions) -> Result<String, AppError> {
+            let path = prompt.lines().next().unwrap_or("").to_string();
+            let delay = match path.as_str() {
+                "File: a.rs" => 60,
+                "File: b.rs" => 30,
+                _ => 0,
+            };
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            self.finished.lock().unwrap().push(path.clone());
+            Ok(format!("Summary of {}", path))
+        }
+    }
+
+    #[tokio::test]
+    async fn map_reduce_commit_keeps_file_order_when_run_concurrently() {
+        let diff = "diff --git a/a.rs b/a.rs\n+a\n\
+                    diff --git a/b.rs b/b.rs\n+b\n\
+                    diff --git a/c.rs b/c.rs\n+c\n";
+        let generator = SlowFirstGenerator {
+            finished: Default::default(),
+        };
+        let commit = map_reduce_commit(
+            &generator,
+            diff,
+            &opts(),
+            "test-model",
+            100,
+            3,
+            &CommitRules::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *generator.finished.lock().unwrap(),
+            ["File: c.rs", "File: b.rs", "File: a.rs"]
+        );
+        assert!(commit.description.ends_with(
+            "- a.rs: Summary of File: a.rs\n\
+             - b.rs: Summary of File: b.rs\n\
+             - c.rs: Summary of File: c.rs"
+        ));
+    }
+}
@@ -0,0 +1,715 @@
+use crate::{conventional::has_scope, gitmoji::starts_with_gitmoji};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Commit schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Commit {
+    #[schemars(length(min = 1))]
+    pub title: String,
+    #[schemars(length(min = 1))]
+    pub description: String,
+}
+
+/// The commit message: the title, then a blank line and the description. A
+/// blank description leaves just the title.
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.description.trim().is_empty() {
+            write!(f, "{}", self.title)
+        } else {
+            write!(f, "{}\n\n{}", self.title, self.description)
+        }
+    }
+}
+
+/// The model's commit JSON didn't match the `Commit` schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Checks what the schema can't guarantee: neither field may be blank.
+pub fn validate_commit(c: &Commit) -> Result<(), String> {
+    match (c.title.trim().is_empty(), c.description.trim().is_empty()) {
+        (true, true) => Err("the title and description are empty".to_string()),
+        (true, false) => Err("the title is empty".to_string()),
+        (false, true) => Err("the description is empty".to_string()),
+        (false, false) => Ok(()),
+    }
+}
+
+/// What a generated commit has to satisfy besides parsing, from the flags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitRules {
+    pub max_title_length: Option<usize>,
+    /// Conventional commit scope the title must use.
+    pub scope: Option<String>,
+    /// The title must start with a gitmoji.
+    pub gitmoji: bool,
+}
+
+impl CommitRules {
+    /// The first rule `commit` breaks, phrased so it can be fed back to the model.
+    pub fn check(&self, commit: &Commit) -> Result<(), String> {
+        validate_commit(commit)?;
+        let title = commit.title.trim();
+        if let Some(max) = self.max_title_length {
+            let length = title.chars().count();
+            if length > max {
+                return Err(format!(
+                    "the title is {} characters long, over the limit of {}",
+                    length, max
+                ));
+            }
+        }
+        // A gitmoji may come before the conventional prefix
+        let unprefixed = match title.split_once(' ') {
+            Some((_, rest)) if starts_with_gitmoji(title) => rest,
+            _ => title,
+        };
+        if let Some(scope) = self
+            .scope
+            .as_deref()
+            .filter(|scope| !has_scope(unprefixed, scope))
+        {
+            return Err(format!(
+                "the title isn't scoped to `{}`, as in `feat({}): ...`",
+                scope, scope
+            ));
+        }
+        if self.gitmoji && !starts_with_gitmoji(title) {
+            return Err("the title doesn't start with a gitmoji".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Parses the commit JSON returned by the model. Unknown fields are ignored, but
+/// both fields must be present and pass `validate_commit`.
+pub fn parse_commit(json: &str) -> Result<Commit, ParseError> {
+    let commit: Commit = serde_json::from_str(json).map_err(|e| ParseError(e.to_string()))?;
+    validate_commit(&commit).map_err(ParseError)?;
+    Ok(commit)
+}
+
+/// JSON schema for `Commit`, with subschemas inlined so it can be handed to the model.
+pub fn commit_schema() -> serde_json::Value {
+    let mut gen =
+        SchemaGenerator::new(SchemaSettings::openapi3().with(|s| s.inline_subschemas = true));
+    let schema = gen.subschema_for::<Commit>().into_object();
+    serde_json::to_value(schema).expect("Failed to serialize the commit schema")
+}
+
+pub const DEFAULT_MAX_TITLE_LENGTH: usize = 72;
+
+/// Shortens `title` to at most `max` characters, cutting on a word boundary when
+/// possible and marking the cut with an ellipsis.
+pub fn enforce_title_length(title: &str, max: usize) -> String {
+    shorten_title(title, max, "…")
+}
+
+/// `enforce_title_length`, marking the cut with `...` instead, for `--ascii`.
+pub fn enforce_ascii_title_length(title: &str, max: usize) -> String {
+    shorten_title(title, max, "...")
+}
+
+fn shorten_title(title: &str, max: usize, ellipsis: &str) -> String {
+    let title = title.trim();
+    if title.chars().count() <= max {
+        return title.to_string();
+    }
+    let Some(room) = max.checked_sub(ellipsis.chars().count()) else {
+        return String::new();
+    };
+
+    let kept: String = title.chars().take(room).collect();
+    let next_is_space = title.chars().nth(room).is_some_and(char::is_whitespace);
+    let cut = if next_is_space {
+        kept.as_str()
+    } else {
+        kept.rfind(char::is_whitespace)
+            .map_or(kept.as_str(), |idx| &kept[..idx])
+    };
+
+    format!("{}{}", cut.trim_end(), ellipsis)
+}
+
+/// `title` between `prefix` and `suffix`, used as given so they can bring their
+/// own spacing, e.g. `[PROJ-123] `. An affix the title already has isn't repeated.
+pub fn apply_affixes(title: &str, prefix: &str, suffix: &str) -> String {
+    let mut title = title.to_string();
+    if !title.starts_with(prefix) {
+        title.insert_str(0, prefix);
+    }
+    if !title.ends_with(suffix) {
+        title.push_str(suffix);
+    }
+    title
+}
+
+/// Turns a description into a `- ` bullet list: every line becomes a bullet
+/// (replacing `*`, `+` or `•` markers), indented lines continue the previous
+/// bullet, and blank lines are dropped.
+pub fn normalize_bullets(desc: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in desc.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let is_continuation = line.starts_with(char::is_whitespace)
+            && !lines.is_empty()
+            && !trimmed.starts_with(['-', '*', '+', '•']);
+        if is_continuation {
+            lines.push(format!("  {}", trimmed));
+        } else {
+            let item = trimmed
+                .strip_prefix(['-', '*', '+', '•'])
+                .unwrap_or(trimmed)
+                .trim_start();
+            lines.push(format!("- {}", item));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Keeps the first `max` lines of `desc`, adding a `...` line if anything was
+/// cut. A bullet item isn't split from its continuation lines: the cut moves
+/// back to the start of the item, unless that would leave nothing.
+pub fn cap_description_lines(desc: &str, max: usize) -> String {
+    let lines: Vec<&str> = desc.trim_end().lines().collect();
+    if lines.len() <= max {
+        return desc.trim_end().to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+
+    let is_continuation = |line: &str| {
+        line.starts_with(char::is_whitespace)
+            && !line.trim().is_empty()
+            && !line.trim_start().starts_with(['-', '*', '+', '•'])
+    };
+    let mut keep = max;
+    while keep > 0 && is_continuation(lines[keep]) {
+        keep -= 1;
+    }
+    if keep == 0 {
+        keep = max;
+    }
+
+    let mut capped: Vec<&str> = lines[..keep].to_vec();
+    while capped.last().is_some_and(|line| line.trim().is_empty()) {
+        capped.pop();
+    }
+    capped.push("...");
+    capped.join("\n")
+}
+
+/// Git's conventional width for commit message bodies.
+pub const BODY_WRAP_WIDTH: usize = 72;
+
+/// Wraps each line of `text` at `width` characters on word boundaries. Line
+/// breaks are kept, bullet items wrap under their text, and fenced or indented
+/// code is left alone; words longer than `width` get a line of their own.
+pub fn wrap_body(text: &str, width: usize) -> String {
+    let mut in_fence = false;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            lines.push(line.to_string());
+        } else if in_fence || line.starts_with("    ") || line.starts_with('\t') {
+            lines.push(line.to_string());
+        } else {
+            lines.extend(wrap_line(line, width));
+        }
+    }
+    lines.join("\n")
+}
+
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+    let marker_len = ["- ", "* ", "+ ", "• "]
+        .iter()
+        .find(|marker| content.starts_with(*marker))
+        .map_or(0, |marker| marker.chars().count());
+    let hang = format!("{}{}", indent, " ".repeat(marker_len));
+
+    let mut lines = Vec::new();
+    let mut current = indent.to_string();
+    let mut current_len = indent.chars().count();
+    let mut has_word = false;
+    for word in content.split_whitespace() {
+        let word_len = word.chars().count();
+        if has_word && current_len + 1 + word_len > width {
+            lines.push(std::mem::replace(&mut current, hang.clone()));
+            current_len = hang.chars().count();
+            has_word = false;
+        }
+        if has_word {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.push_str(word);
+        current_len += word_len;
+        has_word = true;
+    }
+    lines.push(if has_word { current } else { String::new() });
+    lines
+}
+
+/// The content without the ```` ```json ```` fence some models wrap JSON in.
+pub fn strip_code_fence(content: &str) -> &str {
+    let content = content.trim();
+    content
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.trim_start_matches("json").trim())
+        .unwrap_or(content)
+}
+
+/// ASCII stand-in for a non-ASCII character, or `None` to drop it.
+fn ascii_replacement(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'ď' | 'đ' => "d",
+        'Ď' | 'Đ' => "D",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+        'ğ' => "g",
+        'Ğ' => "G",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'İ' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+        'ř' => "r",
+        'Ř' => "R",
+        'ś' | 'š' | 'ş' => "s",
+        'Ś' | 'Š' | 'Ş' => "S",
+        'ť' | 'ţ' => "t",
+        'Ť' | 'Ţ' => "T",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'œ' => "oe",
+        'Œ' => "OE",
+        '‘' | '’' | '′' => "'",
+        '“' | '”' | '″' => "\"",
+        '–' | '‐' | '−' => "-",
+        '—' => "--",
+        '…' => "...",
+        '•' | '·' => "*",
+        '→' => "->",
+        '←' => "<-",
+        '×' => "x",
+        '\u{a0}' => " ",
+        _ => return None,
+    })
+}
+
+/// `text` with accented letters and typographic punctuation replaced by their
+/// closest ASCII, and everything else non-ASCII, such as emoji, dropped. A space
+/// left dangling by a dropped character goes with it.
+pub fn to_ascii(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut dropped = false;
+    for c in text.chars() {
+        if c.is_ascii() {
+            let dangling = c == ' ' && (out.is_empty() || out.ends_with([' ', '\n']));
+            if !(dropped && dangling) {
+                out.push(c);
+            }
+            dropped = false;
+        } else if let Some(replacement) = ascii_replacement(c) {
+            out.push_str(replacement);
+            dropped = false;
+        } else {
+            dropped = true;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(title: &str, description: &str) -> Commit {
+        Commit {
+            title: title.to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    #[test]
+    fn commit_to_string_separates_title_and_description() {
+        assert_eq!(
+            commit("Fix bug", "Details\nMore details").to_string(),
+            "Fix bug\n\nDetails\nMore details"
+        );
+    }
+
+    #[test]
+    fn commit_to_string_without_description_is_the_title() {
+        assert_eq!(commit("Fix bug", "").to_string(), "Fix bug");
+    }
+
+    #[test]
+    fn commit_to_string_with_blank_description_is_the_title() {
+        assert_eq!(commit("Fix bug", " \n\t\n").to_string(), "Fix bug");
+    }
+
+    #[test]
+    fn commit_rules_check_each_rule() {
+        let commit = |title: &str| Commit {
+            title: title.to_string(),
+            description: "Details".to_string(),
+        };
+        let scoped = CommitRules {
+            max_title_length: Some(30),
+            scope: Some("auth".to_string()),
+            gitmoji: false,
+        };
+        assert_eq!(scoped.check(&commit("fix(auth): handle expiry")), Ok(()));
+        assert!(scoped
+            .check(&commit("fix(api): handle expiry"))
+            .unwrap_err()
+            .contains("`auth`"));
+        assert!(scoped
+            .check(&commit("fix(auth): handle token expiry in every worker"))
+            .unwrap_err()
+            .contains("over the limit of 30"));
+
+        let gitmoji = CommitRules {
+            gitmoji: true,
+            ..CommitRules::default()
+        };
+        assert_eq!(gitmoji.check(&commit("🐛 Fix crash")), Ok(()));
+        assert!(gitmoji
+            .check(&commit("Fix crash"))
+            .unwrap_err()
+            .contains("gitmoji"));
+        let both = CommitRules {
+            scope: Some("auth".to_string()),
+            ..gitmoji
+        };
+        assert_eq!(both.check(&commit("🐛 fix(auth): handle expiry")), Ok(()));
+        assert_eq!(CommitRules::default().check(&commit("Anything")), Ok(()));
+    }
+
+    #[test]
+    fn commit_schema_requires_title_and_description() {
+        let schema = commit_schema();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["description", "title"])
+        );
+        assert_eq!(schema["properties"]["title"]["type"], "string");
+        assert_eq!(schema["properties"]["title"]["minLength"], 1);
+        assert_eq!(schema["properties"]["description"]["minLength"], 1);
+    }
+
+    #[test]
+    fn validate_commit_accepts_complete_commits() {
+        let commit = Commit {
+            title: "Fix bug".to_string(),
+            description: "Details".to_string(),
+        };
+        assert_eq!(validate_commit(&commit), Ok(()));
+    }
+
+    #[test]
+    fn validate_commit_rejects_blank_fields() {
+        let commit = |title: &str, description: &str| Commit {
+            title: title.to_string(),
+            description: description.to_string(),
+        };
+        assert_eq!(
+            validate_commit(&commit("", "Details")),
+            Err("the title is empty".to_string())
+        );
+        assert_eq!(
+            validate_commit(&commit("Fix bug", " \n")),
+            Err("the description is empty".to_string())
+        );
+        assert_eq!(
+            validate_commit(&commit(" ", "")),
+            Err("the title and description are empty".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_commit_reads_valid_json() {
+        assert_eq!(
+            parse_commit(r#"{"title": "Fix bug", "description": "Details"}"#),
+            Ok(Commit {
+                title: "Fix bug".to_string(),
+                description: "Details".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_commit_rejects_missing_fields() {
+        let err = parse_commit(r#"{"title": "Fix bug"}"#).unwrap_err();
+        assert!(err.0.contains("description"), "{}", err);
+        assert!(parse_commit("not json").is_err());
+        assert!(parse_commit(r#"{"title": " ", "description": "Details"}"#).is_err());
+    }
+
+    #[test]
+    fn parse_commit_ignores_extra_fields() {
+        let commit =
+            parse_commit(r#"{"title": "Fix bug", "description": "Details", "type": "fix"}"#)
+                .unwrap();
+        assert_eq!(commit.title, "Fix bug");
+    }
+
+    #[test]
+    fn cap_description_lines_under_the_limit() {
+        assert_eq!(cap_description_lines("- one\n- two\n", 3), "- one\n- two");
+    }
+
+    #[test]
+    fn cap_description_lines_at_the_limit() {
+        assert_eq!(
+            cap_description_lines("- one\n- two\n- three", 3),
+            "- one\n- two\n- three"
+        );
+    }
+
+    #[test]
+    fn cap_description_lines_over_the_limit() {
+        assert_eq!(
+            cap_description_lines("- one\n- two\n- three\n- four", 2),
+            "- one\n- two\n..."
+        );
+        assert_eq!(cap_description_lines("First.\n\nSecond.", 2), "First.\n...");
+        assert_eq!(cap_description_lines("First.", 0), "");
+    }
+
+    #[test]
+    fn cap_description_lines_keeps_bullets_whole() {
+        let desc = "- Add the login page\n- Store the session in a\n  signed cookie\n- Log out";
+        assert_eq!(cap_description_lines(desc, 2), "- Add the login page\n...");
+        // A single long item is cut inside it rather than dropped
+        assert_eq!(
+            cap_description_lines("- Store the session in a\n  signed cookie\n  for a day", 2),
+            "- Store the session in a\n  signed cookie\n..."
+        );
+    }
+
+    #[test]
+    fn normalize_bullets_turns_prose_lines_into_bullets() {
+        assert_eq!(
+            normalize_bullets("Adds the login page.\n\nValidates the password."),
+            "- Adds the login page.\n- Validates the password."
+        );
+    }
+
+    #[test]
+    fn normalize_bullets_keeps_bulleted_input() {
+        let desc = "- Adds the login page\n- Validates the password";
+        assert_eq!(normalize_bullets(desc), desc);
+    }
+
+    #[test]
+    fn normalize_bullets_unifies_markers_and_continuations() {
+        assert_eq!(
+            normalize_bullets(
+                "* Adds the login page\n  with a remember-me box\n\n\n• Drops the old form"
+            ),
+            "- Adds the login page\n  with a remember-me box\n- Drops the old form"
+        );
+        assert_eq!(normalize_bullets("\n\n"), "");
+    }
+
+    #[test]
+    fn wrap_body_wraps_paragraphs_on_word_boundaries() {
+        let text = "Adds a login page with a remember-me checkbox and validates the password before submitting.";
+        assert_eq!(
+            wrap_body(text, 40),
+            "Adds a login page with a remember-me\n\
+             checkbox and validates the password\n\
+             before submitting."
+        );
+    }
+
+    #[test]
+    fn wrap_body_keeps_existing_line_breaks() {
+        let text = "First paragraph.\n\nSecond paragraph that is long enough to wrap.";
+        assert_eq!(
+            wrap_body(text, 30),
+            "First paragraph.\n\nSecond paragraph that is long\nenough to wrap."
+        );
+    }
+
+    #[test]
+    fn wrap_body_hangs_bullet_continuations() {
+        let text = "- Validates the password length before the form is submitted";
+        assert_eq!(
+            wrap_body(text, 30),
+            "- Validates the password\n  length before the form is\n  submitted"
+        );
+    }
+
+    #[test]
+    fn wrap_body_leaves_long_tokens_and_code() {
+        let url = "https://example.com/a/very/long/path/that/cannot/be/broken";
+        assert_eq!(
+            wrap_body(&format!("See {}", url), 20),
+            format!("See\n{}", url)
+        );
+
+        let code = "```\nlet value = compute_something_long(argument_one, argument_two);\n```";
+        assert_eq!(wrap_body(code, 20), code);
+        let indented = "    let value = compute_something_long(argument_one);";
+        assert_eq!(wrap_body(indented, 20), indented);
+    }
+
+    #[test]
+    fn enforce_title_length_keeps_short_titles() {
+        assert_eq!(enforce_title_length("Fix bug", 72), "Fix bug");
+    }
+
+    #[test]
+    fn enforce_title_length_keeps_titles_exactly_at_limit() {
+        let title = "a".repeat(50);
+        assert_eq!(enforce_title_length(&title, 50), title);
+    }
+
+    #[test]
+    fn enforce_title_length_cuts_on_word_boundary() {
+        let title = "Add support for amending the previous commit message";
+        let result = enforce_title_length(title, 30);
+        assert_eq!(result, "Add support for amending the…");
+        assert!(result.chars().count() <= 30);
+    }
+
+    #[test]
+    fn enforce_title_length_cuts_long_words() {
+        let title = "Supercalifragilisticexpialidocious";
+        let result = enforce_title_length(title, 10);
+        assert_eq!(result, "Supercali…");
+        assert_eq!(result.chars().count(), 10);
+    }
+
+    #[test]
+    fn enforce_title_length_counts_characters_not_bytes() {
+        assert_eq!(
+            enforce_title_length("日本語のタイトル", 8),
+            "日本語のタイトル"
+        );
+        assert_eq!(enforce_title_length("日本語のタイトル", 4), "日本語…");
+    }
+
+    #[test]
+    fn enforce_ascii_title_length_cuts_with_three_dots() {
+        let title = "Add support for amending the previous commit";
+        let result = enforce_ascii_title_length(title, 30);
+        assert_eq!(result, "Add support for amending...");
+        assert!(result.is_ascii());
+        assert!(result.chars().count() <= 30);
+        assert_eq!(enforce_ascii_title_length("Fix bug", 2), "");
+    }
+
+    #[test]
+    fn enforce_title_length_zero_limit() {
+        assert_eq!(enforce_title_length("Fix bug", 0), "");
+    }
+
+    #[test]
+    fn apply_affixes_wraps_the_title() {
+        assert_eq!(
+            apply_affixes("Add login", "[PROJ-123] ", " (WIP)"),
+            "[PROJ-123] Add login (WIP)"
+        );
+        assert_eq!(apply_affixes("Add login", "", ""), "Add login");
+        assert_eq!(
+            apply_affixes("Add login", "", " [skip ci]"),
+            "Add login [skip ci]"
+        );
+    }
+
+    #[test]
+    fn apply_affixes_doesnt_repeat_an_affix() {
+        assert_eq!(
+            apply_affixes("[PROJ-123] Add login", "[PROJ-123] ", ""),
+            "[PROJ-123] Add login"
+        );
+    }
+
+    #[test]
+    fn apply_affixes_after_capping_stays_within_the_limit() {
+        let (prefix, suffix) = ("[PROJ-123] ", " (WIP)");
+        let budget = 30 - prefix.chars().count() - suffix.chars().count();
+        let title = enforce_title_length("Add support for amending the previous commit", budget);
+        let title = apply_affixes(&title, prefix, suffix);
+        assert_eq!(title, "[PROJ-123] Add support… (WIP)");
+        assert!(title.chars().count() <= 30);
+    }
+
+    #[test]
+    fn to_ascii_transliterates_accents() {
+        assert_eq!(to_ascii("Résumé naïve café"), "Resume naive cafe");
+        assert_eq!(to_ascii("Straße, Łódź, Ærø"), "Strasse, Lodz, AEro");
+        assert_eq!(
+            to_ascii("“Quoted” – it’s done…"),
+            "\"Quoted\" - it's done..."
+        );
+        assert_eq!(to_ascii("Plain ASCII stays"), "Plain ASCII stays");
+    }
+
+    #[test]
+    fn to_ascii_drops_emoji() {
+        assert_eq!(to_ascii("✨ Add login"), "Add login");
+        assert_eq!(to_ascii("Fix 🐛 in parser"), "Fix in parser");
+        assert_eq!(to_ascii("Done 🎉"), "Done ");
+        assert_eq!(
+            to_ascii("- ✅ tests\n- 🔥 dead code"),
+            "- tests\n- dead code"
+        );
+    }
+
+    #[test]
+    fn to_ascii_drops_cjk() {
+        assert_eq!(to_ascii("日本語"), "");
+        assert_eq!(to_ascii("Translate 日本語 strings"), "Translate strings");
+        assert_eq!(to_ascii("Add 中文 README"), "Add README");
+    }
+
+    #[test]
+    fn strip_code_fence_unwraps_fenced_json() {
+        let json = r#"{"title":"Fix bug","description":"Details"}"#;
+        assert_eq!(strip_code_fence(&format!("```json\n{}\n```", json)), json);
+        assert_eq!(strip_code_fence(&format!("```\n{}\n```\n", json)), json);
+        assert_eq!(strip_code_fence(json), json);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::error::AppError;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Runs `cmd` through the shell with `message` on stdin and returns what it
+/// printed, trimmed. A failing command or an empty result is an error.
+pub fn run_post_process(cmd: &str, message: &str) -> Result<String, AppError> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, cmd])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::PostProcess(format!("couldn't run `{}`: {}", cmd, e)))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = message.to_string();
+    // Written from a thread so a command that prints as it reads can't block on a full pipe
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| AppError::PostProcess(e.to_string()))?;
+    // A command that exits without reading its input breaks the pipe; its status says more
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(AppError::PostProcess(format!(
+            "`{}` exited with {}",
+            cmd, output.status
+        )));
+    }
+    let processed = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if processed.is_empty() {
+        return Err(AppError::PostProcess(format!(
+            "`{}` printed an empty message",
+            cmd
+        )));
+    }
+    Ok(processed)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_post_process_uses_the_command_output() {
+        assert_eq!(
+            run_post_process("cat", "Fix bug\n\nDetails\n"),
+            Ok("Fix bug\n\nDetails".to_string())
+        );
+        assert_eq!(
+            run_post_process("tr a-z A-Z", "fix bug"),
+            Ok("FIX BUG".to_string())
+        );
+    }
+
+    #[test]
+    fn run_post_process_fails_with_the_command() {
+        let error = run_post_process("exit 3", "Fix bug").unwrap_err();
+        assert!(matches!(error, AppError::PostProcess(_)));
+        assert!(error.to_string().contains("exited with"), "{}", error);
+    }
+
+    #[test]
+    fn run_post_process_rejects_an_empty_message() {
+        assert!(matches!(
+            run_post_process("cat > /dev/null", "Fix bug"),
+            Err(AppError::PostProcess(_))
+        ));
+    }
+}
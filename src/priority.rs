@@ -0,0 +1,135 @@
+use crate::{mapreduce::split_diff_by_file, truncate_to_n_tokens};
+use regex::Regex;
+
+/// Comma-separated globs from `AUTO_COMMIT_PRIORITY`, e.g. `src/api/**,*.proto`.
+pub fn priority_globs_from_env() -> Vec<String> {
+    std::env::var("AUTO_COMMIT_PRIORITY")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn glob_to_regex(glob: &str) -> Option<Regex> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).ok()
+}
+
+/// Whether `path` matches `glob`. `*` and `?` stay within a directory, `**`
+/// crosses them, and globs without a `/` also match the file name alone.
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let Some(regex) = glob_to_regex(glob) else {
+        return false;
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    regex.is_match(path) || (!glob.contains('/') && regex.is_match(name))
+}
+
+/// Reorders `diff` so files matching `priority_globs` come first, whole, and
+/// truncates the remaining files to whatever is left of the `limit` tokens.
+pub fn prioritize_diff(diff: &str, priority_globs: &[String], limit: usize) -> String {
+    let (priority, rest): (Vec<_>, Vec<_>) =
+        split_diff_by_file(diff).into_iter().partition(|file| {
+            priority_globs
+                .iter()
+                .any(|glob| glob_matches(glob, &file.path))
+        });
+    if priority.is_empty() {
+        return diff.to_string();
+    }
+
+    let priority: String = priority.into_iter().map(|file| file.diff).collect();
+    let budget = limit.saturating_sub(priority.split_whitespace().count());
+    let rest: String = rest.into_iter().map(|file| file.diff).collect();
+    let rest = truncate_to_n_tokens(&rest, budget);
+    if rest.is_empty() {
+        priority
+    } else {
+        format!("{}\n{}", priority.trim_end(), rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    const DIFF: &str = "diff --git a/src/noise.rs b/src/noise.rs
+--- a/src/noise.rs
++++ b/src/noise.rs
+@@ -1 +1 @@
+-one two three four five six seven eight
++nine ten eleven twelve thirteen fourteen
+diff --git a/src/api/handler.rs b/src/api/handler.rs
+--- a/src/api/handler.rs
++++ b/src/api/handler.rs
+@@ -1 +1 @@
+-fn old() {}
++fn important() {}
+";
+
+    #[test]
+    fn glob_matches_paths_and_names() {
+        assert!(glob_matches("src/api/**", "src/api/handler.rs"));
+        assert!(glob_matches("**/handler.rs", "src/api/handler.rs"));
+        assert!(glob_matches("**/handler.rs", "handler.rs"));
+        assert!(glob_matches("*.proto", "proto/user.proto"));
+        assert!(glob_matches("src/*.rs", "src/lib.rs"));
+        assert!(!glob_matches("src/*.rs", "src/api/handler.rs"));
+        assert!(!glob_matches("*.proto", "src/lib.rs"));
+    }
+
+    #[test]
+    fn prioritize_diff_keeps_priority_files_when_truncating() {
+        let globs = vec!["src/api/**".to_string()];
+        let result = prioritize_diff(DIFF, &globs, 20);
+
+        assert!(result.starts_with("diff --git a/src/api/handler.rs"));
+        assert!(result.contains("+fn important() {}"));
+        assert!(!result.contains("fourteen"));
+    }
+
+    #[test]
+    fn prioritize_diff_fills_the_remaining_budget() {
+        let globs = vec!["**/handler.rs".to_string()];
+        let result = prioritize_diff(DIFF, &globs, 1_000);
+
+        assert!(result.starts_with("diff --git a/src/api/handler.rs"));
+        assert!(result.contains("fourteen"));
+    }
+
+    #[test]
+    fn prioritize_diff_without_matches_is_unchanged() {
+        assert_eq!(prioritize_diff(DIFF, &[], 5), DIFF);
+        assert_eq!(prioritize_diff(DIFF, &["*.md".to_string()], 5), DIFF);
+    }
+
+    #[test]
+    fn priority_globs_from_env_splits_on_commas() {
+        let _env = env_lock();
+        std::env::set_var("AUTO_COMMIT_PRIORITY", "src/api/**, *.proto,,");
+        assert_eq!(priority_globs_from_env(), ["src/api/**", "*.proto"]);
+        std::env::remove_var("AUTO_COMMIT_PRIORITY");
+        assert!(priority_globs_from_env().is_empty());
+    }
+}
@@ -0,0 +1,498 @@
+use crate::message::DEFAULT_MAX_TITLE_LENGTH;
+use regex::{Captures, Regex};
+use std::{collections::HashMap, path::Path, sync::LazyLock};
+
+/// A `{name}` placeholder in a custom system prompt.
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([a-z_]+)\}").unwrap());
+
+/// A named set of extra instructions, picked with `--preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptPreset {
+    pub name: &'static str,
+    pub instructions: &'static str,
+    /// Whether the description is dropped from the commit, as with `--title-only`.
+    pub title_only: bool,
+}
+
+static PRESETS: LazyLock<HashMap<&'static str, PromptPreset>> = LazyLock::new(|| {
+    [
+        PromptPreset {
+            name: "detailed",
+            instructions: "Write a detailed description: explain what changed, why, and any \
+                           follow-up work or caveats, in several short paragraphs.",
+            title_only: false,
+        },
+        PromptPreset {
+            name: "terse",
+            instructions: "Put everything in the title; the description is left out of the \
+                           commit.",
+            title_only: true,
+        },
+        PromptPreset {
+            name: "changelog",
+            instructions: "Phrase the title and description for users reading a changelog: \
+                           describe the visible effect of the change, not its implementation.",
+            title_only: false,
+        },
+    ]
+    .into_iter()
+    .map(|preset| (preset.name, preset))
+    .collect()
+});
+
+/// The built-in preset called `name`, ignoring case.
+pub fn resolve_preset(name: &str) -> Option<PromptPreset> {
+    PRESETS.get(name.trim().to_lowercase().as_str()).copied()
+}
+
+/// Names of the built-in presets, sorted.
+pub fn preset_names() -> Vec<&'static str> {
+    let mut names: Vec<_> = PRESETS.keys().copied().collect();
+    names.sort_unstable();
+    names
+}
+
+/// Hints for changes made mostly to one kind of file, by lowercase extension.
+const EXTENSION_HINTS: &[(&[&str], &str)] = &[
+    (
+        &["sql"],
+        "Most changed files are SQL, likely database migrations or queries; describe the \
+         schema or data change.",
+    ),
+    (
+        &["md", "rst", "adoc"],
+        "Most changed files are documentation; describe what the docs now cover.",
+    ),
+    (
+        &["css", "scss", "sass", "less"],
+        "Most changed files are stylesheets; describe the visual change.",
+    ),
+    (
+        &["yml", "yaml"],
+        "Most changed files are YAML, likely configuration or CI workflows; describe the \
+         behavior they change.",
+    ),
+    (
+        &["proto"],
+        "Most changed files are protobuf definitions; describe the API change and whether \
+         it's backwards compatible.",
+    ),
+    (
+        &["tf"],
+        "Most changed files are Terraform; describe the infrastructure change.",
+    ),
+];
+
+/// The extension shared by more changed files than any other, lowercased, or
+/// `None` on a tie or when no file has one.
+pub fn dominant_extension(files: &[&str]) -> Option<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        if let Some(extension) = Path::new(file).extension().and_then(|e| e.to_str()) {
+            *counts.entry(extension.to_lowercase()).or_default() += 1;
+        }
+    }
+    let max = *counts.values().max()?;
+    let mut leaders = counts.into_iter().filter(|(_, count)| *count == max);
+    let (extension, _) = leaders.next()?;
+    leaders.next().is_none().then_some(extension)
+}
+
+/// The prompt hint for files with `extension`, if there is one.
+pub fn extension_hint(extension: &str) -> Option<&'static str> {
+    EXTENSION_HINTS
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension))
+        .map(|(_, hint)| *hint)
+}
+
+/// Instructions appended to the base system prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptOptions {
+    pub max_title_length: usize,
+    pub gitmoji: bool,
+    /// The repository's commit message template, used as the desired structure.
+    pub template: Option<String>,
+    /// The repository's pull request template, used as the structure of the description.
+    pub pr_template: Option<String>,
+    /// Ticket ID taken from the branch name, to reference in a trailer.
+    pub ticket: Option<String>,
+    /// Component to use as the conventional commit scope.
+    pub scope: Option<String>,
+    /// Scope guessed from the changed files, offered when `scope` isn't set.
+    pub suggested_scope: Option<String>,
+    /// Ask for the description as a bullet list.
+    pub bullets: bool,
+    /// Language to write the message in.
+    pub language: Option<String>,
+    pub preset: Option<PromptPreset>,
+    /// Hint for the kind of file most of the change is in.
+    pub file_hint: Option<&'static str>,
+    /// Most lines the description may have.
+    pub max_description_lines: Option<usize>,
+    /// Only the title is kept; the description is dropped.
+    pub title_only: bool,
+}
+
+impl Default for PromptOptions {
+    fn default() -> Self {
+        Self {
+            max_title_length: DEFAULT_MAX_TITLE_LENGTH,
+            gitmoji: false,
+            template: None,
+            pr_template: None,
+            ticket: None,
+            scope: None,
+            suggested_scope: None,
+            bullets: false,
+            language: None,
+            preset: None,
+            file_hint: None,
+            max_description_lines: None,
+            title_only: false,
+        }
+    }
+}
+
+/// System prompt for `auto-commit explain`: a prose summary for reviewers
+/// instead of a commit message.
+pub fn build_explain_prompt() -> String {
+    "You are an experienced developer explaining a change to the reviewers of a pull request. \
+     Describe what the diff changes and why, in a few short paragraphs of plain prose: start \
+     with a one-sentence overview, then cover the notable changes, and finish with anything \
+     reviewers should check. Don't write a commit message, a title or a JSON object."
+        .to_string()
+}
+
+pub fn build_system_prompt(base: &str, opts: &PromptOptions) -> String {
+    let mut prompt = base.trim_end().to_string();
+    prompt.push_str(&format!(
+        "\n\nKeep the commit title under {} characters.",
+        opts.max_title_length
+    ));
+    if opts.gitmoji {
+        prompt.push_str(
+            "\nStart the title with a single gitmoji (https://gitmoji.dev) matching the change, \
+             e.g. ✨ for a new feature or 🐛 for a bug fix.",
+        );
+    }
+    if opts.title_only {
+        prompt.push_str(
+            "\nOnly the title will be kept, so make it describe the whole change on its own; \
+             keep the description to a few words.",
+        );
+    } else if let Some(max) = opts.max_description_lines {
+        prompt.push_str(&format!("\nKeep the description to at most {} lines.", max));
+    }
+    if opts.bullets {
+        prompt.push_str(
+            "\nWrite the description as a list of `- ` bullet points, one change per line, \
+             with no prose paragraphs.",
+        );
+    }
+    if let Some(language) = &opts.language {
+        prompt.push_str(&format!("\nWrite the commit message in {}.", language));
+    }
+    if let Some(preset) = &opts.preset {
+        prompt.push_str(&format!("\n{}", preset.instructions));
+    }
+    if let Some(hint) = opts.file_hint {
+        prompt.push_str(&format!("\n{}", hint));
+    }
+    if let Some(template) = &opts.template {
+        prompt.push_str(&format!(
+            "\n\nFollow the structure of this commit message template:\n{}",
+            template.trim_end()
+        ));
+    }
+    if let Some(template) = &opts.pr_template {
+        prompt.push_str(&format!(
+            "\n\nStructure the description after the headings of this pull request template, \
+             leaving out sections the change has nothing for:\n{}",
+            template.trim_end()
+        ));
+    }
+    if let Some(scope) = &opts.scope {
+        prompt.push_str(&format!(
+            "\n\nUse '{}' as the conventional commit scope, e.g. `feat({}): ...`.",
+            scope, scope
+        ));
+    } else if let Some(scope) = &opts.suggested_scope {
+        prompt.push_str(&format!(
+            "\n\nAll changed files are under '{}'; if you use a conventional commit scope, \
+             '{}' is a good candidate.",
+            scope, scope
+        ));
+    }
+    if let Some(ticket) = &opts.ticket {
+        prompt.push_str(&format!(
+            "\n\nThis change belongs to ticket {}; end the description with a `Refs: {}` trailer.",
+            ticket, ticket
+        ));
+    }
+    prompt
+}
+
+/// `template` with every `{name}` placeholder that's in `vars` replaced by its
+/// value, e.g. `{branch}`, `{files}` or `{model}` in a custom system prompt.
+/// Placeholders `vars` doesn't know are left as they are.
+pub fn interpolate(template: &str, vars: &HashMap<&str, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &Captures| match vars.get(&caps[1]) {
+            Some(value) => value.clone(),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("branch", "feature/login".to_string()),
+            ("model", "gpt-4.1-nano".to_string()),
+        ])
+    }
+
+    #[test]
+    fn interpolate_replaces_every_known_placeholder() {
+        assert_eq!(
+            interpolate("On {branch}, written by {model}.", &vars()),
+            "On feature/login, written by gpt-4.1-nano."
+        );
+        assert_eq!(
+            interpolate("{branch}: {branch}", &vars()),
+            "feature/login: feature/login"
+        );
+    }
+
+    #[test]
+    fn interpolate_leaves_unknown_placeholders_alone() {
+        assert_eq!(
+            interpolate("Use {files} on {branch}; keep {} and {Branch}.", &vars()),
+            "Use {files} on feature/login; keep {} and {Branch}."
+        );
+        assert_eq!(
+            interpolate("No placeholders.", &HashMap::new()),
+            "No placeholders."
+        );
+    }
+
+    #[test]
+    fn build_system_prompt_adds_title_length() {
+        let prompt = build_system_prompt("Base prompt.", &PromptOptions::default());
+        assert_eq!(
+            prompt,
+            "Base prompt.\n\nKeep the commit title under 72 characters."
+        );
+    }
+
+    #[test]
+    fn build_system_prompt_adds_gitmoji_instructions() {
+        let opts = PromptOptions {
+            gitmoji: true,
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.starts_with("Base prompt."));
+        assert!(prompt.contains("gitmoji"));
+    }
+
+    #[test]
+    fn build_system_prompt_asks_for_bullets() {
+        let opts = PromptOptions {
+            bullets: true,
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.contains("list of `- ` bullet points"));
+    }
+
+    #[test]
+    fn build_system_prompt_includes_template() {
+        let opts = PromptOptions {
+            template: Some("type(scope): subject\n\n# Why?\n".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.ends_with("template:\ntype(scope): subject\n\n# Why?"));
+    }
+
+    #[test]
+    fn build_system_prompt_adds_scope() {
+        let opts = PromptOptions {
+            scope: Some("parser".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.contains("Use 'parser' as the conventional commit scope"));
+    }
+
+    #[test]
+    fn build_system_prompt_suggests_inferred_scope() {
+        let opts = PromptOptions {
+            suggested_scope: Some("parser".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.contains("All changed files are under 'parser'"));
+
+        let opts = PromptOptions {
+            scope: Some("lexer".to_string()),
+            ..opts
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.contains("Use 'lexer'"));
+        assert!(!prompt.contains("'parser'"));
+    }
+
+    #[test]
+    fn build_system_prompt_sets_the_language() {
+        let opts = PromptOptions {
+            language: Some("German".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.ends_with("\nWrite the commit message in German."));
+    }
+
+    #[test]
+    fn resolve_preset_finds_each_built_in() {
+        for name in ["detailed", "terse", "changelog"] {
+            let preset = resolve_preset(name).unwrap();
+            assert_eq!(preset.name, name);
+            assert!(!preset.instructions.is_empty());
+        }
+        assert!(resolve_preset("detailed")
+            .unwrap()
+            .instructions
+            .contains("detailed description"));
+        assert!(resolve_preset("terse")
+            .unwrap()
+            .instructions
+            .contains("everything in the title"));
+        assert!(resolve_preset("terse").unwrap().title_only);
+        assert!(!resolve_preset("detailed").unwrap().title_only);
+        assert!(!resolve_preset("changelog").unwrap().title_only);
+        assert!(resolve_preset("changelog")
+            .unwrap()
+            .instructions
+            .contains("changelog"));
+        assert_eq!(resolve_preset(" Terse "), resolve_preset("terse"));
+        assert_eq!(preset_names(), ["changelog", "detailed", "terse"]);
+    }
+
+    #[test]
+    fn resolve_preset_unknown_name() {
+        assert_eq!(resolve_preset("verbose"), None);
+        assert_eq!(resolve_preset(""), None);
+    }
+
+    #[test]
+    fn build_system_prompt_adds_preset_instructions() {
+        let opts = PromptOptions {
+            preset: resolve_preset("changelog"),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.ends_with("not its implementation."));
+    }
+
+    #[test]
+    fn build_explain_prompt_differs_from_the_commit_prompt() {
+        let explain = build_explain_prompt();
+        let commit = build_system_prompt(crate::DEFAULT_SYSTEM_PROMPT, &PromptOptions::default());
+        assert_ne!(explain, commit);
+        assert!(explain.contains("pull request"));
+        assert!(!explain.contains("Keep the commit title"));
+    }
+
+    #[test]
+    fn build_system_prompt_caps_description_lines() {
+        let opts = PromptOptions {
+            max_description_lines: Some(5),
+            ..PromptOptions::default()
+        };
+        assert!(build_system_prompt("Base", &opts).contains("at most 5 lines"));
+        assert!(!build_system_prompt("Base", &PromptOptions::default()).contains("at most"));
+    }
+
+    #[test]
+    fn build_system_prompt_title_only() {
+        let opts = PromptOptions {
+            title_only: true,
+            max_description_lines: Some(5),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base", &opts);
+        assert!(prompt.contains("Only the title will be kept"));
+        assert!(!prompt.contains("at most 5 lines"));
+    }
+
+    #[test]
+    fn build_system_prompt_follows_the_pr_template() {
+        let opts = PromptOptions {
+            pr_template: Some("## Summary\n\n## Test plan\n".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.contains("pull request template"));
+        assert!(prompt.ends_with("## Summary\n\n## Test plan"));
+    }
+
+    #[test]
+    fn build_system_prompt_references_ticket() {
+        let opts = PromptOptions {
+            ticket: Some("JIRA-123".to_string()),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.ends_with("`Refs: JIRA-123` trailer."));
+    }
+
+    #[test]
+    fn dominant_extension_with_a_clear_majority() {
+        let files = [
+            "migrations/001_users.sql",
+            "migrations/002_posts.SQL",
+            "seeds/posts.sql",
+            "src/db.rs",
+        ];
+        assert_eq!(dominant_extension(&files), Some("sql".to_string()));
+        assert_eq!(dominant_extension(&["README.md"]), Some("md".to_string()));
+    }
+
+    #[test]
+    fn dominant_extension_on_a_tie() {
+        assert_eq!(
+            dominant_extension(&["a.sql", "b.sql", "src/a.rs", "src/b.rs", "Makefile"]),
+            None
+        );
+    }
+
+    #[test]
+    fn dominant_extension_without_extensions() {
+        assert_eq!(
+            dominant_extension(&["Makefile", ".gitignore", "bin/run"]),
+            None
+        );
+        assert_eq!(dominant_extension(&[]), None);
+    }
+
+    #[test]
+    fn extension_hint_for_known_extensions() {
+        assert!(extension_hint("sql")
+            .unwrap()
+            .contains("database migrations"));
+        assert_eq!(extension_hint("rs"), None);
+
+        let opts = PromptOptions {
+            file_hint: extension_hint("sql"),
+            ..PromptOptions::default()
+        };
+        let prompt = build_system_prompt("Base prompt.", &opts);
+        assert!(prompt.ends_with("describe the schema or data change."));
+    }
+}
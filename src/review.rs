@@ -0,0 +1,349 @@
+use anstyle::Style;
+use std::collections::HashSet;
+
+/// What to do with a proposed commit message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewAction {
+    Commit,
+    Edit,
+    Regenerate,
+    Abort,
+}
+
+pub const REVIEW_PROMPT: &str = "[c]ommit / [e]dit / [r]egenerate / [a]bort?";
+/// The review prompt when an empty answer aborts.
+pub const REVIEW_PROMPT_DEFAULT_NO: &str = "[c]ommit / [e]dit / [r]egenerate / [a]bort (default)?";
+
+/// What an empty answer to the review prompt does, from `--confirm-default` or
+/// `AUTO_COMMIT_CONFIRM_DEFAULT`: `no` aborts, anything else commits.
+pub fn confirm_default(setting: Option<&str>) -> ReviewAction {
+    let Some(setting) = setting.map(str::trim).filter(|s| !s.is_empty()) else {
+        return ReviewAction::Commit;
+    };
+    match setting.to_lowercase().as_str() {
+        "n" | "no" | "false" => ReviewAction::Abort,
+        "y" | "yes" | "true" => ReviewAction::Commit,
+        _ => {
+            log::warn!("Unknown confirm default `{}`, using `yes`.", setting);
+            ReviewAction::Commit
+        }
+    }
+}
+
+/// The review prompt to show when an empty answer means `default`.
+pub fn review_prompt(default: ReviewAction) -> &'static str {
+    match default {
+        ReviewAction::Abort => REVIEW_PROMPT_DEFAULT_NO,
+        _ => REVIEW_PROMPT,
+    }
+}
+
+/// Maps an answer to the review prompt to an action; an empty answer is `default`.
+pub fn parse_review_action(input: &str, default: ReviewAction) -> Option<ReviewAction> {
+    match input.trim().to_lowercase().as_str() {
+        "" => Some(default),
+        "c" | "commit" | "y" | "yes" => Some(ReviewAction::Commit),
+        "e" | "edit" => Some(ReviewAction::Edit),
+        "r" | "regenerate" => Some(ReviewAction::Regenerate),
+        "a" | "abort" | "n" | "no" | "q" => Some(ReviewAction::Abort),
+        _ => None,
+    }
+}
+
+/// Whether to color terminal output: only on a terminal, and never with `NO_COLOR` set.
+pub fn use_color(no_color: Option<&str>, is_terminal: bool) -> bool {
+    is_terminal && no_color.is_none_or(str::is_empty)
+}
+
+/// Formats a proposed commit message for the review prompt. Without color it's
+/// the message exactly as it will be committed; with color it sits in a left-hand
+/// frame, with the title bold and the rest dimmed.
+pub fn render_proposal(message: &str, use_color: bool) -> String {
+    if !use_color {
+        return message.to_string();
+    }
+    let frame = Style::new().dimmed();
+    let title = Style::new().bold();
+    let body = Style::new().dimmed();
+    message
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let style = if i == 0 { title } else { body };
+            format!("{frame}|{frame:#} {style}{line}{style:#}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub const DEFAULT_MAX_AUTO_FILES: usize = 50;
+pub const DEFAULT_MAX_AUTO_LINES: usize = 2_000;
+
+/// How many top-level directories the staged files may span before they look
+/// like unrelated changes staged together.
+pub const DEFAULT_MAX_TOP_DIRS: usize = 5;
+
+/// Number of different top-level directories `files` are in; files at the
+/// repository root count as one.
+pub fn distinct_top_dirs(files: &[&str]) -> usize {
+    files
+        .iter()
+        .map(|file| match file.split_once('/') {
+            Some((dir, _)) => dir,
+            None => "",
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// A warning when `files` span more than `max` top-level directories, which
+/// suggests the staging is broader than one change; `max` 0 turns it off.
+pub fn broad_staging_warning(files: &[&str], max: usize) -> Option<String> {
+    let dirs = distinct_top_dirs(files);
+    (max > 0 && dirs > max).then(|| {
+        format!(
+            "The staged files span {} top-level directories; if they're unrelated changes, \
+             consider committing them separately with --split.",
+            dirs
+        )
+    })
+}
+
+/// How big a change may be before `--force` alone no longer skips the review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoCommitLimits {
+    pub max_files: usize,
+    pub max_lines: usize,
+}
+
+impl Default for AutoCommitLimits {
+    fn default() -> Self {
+        Self {
+            max_files: DEFAULT_MAX_AUTO_FILES,
+            max_lines: DEFAULT_MAX_AUTO_LINES,
+        }
+    }
+}
+
+impl AutoCommitLimits {
+    /// Whether a change of `files` files and `lines` changed lines has to be
+    /// confirmed: always without `force`, and when it exceeds a limit unless
+    /// `really` is set as well.
+    pub fn needs_confirmation(self, files: usize, lines: usize, force: bool, really: bool) -> bool {
+        let large = files > self.max_files || lines > self.max_lines;
+        !force || (large && !really)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_top_dirs_counts_each_top_level_directory_once() {
+        assert_eq!(distinct_top_dirs(&[]), 0);
+        assert_eq!(
+            distinct_top_dirs(&["src/main.rs", "src/git.rs", "src/generator/openai.rs"]),
+            1
+        );
+        assert_eq!(
+            distinct_top_dirs(&["README.md", "Cargo.toml", "src/lib.rs", "docs/usage.md"]),
+            3
+        );
+    }
+
+    #[test]
+    fn broad_staging_warning_only_past_the_threshold() {
+        let spread = [
+            "api/a.rs",
+            "web/b.ts",
+            "docs/c.md",
+            "ci/d.yml",
+            "infra/e.tf",
+            "f.md",
+        ];
+        let warning = broad_staging_warning(&spread, 5).unwrap();
+        assert!(
+            warning.contains("span 6 top-level directories"),
+            "{}",
+            warning
+        );
+        assert!(warning.contains("--split"));
+        assert_eq!(broad_staging_warning(&spread, 6), None);
+        assert_eq!(broad_staging_warning(&spread, 0), None);
+
+        let concentrated = ["src/a.rs", "src/b.rs", "src/c/d.rs", "src/e/f/g.rs"];
+        assert_eq!(broad_staging_warning(&concentrated, 1), None);
+    }
+
+    #[test]
+    fn needs_confirmation_covers_every_combination() {
+        let limits = AutoCommitLimits {
+            max_files: 10,
+            max_lines: 100,
+        };
+        // (files, lines, force, really, expected)
+        let cases = [
+            (10, 100, false, false, true),
+            (10, 100, false, true, true),
+            (10, 100, true, false, false),
+            (10, 100, true, true, false),
+            (11, 100, false, false, true),
+            (11, 100, false, true, true),
+            (11, 100, true, false, true),
+            (11, 100, true, true, false),
+            (10, 101, false, false, true),
+            (10, 101, false, true, true),
+            (10, 101, true, false, true),
+            (10, 101, true, true, false),
+            (11, 101, false, false, true),
+            (11, 101, false, true, true),
+            (11, 101, true, false, true),
+            (11, 101, true, true, false),
+        ];
+        for (files, lines, force, really, expected) in cases {
+            assert_eq!(
+                limits.needs_confirmation(files, lines, force, really),
+                expected,
+                "files={} lines={} force={} really={}",
+                files,
+                lines,
+                force,
+                really
+            );
+        }
+    }
+
+    #[test]
+    fn render_proposal_without_color_is_the_message() {
+        let message = "Fix bug\n\nDetails\n\nCloses #12";
+        assert_eq!(render_proposal(message, false), message);
+    }
+
+    #[test]
+    fn render_proposal_with_color_styles_each_line() {
+        let rendered = render_proposal("Fix bug\n\nDetails", true);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("\x1b[1mFix bug\x1b[0m"), "{:?}", lines[0]);
+        assert!(lines[2].contains("\x1b[2mDetails\x1b[0m"), "{:?}", lines[2]);
+    }
+
+    #[test]
+    fn use_color_respects_no_color_and_terminals() {
+        assert!(use_color(None, true));
+        assert!(use_color(Some(""), true));
+        assert!(!use_color(Some("1"), true));
+        assert!(!use_color(None, false));
+    }
+
+    #[test]
+    fn parse_review_action_commit() {
+        assert_eq!(
+            parse_review_action("c", ReviewAction::Commit),
+            Some(ReviewAction::Commit)
+        );
+        assert_eq!(
+            parse_review_action("Commit", ReviewAction::Commit),
+            Some(ReviewAction::Commit)
+        );
+        assert_eq!(
+            parse_review_action("y", ReviewAction::Commit),
+            Some(ReviewAction::Commit)
+        );
+        assert_eq!(
+            parse_review_action("", ReviewAction::Commit),
+            Some(ReviewAction::Commit)
+        );
+        assert_eq!(
+            parse_review_action("  \n", ReviewAction::Commit),
+            Some(ReviewAction::Commit)
+        );
+    }
+
+    #[test]
+    fn parse_review_action_empty_answer_uses_the_default() {
+        assert_eq!(
+            parse_review_action("", ReviewAction::Abort),
+            Some(ReviewAction::Abort)
+        );
+        assert_eq!(
+            parse_review_action("c", ReviewAction::Abort),
+            Some(ReviewAction::Commit)
+        );
+    }
+
+    #[test]
+    fn confirm_default_yes() {
+        assert_eq!(confirm_default(Some("yes")), ReviewAction::Commit);
+        assert_eq!(confirm_default(Some("Y")), ReviewAction::Commit);
+    }
+
+    #[test]
+    fn confirm_default_no() {
+        assert_eq!(confirm_default(Some("no")), ReviewAction::Abort);
+        assert_eq!(confirm_default(Some(" NO ")), ReviewAction::Abort);
+        assert_eq!(review_prompt(ReviewAction::Abort), REVIEW_PROMPT_DEFAULT_NO);
+    }
+
+    #[test]
+    fn confirm_default_unset() {
+        assert_eq!(confirm_default(None), ReviewAction::Commit);
+        assert_eq!(confirm_default(Some("")), ReviewAction::Commit);
+        assert_eq!(confirm_default(Some("maybe")), ReviewAction::Commit);
+        assert_eq!(review_prompt(ReviewAction::Commit), REVIEW_PROMPT);
+    }
+
+    #[test]
+    fn parse_review_action_edit() {
+        assert_eq!(
+            parse_review_action("e", ReviewAction::Commit),
+            Some(ReviewAction::Edit)
+        );
+        assert_eq!(
+            parse_review_action("EDIT", ReviewAction::Commit),
+            Some(ReviewAction::Edit)
+        );
+    }
+
+    #[test]
+    fn parse_review_action_regenerate() {
+        assert_eq!(
+            parse_review_action("r", ReviewAction::Commit),
+            Some(ReviewAction::Regenerate)
+        );
+        assert_eq!(
+            parse_review_action("regenerate\n", ReviewAction::Commit),
+            Some(ReviewAction::Regenerate)
+        );
+    }
+
+    #[test]
+    fn parse_review_action_abort() {
+        assert_eq!(
+            parse_review_action("a", ReviewAction::Commit),
+            Some(ReviewAction::Abort)
+        );
+        assert_eq!(
+            parse_review_action("abort", ReviewAction::Commit),
+            Some(ReviewAction::Abort)
+        );
+        assert_eq!(
+            parse_review_action("n", ReviewAction::Commit),
+            Some(ReviewAction::Abort)
+        );
+        assert_eq!(
+            parse_review_action("q", ReviewAction::Commit),
+            Some(ReviewAction::Abort)
+        );
+    }
+
+    #[test]
+    fn parse_review_action_unknown() {
+        assert_eq!(parse_review_action("x", ReviewAction::Commit), None);
+        assert_eq!(
+            parse_review_action("commit please", ReviewAction::Commit),
+            None
+        );
+    }
+}
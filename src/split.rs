@@ -0,0 +1,361 @@
+//! `--split`: asks the model to group the staged files into logical commits,
+//! then makes one commit per group.
+
+use crate::{error::AppError, message::strip_code_fence};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, io};
+
+/// The model's proposal for splitting the staged changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CommitPlan {
+    #[schemars(length(min = 1))]
+    pub commits: Vec<PlannedCommit>,
+}
+
+/// One commit of a `CommitPlan`: the files it takes and its message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PlannedCommit {
+    #[schemars(length(min = 1))]
+    pub files: Vec<String>,
+    #[schemars(length(min = 1))]
+    pub title: String,
+    pub description: String,
+}
+
+/// System prompt for `--split`, with the `CommitPlan` schema the reply must follow.
+pub fn build_split_prompt() -> String {
+    let mut gen =
+        SchemaGenerator::new(SchemaSettings::openapi3().with(|s| s.inline_subschemas = true));
+    let schema = serde_json::to_value(gen.subschema_for::<CommitPlan>().into_object())
+        .expect("Failed to serialize the plan schema");
+    format!(
+        "You are an experienced developer splitting a change into focused commits. Group the \
+         changed files into as few logical commits as make sense, each doing one thing, in the \
+         order they should be committed. Every changed file must be in exactly one commit. Give \
+         each commit a title under 72 characters and a short description. Reply with only a \
+         JSON object matching this schema:\n{}",
+        schema
+    )
+}
+
+/// Parses the model's plan, which may be wrapped in a code fence, and checks
+/// that it puts each of `staged` in exactly one non-empty commit.
+pub fn parse_plan(reply: &str, staged: &[&str]) -> Result<CommitPlan, AppError> {
+    let plan: CommitPlan = serde_json::from_str(strip_code_fence(reply))
+        .map_err(|e| AppError::Api(format!("The model's commit plan isn't valid: {}", e)))?;
+    validate_plan(&plan, staged).map_err(|e| AppError::Api(format!("The model's {}", e)))?;
+    Ok(plan)
+}
+
+fn validate_plan(plan: &CommitPlan, staged: &[&str]) -> Result<(), String> {
+    if plan.commits.is_empty() {
+        return Err("commit plan is empty".to_string());
+    }
+    let staged: HashSet<&str> = staged.iter().copied().collect();
+    let mut seen = HashSet::new();
+    for commit in &plan.commits {
+        if commit.files.is_empty() || commit.title.trim().is_empty() {
+            return Err(format!(
+                "commit plan has an incomplete commit: {:?}",
+                commit.title
+            ));
+        }
+        for file in &commit.files {
+            if !staged.contains(file.as_str()) {
+                return Err(format!("commit plan lists {}, which isn't staged", file));
+            }
+            if !seen.insert(file.as_str()) {
+                return Err(format!("commit plan lists {} more than once", file));
+            }
+        }
+    }
+    let mut missing: Vec<&str> = staged.difference(&seen).copied().collect();
+    missing.sort_unstable();
+    if !missing.is_empty() {
+        return Err(format!("commit plan leaves out {}", missing.join(", ")));
+    }
+    Ok(())
+}
+
+/// Arguments to `git` that list the staged paths for a plan. Renames are
+/// listed as the deleted old path and the added new one, so a plan can't
+/// commit one without the other going somewhere.
+pub const STAGED_PATHS_ARGS: &[&str] = &["diff", "--staged", "--name-only", "--no-renames"];
+
+/// The git commands that carry out `plan`: unstage everything, then for each
+/// group restore its files' staged versions from `tree` (the index saved with
+/// `git write-tree` beforehand) and commit them with the matching entry of
+/// `messages`. The work tree isn't touched, so edits that weren't staged stay
+/// unstaged.
+pub fn plan_commands(
+    plan: &CommitPlan,
+    messages: &[String],
+    sign: bool,
+    tree: &str,
+) -> Vec<Vec<String>> {
+    let mut commands = vec![vec!["reset".to_string(), "--quiet".to_string()]];
+    for (commit, message) in plan.commits.iter().zip(messages) {
+        let mut restore = vec![
+            "restore".to_string(),
+            format!("--source={}", tree),
+            "--staged".to_string(),
+            "--".to_string(),
+        ];
+        restore.extend(commit.files.iter().cloned());
+        commands.push(restore);
+
+        let mut git_commit = vec!["commit".to_string(), "--quiet".to_string()];
+        if sign {
+            git_commit.push("-S".to_string());
+        }
+        git_commit.extend(["-m".to_string(), message.clone()]);
+        commands.push(git_commit);
+    }
+    commands
+}
+
+/// Runs `commands` in order with `run`, which executes git with the given
+/// arguments and returns whether it succeeded along with its output. Stops at
+/// the first failure; the commits made so far are kept, and the index is put
+/// back from `tree` so the changes not yet committed are staged again.
+pub fn run_plan_with(
+    commands: &[Vec<String>],
+    tree: &str,
+    mut run: impl FnMut(&[&str]) -> io::Result<(bool, String)>,
+) -> Result<(), AppError> {
+    for command in commands {
+        let args: Vec<&str> = command.iter().map(String::as_str).collect();
+        let failure = match run(&args) {
+            Ok((true, _)) => continue,
+            Ok((false, output)) => format!("`git {}` failed: {}", args[0], output),
+            Err(e) => format!("couldn't run `git {}`: {}", args[0], e),
+        };
+        let restored = matches!(run(&["read-tree", tree]), Ok((true, _)));
+        return Err(AppError::Split(if restored {
+            format!(
+                "{}; the changes not yet committed are staged again",
+                failure
+            )
+        } else {
+            format!(
+                "{}; run `git read-tree {}` to stage the changes not yet committed again",
+                failure, tree
+            )
+        }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(plan: &CommitPlan) -> Vec<String> {
+        plan.commits.iter().map(|c| c.title.clone()).collect()
+    }
+
+    fn sample_plan() -> CommitPlan {
+        CommitPlan {
+            commits: vec![
+                PlannedCommit {
+                    files: vec!["src/login.rs".to_string(), "src/lib.rs".to_string()],
+                    title: "Add a login page".to_string(),
+                    description: "Users can now sign in.".to_string(),
+                },
+                PlannedCommit {
+                    files: vec!["README.md".to_string()],
+                    title: "Fix a typo in the README".to_string(),
+                    description: String::new(),
+                },
+            ],
+        }
+    }
+
+    const STAGED: [&str; 3] = ["README.md", "src/lib.rs", "src/login.rs"];
+
+    #[test]
+    fn parse_plan_reads_a_fenced_plan() {
+        let reply = format!(
+            "```json\n{}\n```",
+            serde_json::to_string(&sample_plan()).unwrap()
+        );
+        assert_eq!(parse_plan(&reply, &STAGED), Ok(sample_plan()));
+    }
+
+    #[test]
+    fn parse_plan_rejects_plans_that_dont_cover_the_staged_files() {
+        let mut unknown = sample_plan();
+        unknown.commits[1].files.push("src/other.rs".to_string());
+        let mut duplicate = sample_plan();
+        duplicate.commits[1].files.push("src/lib.rs".to_string());
+        let mut missing = sample_plan();
+        missing.commits.pop();
+        let mut empty = sample_plan();
+        empty.commits[1].files.clear();
+
+        for (plan, expected) in [
+            (unknown, "src/other.rs, which isn't staged"),
+            (duplicate, "src/lib.rs more than once"),
+            (missing, "leaves out README.md"),
+            (empty, "incomplete commit"),
+        ] {
+            let reply = serde_json::to_string(&plan).unwrap();
+            match parse_plan(&reply, &STAGED) {
+                Err(AppError::Api(msg)) => assert!(msg.contains(expected), "{}", msg),
+                other => panic!("expected an API error, got {:?}", other),
+            }
+        }
+        assert!(parse_plan("Commit everything at once.", &STAGED).is_err());
+    }
+
+    #[test]
+    fn plan_commands_stage_and_commit_each_group() {
+        let commands = plan_commands(&sample_plan(), &titles(&sample_plan()), false, "4b825dc");
+        assert_eq!(
+            commands,
+            [
+                vec!["reset", "--quiet"],
+                vec![
+                    "restore",
+                    "--source=4b825dc",
+                    "--staged",
+                    "--",
+                    "src/login.rs",
+                    "src/lib.rs"
+                ],
+                vec!["commit", "--quiet", "-m", "Add a login page"],
+                vec!["restore", "--source=4b825dc", "--staged", "--", "README.md"],
+                vec!["commit", "--quiet", "-m", "Fix a typo in the README"],
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_commands_sign_each_commit() {
+        let commands = plan_commands(&sample_plan(), &titles(&sample_plan()), true, "4b825dc");
+        assert_eq!(
+            commands[2],
+            ["commit", "--quiet", "-S", "-m", "Add a login page"]
+        );
+    }
+
+    #[test]
+    fn run_plan_with_stops_at_the_first_failure() {
+        let commands = plan_commands(&sample_plan(), &titles(&sample_plan()), false, "4b825dc");
+        let mut calls = Vec::new();
+        let result = run_plan_with(&commands, "4b825dc", |args| {
+            calls.push(args.join(" "));
+            Ok((calls.len() != 3, "hook rejected the commit".to_string()))
+        });
+        assert_eq!(
+            result,
+            Err(AppError::Split(
+                "`git commit` failed: hook rejected the commit; \
+                 the changes not yet committed are staged again"
+                    .to_string()
+            ))
+        );
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls[3], "read-tree 4b825dc");
+    }
+
+    #[test]
+    fn run_plan_with_names_the_recovery_command_if_restoring_fails() {
+        let commands = plan_commands(&sample_plan(), &titles(&sample_plan()), false, "4b825dc");
+        let result = run_plan_with(&commands, "4b825dc", |args| {
+            Ok((args[0] == "reset", "index.lock exists".to_string()))
+        });
+        match result {
+            Err(AppError::Split(msg)) => {
+                assert!(msg.starts_with("`git restore` failed"), "{}", msg);
+                assert!(msg.contains("run `git read-tree 4b825dc`"), "{}", msg);
+            }
+            other => panic!("expected a split error, got {:?}", other),
+        }
+    }
+
+    /// Runs git in `dir` with an identity and without the user's config.
+    fn git_in(dir: &std::path::Path, args: &[&str]) -> io::Result<(bool, String)> {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success(), stdout))
+    }
+
+    #[test]
+    fn run_plan_keeps_unstaged_edits_and_renames_whole() {
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("auto-commit-split-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| {
+            let (ok, out) = git_in(&dir, args).unwrap();
+            assert!(ok, "git {:?}: {}", args, out);
+            out
+        };
+        git(&["init", "-q"]);
+        fs::write(dir.join("lib.rs"), "one\n").unwrap();
+        fs::write(dir.join("old.rs"), "moved\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Initial"]);
+
+        // lib.rs is partly staged, old.rs is renamed
+        fs::write(dir.join("lib.rs"), "one\ntwo\n").unwrap();
+        git(&["add", "lib.rs"]);
+        fs::write(dir.join("lib.rs"), "one\ntwo\nthree\n").unwrap();
+        git(&["mv", "old.rs", "new.rs"]);
+
+        let staged = git(STAGED_PATHS_ARGS);
+        let staged: Vec<&str> = staged.lines().collect();
+        assert_eq!(staged, ["lib.rs", "new.rs", "old.rs"]);
+        let plan = CommitPlan {
+            commits: vec![
+                PlannedCommit {
+                    files: vec!["lib.rs".to_string()],
+                    title: "Add two".to_string(),
+                    description: String::new(),
+                },
+                PlannedCommit {
+                    files: vec!["new.rs".to_string(), "old.rs".to_string()],
+                    title: "Rename old to new".to_string(),
+                    description: String::new(),
+                },
+            ],
+        };
+        validate_plan(&plan, &staged).unwrap();
+        let tree = git(&["write-tree"]);
+        let commands = plan_commands(&plan, &titles(&plan), false, &tree);
+        run_plan_with(&commands, &tree, |args| git_in(&dir, args)).unwrap();
+
+        let first = git(&["show", "HEAD~1:lib.rs"]);
+        let renamed = git(&["ls-tree", "--name-only", "HEAD"]);
+        let unstaged = git(&["diff", "--name-only"]);
+        let work_tree = fs::read_to_string(dir.join("lib.rs")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first, "one\ntwo");
+        assert_eq!(renamed, "lib.rs\nnew.rs");
+        assert_eq!(unstaged, "lib.rs");
+        assert_eq!(work_tree, "one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn build_split_prompt_includes_the_schema() {
+        let prompt = build_split_prompt();
+        assert!(prompt.contains("\"commits\""));
+        assert!(prompt.contains("\"files\""));
+    }
+}
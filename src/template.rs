@@ -0,0 +1,150 @@
+use crate::git::{git_output, repo_root};
+use std::path::{Path, PathBuf};
+
+/// Where to look for the commit template: the configured `commit.template`
+/// (relative paths are taken from the repo root), or `.gitmessage` in the repo root.
+pub fn resolve_template_path(configured: Option<&str>, repo_root: &Path) -> PathBuf {
+    match configured.map(str::trim).filter(|path| !path.is_empty()) {
+        Some(path) => repo_root.join(path),
+        None => repo_root.join(".gitmessage"),
+    }
+}
+
+/// Reads the template at `path`, ignoring missing or blank files.
+pub fn load_template(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .filter(|template| !template.trim().is_empty())
+}
+
+/// Where GitHub looks for a repository's pull request template, in the order
+/// it checks them.
+pub const PR_TEMPLATE_PATHS: &[&str] = &[
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+];
+
+/// The first of `PR_TEMPLATE_PATHS` that exists under `repo_root`.
+pub fn find_pr_template(repo_root: &Path) -> Option<PathBuf> {
+    PR_TEMPLATE_PATHS
+        .iter()
+        .map(|path| repo_root.join(path))
+        .find(|path| path.is_file())
+}
+
+/// The pull request template at `path` (relative paths are taken from the repo
+/// root), or the one GitHub would use when `path` isn't given.
+pub fn read_pr_template(path: Option<&Path>) -> Option<String> {
+    let root = repo_root().unwrap_or_else(|| PathBuf::from("."));
+    let path = match path {
+        Some(path) => root.join(path),
+        None => find_pr_template(&root)?,
+    };
+    load_template(&path)
+}
+
+/// The commit message template configured for the current repository, if any.
+pub fn read_commit_template() -> Option<String> {
+    let root = repo_root().unwrap_or_else(|| PathBuf::from("."));
+    // `--path` expands a leading `~` the same way git does
+    let configured = git_output(&["config", "--path", "--get", "commit.template"]);
+    load_template(&resolve_template_path(configured.as_deref(), &root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_repo(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("auto-commit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn configured_template_is_loaded() {
+        let root = temp_repo("template-configured");
+        fs::write(root.join("commit-template.txt"), "Subject\n\n# Why?\n").unwrap();
+
+        let path = resolve_template_path(Some("commit-template.txt"), &root);
+        assert_eq!(path, root.join("commit-template.txt"));
+        assert_eq!(load_template(&path).as_deref(), Some("Subject\n\n# Why?\n"));
+
+        let absolute = root.join("commit-template.txt");
+        let path = resolve_template_path(absolute.to_str(), Path::new("/elsewhere"));
+        assert_eq!(path, absolute);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn gitmessage_is_used_without_config() {
+        let root = temp_repo("template-gitmessage");
+        assert_eq!(resolve_template_path(None, &root), root.join(".gitmessage"));
+        assert_eq!(load_template(&resolve_template_path(None, &root)), None);
+
+        fs::write(root.join(".gitmessage"), "type(scope): subject\n").unwrap();
+        assert_eq!(
+            load_template(&resolve_template_path(None, &root)).as_deref(),
+            Some("type(scope): subject\n")
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn find_pr_template_checks_each_known_location() {
+        for (i, location) in PR_TEMPLATE_PATHS.iter().enumerate() {
+            let root = temp_repo(&format!("pr-template-{}", i));
+            let path = root.join(location);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "## Summary\n\n## Testing\n").unwrap();
+
+            // Case-insensitive file systems find the other spelling first
+            let found = find_pr_template(&root).unwrap();
+            assert!(found
+                .to_string_lossy()
+                .eq_ignore_ascii_case(&path.to_string_lossy()));
+
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn find_pr_template_prefers_the_github_directory() {
+        let root = temp_repo("pr-template-order");
+        fs::create_dir_all(root.join(".github")).unwrap();
+        fs::write(root.join("pull_request_template.md"), "root").unwrap();
+        fs::write(root.join(".github/pull_request_template.md"), "github").unwrap();
+        assert_eq!(
+            find_pr_template(&root),
+            Some(root.join(".github/pull_request_template.md"))
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn find_pr_template_without_a_template() {
+        let root = temp_repo("pr-template-none");
+        fs::create_dir_all(root.join(".github")).unwrap();
+        assert_eq!(find_pr_template(&root), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nonexistent_template_path_is_ignored() {
+        let root = temp_repo("template-missing");
+        let path = resolve_template_path(Some("does/not/exist.txt"), &root);
+        assert_eq!(load_template(&path), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
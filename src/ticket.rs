@@ -0,0 +1,93 @@
+use log::warn;
+use regex::Regex;
+
+/// Jira-style keys such as `JIRA-123` or `PROJ2-7`.
+pub const DEFAULT_TICKET_PATTERN: &str = r"[A-Z][A-Z0-9]+-\d+";
+
+/// `AUTO_COMMIT_TICKET_PATTERN`, or `DEFAULT_TICKET_PATTERN` when it's unset or invalid.
+pub fn ticket_pattern() -> Regex {
+    if let Ok(pattern) = std::env::var("AUTO_COMMIT_TICKET_PATTERN") {
+        match Regex::new(&pattern) {
+            Ok(regex) => return regex,
+            Err(e) => warn!("Ignoring invalid AUTO_COMMIT_TICKET_PATTERN: {}", e),
+        }
+    }
+    Regex::new(DEFAULT_TICKET_PATTERN).expect("default ticket pattern is valid")
+}
+
+/// The ticket ID in `branch`, using the configured `ticket_pattern()`.
+pub fn extract_ticket(branch: &str) -> Option<String> {
+    extract_ticket_with(branch, &ticket_pattern())
+}
+
+/// The first match of `pattern` in `branch`, or its first capture group if it has one.
+pub fn extract_ticket_with(branch: &str, pattern: &Regex) -> Option<String> {
+    let captures = pattern.captures(branch)?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(0))
+        .map(|m| m.as_str().to_string())
+}
+
+/// The issue number a branch is named after, e.g. `fix/123-crash`, `issue-42`
+/// or `gh-7`.
+pub fn extract_issue_number(branch: &str) -> Option<u64> {
+    let pattern =
+        Regex::new(r"(?i)(?:^|/)(?:(?:issues?|gh)[-_/]?)?(\d+)(?:[-_]|$)").expect("valid regex");
+    pattern.captures(branch)?[1].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    #[test]
+    fn extract_issue_number_reads_numbered_branches() {
+        assert_eq!(extract_issue_number("fix/123-crash-on-start"), Some(123));
+        assert_eq!(extract_issue_number("issue-42-add-login"), Some(42));
+        assert_eq!(extract_issue_number("feature/GH-7"), Some(7));
+        assert_eq!(extract_issue_number("main"), None);
+        assert_eq!(extract_issue_number("feature/JIRA-123-add-login"), None);
+    }
+
+    #[test]
+    fn extract_ticket_finds_jira_keys() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_TICKET_PATTERN");
+        assert_eq!(
+            extract_ticket("feature/JIRA-123-add-login"),
+            Some("JIRA-123".to_string())
+        );
+        assert_eq!(extract_ticket("PROJ2-7"), Some("PROJ2-7".to_string()));
+        assert_eq!(
+            extract_ticket("bugfix/ABC-42_fix_crash"),
+            Some("ABC-42".to_string())
+        );
+    }
+
+    #[test]
+    fn extract_ticket_ignores_branches_without_tickets() {
+        let _env = env_lock();
+        std::env::remove_var("AUTO_COMMIT_TICKET_PATTERN");
+        assert_eq!(extract_ticket("main"), None);
+        assert_eq!(extract_ticket("feature/add-login"), None);
+        // What `git rev-parse --abbrev-ref HEAD` prints on a detached HEAD
+        assert_eq!(extract_ticket("HEAD"), None);
+    }
+
+    #[test]
+    fn extract_ticket_uses_configured_pattern() {
+        let _env = env_lock();
+        std::env::set_var("AUTO_COMMIT_TICKET_PATTERN", r"issue-(\d+)");
+        assert_eq!(extract_ticket("fix/issue-42-crash"), Some("42".to_string()));
+        assert_eq!(extract_ticket("feature/JIRA-123"), None);
+
+        std::env::set_var("AUTO_COMMIT_TICKET_PATTERN", "(");
+        assert_eq!(
+            extract_ticket("feature/JIRA-123"),
+            Some("JIRA-123".to_string())
+        );
+        std::env::remove_var("AUTO_COMMIT_TICKET_PATTERN");
+    }
+}
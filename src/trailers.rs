@@ -0,0 +1,228 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// `Co-authored-by:` trailers for `authors`, one per line, each given as
+/// `Name <email>`.
+pub fn format_coauthors(authors: &[String]) -> Result<String, String> {
+    let format = Regex::new(r"^[^<>]*[^<>\s] <[^<>@\s]+@[^<>@\s]+>$").expect("valid regex");
+    authors
+        .iter()
+        .map(|author| {
+            let author = author.trim();
+            if format.is_match(author) {
+                Ok(format!("Co-authored-by: {}", author))
+            } else {
+                Err(format!(
+                    "Invalid co-author `{}`, expected `Name <email>`.",
+                    author
+                ))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|lines| lines.join("\n"))
+}
+
+/// The DCO `Signed-off-by:` trailer for the given identity.
+pub fn signoff_trailer(name: &str, email: &str) -> String {
+    format!("Signed-off-by: {} <{}>", name.trim(), email.trim())
+}
+
+/// `Closes #N` trailers for `issues`, one per line, without repeats.
+pub fn format_issue_trailers(issues: &[u64]) -> String {
+    let mut seen = Vec::new();
+    for issue in issues {
+        if !seen.contains(issue) {
+            seen.push(*issue);
+        }
+    }
+    seen.iter()
+        .map(|issue| format!("Closes #{}", issue))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appends a block of trailers to `message`, separated by a blank line.
+pub fn append_trailer_block(message: &str, trailers: &str) -> String {
+    if trailers.is_empty() {
+        return message.to_string();
+    }
+    format!("{}\n\n{}", message.trim_end(), trailers)
+}
+
+static TRAILER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9-]*: \S.*$").expect("valid regex"));
+
+static CLOSES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^Closes #\d+$").expect("valid regex"));
+
+/// Whether every line of `paragraph` is a trailer, counting `Closes #N` ones.
+fn is_trailer_block(paragraph: &str) -> bool {
+    paragraph
+        .lines()
+        .all(|line| TRAILER.is_match(line) || CLOSES.is_match(line))
+}
+
+/// Appends `Key: Value` trailers such as `Reviewed-by: Ada <ada@example.com>` to
+/// `message`. They join a trailer block the message already ends with, or
+/// start one after a blank line; trailers already present are skipped.
+pub fn append_trailers(message: &str, trailers: &[String]) -> Result<String, String> {
+    let message = message.trim_end();
+    let last_paragraph = message.rsplit("\n\n").next().unwrap_or_default();
+    let has_block = message.contains("\n\n") && is_trailer_block(last_paragraph);
+
+    let mut lines: Vec<String> = Vec::new();
+    for trailer in trailers {
+        let trailer = trailer.trim();
+        if !TRAILER.is_match(trailer) {
+            return Err(format!(
+                "Invalid trailer `{}`, expected `Key: Value`.",
+                trailer
+            ));
+        }
+        let present = has_block && last_paragraph.lines().any(|line| line == trailer);
+        if !present && !lines.iter().any(|line| line == trailer) {
+            lines.push(trailer.to_string());
+        }
+    }
+
+    if lines.is_empty() {
+        Ok(message.to_string())
+    } else if has_block {
+        Ok(format!("{}\n{}", message, lines.join("\n")))
+    } else {
+        Ok(append_trailer_block(message, &lines.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_coauthors_formats_valid_entries() {
+        let authors = vec![
+            "Ada Lovelace <ada@example.com>".to_string(),
+            " Alan Turing <alan@example.org> ".to_string(),
+        ];
+        assert_eq!(
+            format_coauthors(&authors),
+            Ok("Co-authored-by: Ada Lovelace <ada@example.com>\n\
+                Co-authored-by: Alan Turing <alan@example.org>"
+                .to_string())
+        );
+        assert_eq!(format_coauthors(&[]), Ok(String::new()));
+    }
+
+    #[test]
+    fn format_coauthors_rejects_malformed_entries() {
+        for author in [
+            "no-email-here",
+            "<ada@example.com>",
+            "Ada <not-an-email>",
+            "Ada ada@example.com",
+        ] {
+            assert!(
+                format_coauthors(&[author.to_string()]).is_err(),
+                "{}",
+                author
+            );
+        }
+    }
+
+    #[test]
+    fn signoff_trailer_uses_the_identity() {
+        assert_eq!(
+            signoff_trailer("Ada Lovelace", " ada@example.com\n"),
+            "Signed-off-by: Ada Lovelace <ada@example.com>"
+        );
+        assert!(append_trailers("", &[signoff_trailer("Ada", "ada@example.com")]).is_ok());
+    }
+
+    #[test]
+    fn format_issue_trailers_lists_each_issue() {
+        assert_eq!(format_issue_trailers(&[12, 7]), "Closes #12\nCloses #7");
+    }
+
+    #[test]
+    fn format_issue_trailers_drops_repeats() {
+        assert_eq!(
+            format_issue_trailers(&[12, 7, 12, 7]),
+            "Closes #12\nCloses #7"
+        );
+    }
+
+    #[test]
+    fn format_issue_trailers_without_issues_is_empty() {
+        assert_eq!(format_issue_trailers(&[]), "");
+        assert_eq!(
+            append_trailer_block("Fix bug", &format_issue_trailers(&[])),
+            "Fix bug"
+        );
+    }
+
+    #[test]
+    fn append_trailer_block_adds_a_blank_line() {
+        assert_eq!(
+            append_trailer_block(
+                "Fix bug\n\nDetails\n",
+                "Co-authored-by: Ada <ada@example.com>"
+            ),
+            "Fix bug\n\nDetails\n\nCo-authored-by: Ada <ada@example.com>"
+        );
+        assert_eq!(append_trailer_block("Fix bug", ""), "Fix bug");
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn append_trailers_starts_a_block_after_a_blank_line() {
+        assert_eq!(
+            append_trailers(
+                "Fix bug\n\nDetails\n",
+                &strings(&[
+                    "Reviewed-by: Ada <ada@example.com>",
+                    " Signed-off-by: Alan <alan@example.org>"
+                ])
+            ),
+            Ok("Fix bug\n\nDetails\n\nReviewed-by: Ada <ada@example.com>\n\
+                Signed-off-by: Alan <alan@example.org>"
+                .to_string())
+        );
+        assert_eq!(append_trailers("Fix bug\n", &[]), Ok("Fix bug".to_string()));
+    }
+
+    #[test]
+    fn append_trailers_joins_an_existing_block_without_repeats() {
+        let message = "Fix bug\n\nDetails\n\nCloses #12\nReviewed-by: Ada <ada@example.com>";
+        assert_eq!(
+            append_trailers(
+                message,
+                &strings(&[
+                    "Reviewed-by: Ada <ada@example.com>",
+                    "Tested-by: CI",
+                    "Tested-by: CI"
+                ])
+            ),
+            Ok(format!("{}\nTested-by: CI", message))
+        );
+    }
+
+    #[test]
+    fn append_trailers_rejects_malformed_trailers() {
+        for trailer in [
+            "Reviewed-by Ada",
+            "Reviewed by: Ada",
+            ": Ada",
+            "Reviewed-by:",
+            "Key:Value",
+        ] {
+            assert!(
+                append_trailers("Fix bug", &strings(&[trailer])).is_err(),
+                "{}",
+                trailer
+            );
+        }
+    }
+}
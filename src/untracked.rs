@@ -0,0 +1,98 @@
+use log::debug;
+use std::io;
+
+/// Untracked files larger than this are listed without their contents.
+pub const MAX_UNTRACKED_FILE_BYTES: usize = 16 * 1024;
+
+/// Synthetic "new file" diffs for untracked `files`, read through `read_fn`.
+/// Binary files and files over `MAX_UNTRACKED_FILE_BYTES` are listed without
+/// contents; files that can't be read are skipped.
+pub fn build_untracked_context(
+    files: &[&str],
+    read_fn: impl Fn(&str) -> io::Result<Vec<u8>>,
+) -> String {
+    let mut context = String::new();
+    for path in files {
+        let bytes = match read_fn(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("Skipping untracked file {}: {}", path, e);
+                continue;
+            }
+        };
+        context.push_str(&format!(
+            "diff --git a/{0} b/{0}\nnew file mode 100644\n",
+            path
+        ));
+        let text = std::str::from_utf8(&bytes)
+            .ok()
+            .filter(|text| !text.contains('\0'));
+        match text {
+            None => context.push_str(&format!("Binary files /dev/null and b/{} differ\n", path)),
+            Some(_) if bytes.len() > MAX_UNTRACKED_FILE_BYTES => context.push_str(&format!(
+                "--- /dev/null\n+++ b/{}\n(new file of {} bytes, contents omitted)\n",
+                path,
+                bytes.len()
+            )),
+            Some(text) => {
+                let lines: Vec<&str> = text.lines().collect();
+                context.push_str(&format!(
+                    "--- /dev/null\n+++ b/{}\n@@ -0,0 +1,{} @@\n",
+                    path,
+                    lines.len()
+                ));
+                for line in lines {
+                    context.push('+');
+                    context.push_str(line);
+                    context.push('\n');
+                }
+            }
+        }
+    }
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &str) -> io::Result<Vec<u8>> {
+        match path {
+            "notes.txt" => Ok(b"first\nsecond\n".to_vec()),
+            "logo.png" => Ok(vec![0x89, b'P', b'N', b'G', 0, 0]),
+            "big.log" => Ok(vec![b'a'; MAX_UNTRACKED_FILE_BYTES + 1]),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "missing")),
+        }
+    }
+
+    #[test]
+    fn build_untracked_context_adds_text_files() {
+        assert_eq!(
+            build_untracked_context(&["notes.txt"], read),
+            "diff --git a/notes.txt b/notes.txt\nnew file mode 100644\n\
+             --- /dev/null\n+++ b/notes.txt\n@@ -0,0 +1,2 @@\n+first\n+second\n"
+        );
+    }
+
+    #[test]
+    fn build_untracked_context_omits_binary_contents() {
+        let context = build_untracked_context(&["logo.png"], read);
+        assert!(context.ends_with("Binary files /dev/null and b/logo.png differ\n"));
+        assert!(!context.contains("PNG"));
+    }
+
+    #[test]
+    fn build_untracked_context_omits_large_files() {
+        let context = build_untracked_context(&["big.log"], read);
+        assert!(context.contains(&format!(
+            "(new file of {} bytes, contents omitted)",
+            MAX_UNTRACKED_FILE_BYTES + 1
+        )));
+        assert!(!context.contains("+aaa"));
+    }
+
+    #[test]
+    fn build_untracked_context_skips_unreadable_files() {
+        assert_eq!(build_untracked_context(&["gone.txt"], read), "");
+    }
+}
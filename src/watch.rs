@@ -0,0 +1,38 @@
+//! `auto-commit watch`: re-proposes a message whenever the staged changes do.
+
+use crate::cache::fnv1a;
+use std::time::Duration;
+
+/// How often the staged diff is checked for changes.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fingerprint of a staged diff, compared between polls.
+pub fn diff_hash(diff: &str) -> u64 {
+    fnv1a(diff.as_bytes())
+}
+
+/// Whether the staged diff hashed to `current` differs from the one last seen,
+/// which is `None` before the first poll.
+pub fn index_changed(prev_hash: Option<u64>, current: u64) -> bool {
+    prev_hash != Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_hash_is_stable_and_content_sensitive() {
+        let diff = "diff --git a/a b/a\n+one\n";
+        assert_eq!(diff_hash(diff), diff_hash(diff));
+        assert_ne!(diff_hash(diff), diff_hash("diff --git a/a b/a\n+two\n"));
+    }
+
+    #[test]
+    fn index_changed_on_the_first_poll_and_on_new_content() {
+        let first = diff_hash("+one");
+        assert!(index_changed(None, first));
+        assert!(!index_changed(Some(first), first));
+        assert!(index_changed(Some(first), diff_hash("+two")));
+    }
+}
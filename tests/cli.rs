@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-pub fn old() {}
++pub fn new() {}
+";
+
+/// Runs the binary with `args`, feeding `stdin` to it.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_auto-commit"))
+        .args(args)
+        .env_remove("AUTO_COMMIT_PROVIDER")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start auto-commit");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// `command` run in `dir` with a git identity and no user or system git config.
+fn in_repo(mut command: Command, dir: &Path) -> Command {
+    command
+        .current_dir(dir)
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com");
+    command
+}
+
+fn git(dir: &Path, args: &[&str]) -> Output {
+    let mut git = in_repo(Command::new("git"), dir);
+    git.args(args).output().expect("failed to run git")
+}
+
+/// A fresh repository with `src/lib.rs` staged.
+fn staged_repo(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("auto-commit-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    assert!(git(&dir, &["init", "-q"]).status.success());
+    fs::write(dir.join("src/lib.rs"), "pub fn new() {}\n").unwrap();
+    assert!(git(&dir, &["add", "src/lib.rs"]).status.success());
+    dir
+}
+
+/// Runs the binary in the repository at `dir`, without a terminal or network.
+fn run_in(dir: &Path, args: &[&str]) -> Output {
+    let mut command = in_repo(Command::new(env!("CARGO_BIN_EXE_auto-commit")), dir);
+    command
+        .args(args)
+        .env_remove("AUTO_COMMIT_PROVIDER")
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to start auto-commit")
+}
+
+#[test]
+fn quiet_commit_keeps_git_output_off_stdout() {
+    let dir = staged_repo("quiet-commit");
+    let output = run_in(&dir, &["--offline", "--force", "--quiet"]);
+    let log = git(&dir, &["log", "--format=%s"]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        output.stdout.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    assert!(String::from_utf8_lossy(&log.stdout).contains("src/lib.rs"));
+}
+
+#[cfg(unix)]
+#[test]
+fn rejected_commit_fails_and_isnt_pushed() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = staged_repo("rejected-commit");
+    let hook = dir.join(".git/hooks/pre-commit");
+    fs::write(&hook, "#!/bin/sh\necho 'rejected by hook' >&2\nexit 1\n").unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = run_in(&dir, &["--offline", "--force", "--push"]);
+    let log = git(&dir, &["log"]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("The commit wasn't made"), "{}", stderr);
+    assert!(!stderr.contains("upstream"), "{}", stderr);
+    assert!(!log.status.success(), "a commit was made");
+}
+
+#[test]
+fn init_yes_writes_only_what_was_given() {
+    let dir = std::env::temp_dir().join(format!("auto-commit-cli-init-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_auto-commit"))
+        .args(["--yes", "init", "--language", "English"])
+        .env("XDG_CONFIG_HOME", &dir)
+        .env("OPENAI_API_KEY", "sk-from-the-environment")
+        .env_remove("AUTO_COMMIT_PROVIDER")
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to start auto-commit");
+    let written = fs::read_to_string(dir.join("auto-commit/config.toml"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("sk-from-the-environment"), "{}", stderr);
+    assert_eq!(written.unwrap(), "language = \"English\"\n");
+}
+
+#[test]
+fn push_is_rejected_with_subcommands() {
+    for command in ["explain", "check"] {
+        let output = run(&["--push", "--offline", command], DIFF);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{}", stderr);
+        let expected = format!("--push can't be used with {}", command);
+        assert!(stderr.contains(&expected), "{}", stderr);
+    }
+}
+
+#[test]
+fn quiet_dry_run_prints_only_the_message() {
+    let output = run(&["--diff-from-stdin", "--offline", "--quiet"], DIFF);
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Update src/lib.rs"), "{}", stdout);
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn errors_go_to_stderr_even_when_quiet() {
+    let output = run(&["--diff-from-stdin", "--offline", "--quiet"], "");
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No staged files"));
+}
@@ -0,0 +1,183 @@
+//! End-to-end generation against a local stand-in for the OpenAI API, so the
+//! request and response handling is covered without a real key.
+
+use auto_commit::generator::{
+    failover::Failover, CommitGenerator, GenOptions, OpenAiEndpoint, OpenAiGenerator,
+};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-pub fn old() {}
++pub fn new() {}
+";
+
+/// A canned chat completion whose answer is a `commit` tool call.
+fn completion() -> Value {
+    let arguments = json!({
+        "title": "Rename old to new",
+        "description": "The function's name now says what it returns.",
+    });
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 1_700_000_000,
+        "model": "gpt-4",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_mock",
+                    "type": "function",
+                    "function": { "name": "commit", "arguments": arguments.to_string() },
+                }],
+            },
+            "finish_reason": "tool_calls",
+            "logprobs": null,
+        }],
+        "usage": { "prompt_tokens": 120, "completion_tokens": 30, "total_tokens": 150 },
+    })
+}
+
+/// The error OpenAI answers a rate limited request with.
+fn rate_limited() -> Value {
+    json!({
+        "error": {
+            "message": "Rate limit reached for gpt-4 on requests per min.",
+            "type": "requests",
+            "param": null,
+            "code": "rate_limit_exceeded",
+        }
+    })
+}
+
+/// Serves `response` to a single HTTP request on `listener`, returning the
+/// request line and JSON body it received.
+async fn serve_once(listener: TcpListener, response: Value) -> (String, Value) {
+    let (request_line, _, body) = serve(&listener, "200 OK", response).await;
+    (request_line, body)
+}
+
+/// Answers one HTTP request on `listener` with `status` and `response`,
+/// returning the request line, `Authorization` header and JSON body it received.
+async fn serve(listener: &TcpListener, status: &str, response: Value) -> (String, String, Value) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await.unwrap();
+    let mut content_length = 0;
+    let mut authorization = String::new();
+    loop {
+        let mut header = String::new();
+        stream.read_line(&mut header).await.unwrap();
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = value.trim().to_string();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await.unwrap();
+
+    let response = response.to_string();
+    let reply = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\
+         connection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+    stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+    stream.get_mut().shutdown().await.unwrap();
+
+    (
+        request_line.trim().to_string(),
+        authorization,
+        serde_json::from_slice(&body).unwrap(),
+    )
+}
+
+fn opts() -> GenOptions {
+    GenOptions {
+        // Older than structured outputs, so the commit comes back as a tool call
+        model: "gpt-4".to_string(),
+        system_prompt: "Write great commits.".to_string(),
+        max_tokens: 500,
+        no_tools: false,
+        seed: None,
+    }
+}
+
+#[tokio::test]
+async fn generates_a_commit_from_a_mock_openai_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let server = tokio::spawn(serve_once(listener, completion()));
+
+    let generator =
+        OpenAiGenerator::with_endpoint("sk-test".to_string(), OpenAiEndpoint::Custom { base_url });
+    let commit = generator.generate(DIFF, &opts()).await.unwrap();
+
+    assert_eq!(commit.title, "Rename old to new");
+    assert_eq!(
+        commit.description,
+        "The function's name now says what it returns."
+    );
+    assert_eq!(generator.usage().prompt_tokens, 120);
+
+    let (request_line, body) = server.await.unwrap();
+    assert_eq!(request_line, "POST /v1/chat/completions HTTP/1.1");
+    assert_eq!(body["model"], "gpt-4");
+    assert_eq!(body["tool_choice"]["function"]["name"], "commit");
+    let messages = body["messages"].to_string();
+    assert!(messages.contains("pub fn new()"), "{}", messages);
+}
+
+#[tokio::test]
+async fn a_rate_limited_key_moves_on_to_the_next() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let first = serve(&listener, "429 Too Many Requests", rate_limited()).await;
+        let second = serve(&listener, "200 OK", completion()).await;
+        (first.1, second.1)
+    });
+
+    let generator = Failover::new(
+        ["sk-first", "sk-second"]
+            .into_iter()
+            .map(|key| {
+                let endpoint = OpenAiEndpoint::Custom {
+                    base_url: base_url.clone(),
+                };
+                OpenAiGenerator::with_endpoint(key.to_string(), endpoint).without_backoff()
+            })
+            .collect(),
+    );
+    let commit = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        generator.generate(DIFF, &opts()),
+    )
+    .await
+    .expect("the 429 was retried instead of rotated")
+    .unwrap();
+
+    assert_eq!(commit.title, "Rename old to new");
+    let (first, second) = server.await.unwrap();
+    assert_eq!(first, "Bearer sk-first");
+    assert_eq!(second, "Bearer sk-second");
+}